@@ -2,6 +2,8 @@ import matplotlib.pyplot as plt
 import numpy as np
 import pandas as pd
 import os
+import shutil
+import subprocess
 
 FILENAME = os.path.join(os.path.dirname(__file__), "..", "analysis-data", "10-16-23.pickle")
 
@@ -152,8 +154,27 @@ def plot_histogram(filename):
         i += 1
 
 
+def render_best_heuristic(filename):
+    # Writes the best heuristic over all seeds as a Graphviz graph and a LaTeX
+    # formula, rendering the graph if Graphviz is installed
+    from libcmput651py import heuristic
+
+    alife_data = pd.read_pickle(filename)
+    df = pd.concat((pd.DataFrame.from_dict(seed['result']['heuristics']) for seed in alife_data), ignore_index=True)
+    best = heuristic.parse(df.loc[df['score'].idxmin(), 'heuristic'])
+
+    with open('plots/best_heuristic.dot', 'w') as file:
+        file.write(best.to_dot())
+    with open('plots/best_heuristic.tex', 'w') as file:
+        file.write('$$' + best.to_latex() + '$$\n')
+
+    if shutil.which('dot') is not None:
+        subprocess.run(['dot', '-Tpng', 'plots/best_heuristic.dot', '-o', 'plots/best_heuristic.png'], check=True)
+
+
 plot_best_scores_time(FILENAME)
 # plot_average_scores_time(FILENAME)
 # plot_histogram(FILENAME)
 # plot_score_path_length(FILENAME)
 # plot_all_scores(FILENAME)
+render_best_heuristic(FILENAME)
@@ -1,3 +1,4 @@
+pub mod admissibility;
 pub mod problem;
 pub mod state;
 pub mod cycle;
\ No newline at end of file
@@ -0,0 +1,198 @@
+use std::collections::BinaryHeap;
+
+#[cfg(feature = "python")]
+use pyo3::prelude::*;
+
+use super::state::State;
+use crate::constants::EDGE_COST;
+use crate::heuristic::executors::interpreter::Interpreter;
+use crate::heuristic::executors::HeuristicExecuter;
+use crate::heuristic::Heuristic;
+use crate::map::util::{Map, Tile};
+
+// Slack allowed in both checks, so that rounding in e.g. `euclid` is not reported
+const TOLERANCE: f32 = 1e-4;
+
+// An edge `from` - `to` across which the heuristic for `goal` changes by more
+// than the cost of the edge
+#[derive(Debug, Clone, PartialEq)]
+#[cfg_attr(feature = "python", pyclass(get_all))]
+pub struct ConsistencyViolation {
+    pub goal: usize,
+    pub from: usize,
+    pub to: usize,
+    pub difference: f32,
+}
+
+// How a heuristic compares to the true distances on a map, over every pair of a
+// passable state and one of the checked goals
+#[derive(Debug, Clone)]
+#[cfg_attr(feature = "python", pyclass(get_all))]
+pub struct AdmissibilityReport {
+    pub num_states: usize,
+    pub num_overestimations: usize,
+    pub overestimation_fraction: f64,
+    // The largest h / h*, over the states other than the goal itself. At most 1
+    // for an admissible heuristic.
+    pub max_overestimation_ratio: f64,
+    // Each edge is reported once per goal, with `from < to`
+    pub consistency_violations: Vec<ConsistencyViolation>,
+}
+
+#[cfg_attr(feature = "python", pymethods)]
+impl AdmissibilityReport {
+    pub fn is_admissible(&self) -> bool {
+        self.num_overestimations == 0
+    }
+
+    pub fn is_consistent(&self) -> bool {
+        self.consistency_violations.is_empty()
+    }
+}
+
+// Compares `heuristic` against the true distances to each of `goals`, which must
+// be passable positions of `map`
+pub fn check_admissibility(
+    map: &Map,
+    heuristic: &Heuristic,
+    goals: &[usize],
+) -> AdmissibilityReport {
+    let executor = Interpreter::create_with_features(heuristic, map.features.clone());
+
+    let mut num_states = 0;
+    let mut num_overestimations = 0;
+    let mut max_overestimation_ratio: f64 = 0.0;
+    let mut consistency_violations = Vec::new();
+
+    for &goal in goals {
+        assert!(
+            map.map[goal] == Tile::Passable,
+            "Goal {} is not passable",
+            goal
+        );
+
+        let (gx, gy) = map.ind2sub(goal);
+        let (gx, gy) = (gx as f32, gy as f32);
+        let h = (0..map.map.len())
+            .map(|pos| {
+                let (x, y) = map.ind2sub(pos);
+                executor.execute(x as f32, y as f32, gx, gy)
+            })
+            .collect::<Vec<_>>();
+        let h_star = true_distances(map, goal);
+
+        let reachable = h_star
+            .iter()
+            .enumerate()
+            .filter_map(|(pos, distance)| distance.map(|distance| (pos, distance)));
+
+        for (pos, distance) in reachable {
+            num_states += 1;
+            if h[pos] > distance + TOLERANCE {
+                num_overestimations += 1;
+            }
+            if distance > 0.0 {
+                max_overestimation_ratio =
+                    max_overestimation_ratio.max(h[pos] as f64 / distance as f64);
+            }
+
+            for &neighbour in map.neighbours[pos].iter().filter(|&&n| pos < n) {
+                let difference = (h[pos] - h[neighbour]).abs();
+                if difference.is_nan() || difference > EDGE_COST + TOLERANCE {
+                    consistency_violations.push(ConsistencyViolation {
+                        goal,
+                        from: pos,
+                        to: neighbour,
+                        difference,
+                    });
+                }
+            }
+        }
+    }
+
+    AdmissibilityReport {
+        num_states,
+        num_overestimations,
+        overestimation_fraction: num_overestimations as f64 / num_states.max(1) as f64,
+        max_overestimation_ratio,
+        consistency_violations,
+    }
+}
+
+// Backward Dijkstra from `goal`, giving the length of the shortest path from each
+// position to it, or None for positions that cannot reach it
+pub fn true_distances(map: &Map, goal: usize) -> Vec<Option<f32>> {
+    let mut open = BinaryHeap::new();
+    open.push(State::new(goal, 0.0, 0.0));
+
+    let mut g = vec![None; map.map.len()];
+    g[goal] = Some(0.0);
+
+    while let Some(cur) = open.pop() {
+        if g[cur.position] != Some(cur.g) {
+            continue;
+        }
+
+        // Edges are undirected, so the neighbours of a position are also the
+        // positions leading to it
+        for &neighbour in map.neighbours[cur.position].iter() {
+            let new_g = cur.g + EDGE_COST;
+            if g[neighbour].is_none_or(|old_g| new_g < old_g) {
+                g[neighbour] = Some(new_g);
+                open.push(State::new(neighbour, new_g, 0.0));
+            }
+        }
+    }
+
+    g
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::heuristic::parser::parse_heuristic;
+
+    use test_case::test_case;
+
+    #[test]
+    fn test_true_distances() {
+        let map = Map::walled();
+        let h_star = true_distances(&map, map.sub2ind(0, 0));
+
+        assert_eq!(h_star[map.sub2ind(0, 0)], Some(0.0));
+        assert_eq!(h_star[map.sub2ind(3, 2)], Some(5.0));
+        assert_eq!(h_star[map.sub2ind(0, 3)], Some(9.0));
+        assert_eq!(h_star[map.sub2ind(1, 2)], None);
+    }
+
+    #[test_case("manhattan", true, true)]
+    #[test_case("0", true, true)]
+    #[test_case("(* 2 manhattan)", false, false)]
+    #[test_case("(* 0.5 (sqr deltaX))", false, false)]
+    fn test_check_admissibility(input: &str, admissible: bool, consistent: bool) {
+        let map = Map::walled();
+        let goals = [map.sub2ind(0, 0), map.sub2ind(0, 4), map.sub2ind(3, 2)];
+        let report = check_admissibility(&map, &parse_heuristic(input), &goals);
+
+        assert_eq!(report.num_states, 3 * 17);
+        assert_eq!(report.is_admissible(), admissible);
+        assert_eq!(report.is_consistent(), consistent);
+        assert_eq!(report.max_overestimation_ratio <= 1.0, admissible);
+    }
+
+    #[test]
+    fn test_overestimation() {
+        let map = Map::walled();
+        // Twice the true distance on the goal's side of the wall, but behind it the
+        // detour makes up for the factor of 2 at (0, 3), (0, 4) and (1, 3)
+        let report = check_admissibility(&map, &parse_heuristic("(* 2 manhattan)"), &[0]);
+
+        assert_eq!(report.num_overestimations, 13);
+        assert_eq!(report.overestimation_fraction, 13.0 / 17.0);
+        assert_eq!(report.max_overestimation_ratio, 2.0);
+        assert!(report
+            .consistency_violations
+            .iter()
+            .all(|violation| violation.difference == 2.0));
+    }
+}
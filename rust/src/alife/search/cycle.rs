@@ -1,13 +1,24 @@
 use super::problem::{Problem, ProblemResult};
+#[cfg(feature = "jit")]
+use crate::heuristic::executors::compiled::CompiledHeuristic;
+#[cfg(feature = "cranelift")]
+use crate::heuristic::executors::cranelift::Cranelift;
 use crate::{
-    heuristic::{executors::jit::Jit, Heuristic},
-    map::util::Map,
+    constants::{TABLE_SAMPLE_SIZE, TABLE_TRAVERSAL_RATIO},
+    heuristic::{
+        executors::{
+            interpreter::Interpreter, table::HeuristicTable, vm::Vm, Backend, HeuristicExecuter,
+        },
+        Heuristic,
+    },
+    map::util::{Map, Tile},
 };
+#[cfg(feature = "python")]
 use pyo3::pyclass;
 use rayon::prelude::*;
 
 #[derive(Clone)]
-#[pyclass]
+#[cfg_attr(feature = "python", pyclass)]
 pub struct ProblemCycle {
     pub problems: Vec<Problem>,
 }
@@ -47,12 +58,25 @@ impl ProblemCycle {
     }
 }
 
+// How a `CycleSolver` evaluates its heuristic
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum EvaluationMode {
+    // For each state as it is reached
+    Direct,
+    // Through a `HeuristicTable` for the goal of each problem
+    Table,
+    // Tables if the first few problems, solved directly, suggest they pay off
+    Automatic,
+}
+
 #[derive(Clone)]
 pub struct CycleSolver {
     map: Map,
     heuristic: Heuristic,
     results: Vec<Option<ProblemResult>>,
     problems: ProblemCycle,
+    mode: EvaluationMode,
+    backend: Backend,
 }
 
 impl CycleSolver {
@@ -67,27 +91,65 @@ impl CycleSolver {
             heuristic,
             results: vec![None; problems.len()],
             problems,
+            mode: EvaluationMode::Automatic,
+            backend: Backend::default(),
         }
     }
 
+    pub fn with_mode(mut self, mode: EvaluationMode) -> CycleSolver {
+        self.mode = mode;
+        self
+    }
+
+    pub fn with_backend(mut self, backend: Backend) -> CycleSolver {
+        self.backend = backend;
+        self
+    }
+
+    // Compiles the heuristic with the chosen backend, by default the `Jit`
     pub fn solve_cycle(&mut self) -> Vec<ProblemResult> {
-        let context = inkwell::context::Context::create();
-        let executor = Jit::create(&self.heuristic, &context);
+        let (heuristic, features) = (&self.heuristic, self.map.features.clone());
+        match self.backend {
+            Backend::Interpreter => {
+                self.solve_cycle_with(&Interpreter::create_with_features(heuristic, features))
+            }
+            Backend::Vm => self.solve_cycle_with(&Vm::create_with_features(heuristic, features)),
+            #[cfg(feature = "jit")]
+            Backend::Llvm => self.solve_cycle_with(&CompiledHeuristic::create_with_features(
+                heuristic, features,
+            )),
+            #[cfg(feature = "cranelift")]
+            Backend::Cranelift => {
+                self.solve_cycle_with(&Cranelift::create_with_features(heuristic, features))
+            }
+        }
+    }
 
-        // Parallel problem solving :)
-        let raw = executor.get_raw().clone();
-        self.results
-            .par_iter_mut()
-            // .iter_mut()
-            .enumerate()
-            .for_each(|(idx, result)| {
-                if result.is_none() {
-                    let problem = self.problems.get(idx);
-                    *result = Some(
-                        problem.solve(&self.map, |sx, sy, gx, gy| unsafe { raw(sx, sy, gx, gy) }),
-                    );
-                }
-            });
+    // Solves the cycle with an already compiled heuristic, e.g. one compiled
+    // together with the rest of its population or taken from a `CompileCache`
+    pub fn solve_cycle_with(
+        &mut self,
+        executor: &(impl HeuristicExecuter + Sync),
+    ) -> Vec<ProblemResult> {
+        let execute_batch = |x1: &[f32], y1: &[f32], x2: f32, y2: f32, out: &mut [f32]| {
+            executor.execute_batch(x1, y1, x2, y2, out)
+        };
+
+        let mut unsolved = (0..self.results.len())
+            .filter(|&idx| self.results[idx].is_none())
+            .collect::<Vec<_>>();
+        let use_tables = match self.mode {
+            EvaluationMode::Direct => false,
+            EvaluationMode::Table => true,
+            EvaluationMode::Automatic => {
+                let sample = unsolved
+                    .drain(..TABLE_SAMPLE_SIZE.min(unsolved.len()))
+                    .collect::<Vec<_>>();
+                self.solve_problems(&sample, false, &execute_batch);
+                self.expect_tables_to_pay_off(&sample)
+            }
+        };
+        self.solve_problems(&unsolved, use_tables, &execute_batch);
 
         self.results
             .clone()
@@ -96,6 +158,55 @@ impl CycleSolver {
             .collect()
     }
 
+    // Parallel problem solving :)
+    fn solve_problems(
+        &mut self,
+        indices: &[usize],
+        use_tables: bool,
+        execute_batch: &(impl Fn(&[f32], &[f32], f32, f32, &mut [f32]) + Sync),
+    ) {
+        let results = indices
+            .par_iter()
+            .map(|&idx| {
+                let problem = self.problems.get(idx);
+                match use_tables {
+                    true => {
+                        let table =
+                            HeuristicTable::tabulate(&self.map, problem.goal, execute_batch);
+                        problem.solve_batched(&self.map, |x1, y1, _, _, out| {
+                            table.execute_batch(x1, y1, out)
+                        })
+                    }
+                    false => problem.solve_batched(&self.map, execute_batch),
+                }
+            })
+            .collect::<Vec<_>>();
+
+        for (&idx, result) in indices.iter().zip(results) {
+            self.results[idx] = Some(result);
+        }
+    }
+
+    // Tabulating costs about one evaluation per passable cell, while a direct search
+    // evaluates once per traversed edge
+    fn expect_tables_to_pay_off(&self, sample: &[usize]) -> bool {
+        if sample.is_empty() {
+            return false;
+        }
+
+        let traversals = sample
+            .iter()
+            .map(|&idx| self.results[idx].as_ref().unwrap().num_traversals)
+            .sum::<usize>();
+        let passable = self
+            .map
+            .map
+            .iter()
+            .filter(|&tile| *tile == Tile::Passable)
+            .count();
+        traversals as f64 / sample.len() as f64 > TABLE_TRAVERSAL_RATIO * passable as f64
+    }
+
     // pub fn solve_current(&mut self) -> ProblemResult {
     //     if self.results[self.problem_index].is_none() {
     //         let problem = self.problems.get(self.problem_index);
@@ -1,6 +1,7 @@
 use std::collections::BinaryHeap;
 
 use super::state::State;
+#[cfg(feature = "python")]
 use pyo3::prelude::*;
 
 use crate::{
@@ -16,15 +17,11 @@ pub struct Problem {
 }
 
 #[derive(Clone)]
-#[pyclass]
+#[cfg_attr(feature = "python", pyclass(get_all))]
 pub struct ProblemResult {
-    #[pyo3(get)]
     pub expansions: Vec<usize>,
-    #[pyo3(get)]
     pub num_traversals: usize,
-    #[pyo3(get)]
     pub solution_path: Vec<usize>,
-    #[pyo3(get)]
     pub solved: bool,
 }
 
@@ -34,10 +31,26 @@ impl Problem {
     }
 
     pub fn solve(&self, map: &Map, executor: impl Fn(f32, f32, f32, f32) -> f32) -> ProblemResult {
+        self.solve_batched(map, |x1, y1, x2, y2, out| {
+            for ((value, &x1), &y1) in out.iter_mut().zip(x1).zip(y1) {
+                *value = executor(x1, y1, x2, y2);
+            }
+        })
+    }
+
+    // Like `solve`, but with an executor in the form of `HeuristicExecuter::execute_batch`,
+    // which is given all open neighbours of an expanded state at once
+    pub fn solve_batched(
+        &self,
+        map: &Map,
+        executor: impl Fn(&[f32], &[f32], f32, f32, &mut [f32]),
+    ) -> ProblemResult {
         let (sx, sy) = map.ind2sub(self.start);
         let (gx, gy) = map.ind2sub(self.goal);
         let (sx, sy, gx, gy) = (sx as f32, sy as f32, gx as f32, gy as f32);
-        let start = State::new(self.start, 0.0, executor(sx, sy, gx, gy));
+        let mut start_h = [0.0];
+        executor(&[sx], &[sy], gx, gy, &mut start_h);
+        let start = State::new(self.start, 0.0, start_h[0]);
 
         // Create priority queue
         let mut open = BinaryHeap::new();
@@ -57,6 +70,11 @@ impl Problem {
         let mut num_traversals = 0;
         let mut solved = false;
 
+        // The neighbours of the current state and their coordinates and heuristic
+        // values, reused between expansions
+        let mut neighbours = Vec::new();
+        let (mut xs, mut ys, mut hs) = (Vec::new(), Vec::new(), Vec::new());
+
         while !open.is_empty() {
             // Extract the state with the lowest f value
             let cur = open.pop().unwrap();
@@ -74,21 +92,27 @@ impl Problem {
             closed[cur.position] = true;
             expansions.push(cur.position);
 
-            // Iterate over all neighbours
-            for &neighbour in map.neighbours[cur.position].iter() {
-                if closed[neighbour] {
-                    continue;
-                }
+            // Evaluate the heuristic for all neighbours that are not closed together
+            neighbours.clear();
+            neighbours.extend(
+                map.neighbours[cur.position]
+                    .iter()
+                    .filter(|&&neighbour| !closed[neighbour]),
+            );
+            xs.clear();
+            ys.clear();
+            for &neighbour in neighbours.iter() {
+                let (nx, ny) = map.ind2sub(neighbour);
+                xs.push(nx as f32);
+                ys.push(ny as f32);
+            }
+            hs.resize(neighbours.len(), 0.0);
+            executor(&xs, &ys, gx, gy, &mut hs);
 
+            for (&neighbour, &new_h) in neighbours.iter().zip(hs.iter()) {
                 let new_g = cur_g + EDGE_COST;
                 num_traversals += 1;
 
-                let (gx, gy) = map.ind2sub(self.goal);
-                let (nx, ny) = map.ind2sub(neighbour);
-
-                let (gx, gy, nx, ny) = (gx as f32, gy as f32, nx as f32, ny as f32);
-                let new_h = executor(nx, ny, gx, gy);
-
                 let new_state = State::new(neighbour, new_g, new_h);
                 if g[neighbour].is_none() || new_g < g[neighbour].unwrap() {
                     // Update parent
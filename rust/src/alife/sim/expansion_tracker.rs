@@ -63,7 +63,7 @@ impl ExpansionTracker {
             .join(",");
 
         HeuristicResult {
-            heuristic: self.heuristic.root.to_string(),
+            heuristic: self.heuristic.to_string(),
             expansions,
             traversals,
             solution_path_lens,
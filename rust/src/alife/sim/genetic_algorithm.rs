@@ -1,3 +1,8 @@
+#[cfg(feature = "jit")]
+use inkwell::OptimizationLevel;
+#[cfg(feature = "python")]
+use pyo3::{exceptions::PyValueError, PyResult};
+#[cfg(feature = "python")]
 use pyo3::{pyclass, pymethods};
 use rayon::prelude::*;
 use std::cmp::Ordering;
@@ -7,10 +12,21 @@ use std::hash::Hash;
 use std::time::{Duration, Instant, SystemTime};
 
 use crate::alife::search::cycle::{CycleSolver, ProblemCycle};
-use crate::constants::MAX_TREE_SIZE;
+#[cfg(feature = "jit")]
+use crate::constants::COMPILE_CACHE_CAPACITY;
+use crate::constants::{MAX_TREE_SIZE, MAX_UNSAFE_REJECTIONS};
+use crate::heuristic::adf::{random_adfs, Scope};
+use crate::heuristic::archive::{ArchiveError, HeuristicArchive, HeuristicRecord};
+#[cfg(feature = "jit")]
+use crate::heuristic::executors::compiled::{CompileCache, CompiledHeuristic};
+use crate::heuristic::executors::Backend;
+use crate::heuristic::fingerprint::{EquivalenceClasses, NoPassableTiles, FINGERPRINT_SAMPLES};
+use crate::heuristic::interval::{analyze, AnalysisError};
 use crate::heuristic::mutate_probs::TermProbabilities;
-use crate::heuristic::mutator::mutate_heuristic;
-use crate::heuristic::util::random_heuristic;
+use crate::heuristic::mutator::mutate;
+use crate::heuristic::optimize::ConstantOptimizer;
+use crate::heuristic::simplify::simplify_heuristic;
+use crate::heuristic::util::random_heuristic_in;
 use crate::heuristic::util::{normalize_vector, random_weighted_sample};
 use crate::heuristic::Heuristic;
 use crate::map::util::Map;
@@ -20,14 +36,13 @@ pub const MAX_POPULATION_SIZE: usize = 40;
 pub const MAX_BEST_INDIVIDUALS: usize = 10;
 
 #[derive(Debug, Clone)]
-#[pyclass]
+#[cfg_attr(feature = "python", pyclass(get_all))]
 pub struct GeneticAlgorithmResult {
-    #[pyo3(get)]
     pub best_heuristics: Vec<String>,
-    #[pyo3(get)]
     pub best_fitnesses: Vec<f64>,
-    #[pyo3(get)]
     pub history: Vec<Vec<(String, f64, u64)>>,
+    // The best individuals with their lineage, as a JSON `HeuristicArchive`
+    pub archive: String,
 }
 
 #[derive(Debug, Clone)]
@@ -39,7 +54,7 @@ pub struct Individual {
 
 impl PartialEq for Individual {
     fn eq(&self, other: &Self) -> bool {
-        self.heuristic.root() == other.heuristic.root()
+        self.heuristic.to_string() == other.heuristic.to_string()
     }
 }
 
@@ -47,7 +62,7 @@ impl Eq for Individual {}
 
 impl Hash for Individual {
     fn hash<H: std::hash::Hasher>(&self, state: &mut H) {
-        self.heuristic.root().to_string().hash(state);
+        self.heuristic.to_string().hash(state);
     }
 }
 
@@ -55,32 +70,93 @@ impl Individual {
     fn fitness(&self, baseline_expansions: usize, baseline_path_len: usize) -> f64 {
         let path_len_ratio = self.path_len as f64 / baseline_path_len as f64;
         let expansion_ratio = self.expansions as f64 / baseline_expansions as f64;
-        let size_weight = 200.0 + self.heuristic.size() as f64;
+        // Subtrees used more than once, e.g. through a let-binding, are only
+        // computed once, so they are only penalized once
+        let size_weight = 200.0 + self.heuristic.shared_size() as f64;
 
         path_len_ratio.powi(2) * expansion_ratio * size_weight
     }
 }
 
-#[pyclass]
-pub struct GeneticAlgorithm {
-    // The map used to perform the search
-    pub map: Map,
-    // The problem cycle on which all heuristics will be evaluated
-    pub cycle: ProblemCycle,
-    // The results of solving the problem cycle with manhattan distance
-    pub baseline: CycleSolver,
-    pub baseline_expansions: usize,
-    pub baseline_path_len: usize,
-    // The maximum number of expansions allowed per heuristic
-    pub expansion_bound: usize,
-    // The maximum amount of time allowed for the simulation
-    pub time_limit: Duration,
-    // max population size
-    pub max_population_size: usize,
-    pub h_population: Vec<Heuristic>,
-    pub i_population: Vec<Individual>,
-    pub best_individuals: Vec<Individual>,
-    pub term_probs: Option<TermProbabilities>,
+// Declares a struct that is a `pyclass` with the `python` feature. `pyclass` only
+// sees `pyo3` attributes written out on a field, not ones behind `cfg_attr`, so
+// the properties of a field are given in brackets before it instead, e.g.
+// `[get, set] pub size: usize`, and only turned into `#[pyo3(get, set)]` for Python.
+macro_rules! python_class {
+    (
+        $(#[$attr:meta])*
+        pub struct $name:ident {
+            $($(#[$field_attr:meta])* $([$($property:ident),+])? pub $field:ident: $ty:ty,)*
+        }
+    ) => {
+        #[cfg(feature = "python")]
+        $(#[$attr])*
+        #[pyclass]
+        pub struct $name {
+            $($(#[$field_attr])* $(#[pyo3($($property),+)])? pub $field: $ty,)*
+        }
+
+        #[cfg(not(feature = "python"))]
+        $(#[$attr])*
+        pub struct $name {
+            $($(#[$field_attr])* pub $field: $ty,)*
+        }
+    };
+}
+
+python_class! {
+    pub struct GeneticAlgorithm {
+        // The map used to perform the search
+        pub map: Map,
+        // The problem cycle on which all heuristics will be evaluated
+        pub cycle: ProblemCycle,
+        // The results of solving the problem cycle with manhattan distance
+        pub baseline: CycleSolver,
+        pub baseline_expansions: usize,
+        pub baseline_path_len: usize,
+        // The maximum number of expansions allowed per heuristic
+        pub expansion_bound: usize,
+        // The maximum amount of time allowed for the simulation
+        pub time_limit: Duration,
+        // max population size
+        pub max_population_size: usize,
+        pub h_population: Vec<Heuristic>,
+        pub i_population: Vec<Individual>,
+        pub best_individuals: Vec<Individual>,
+        pub term_probs: Option<TermProbabilities>,
+        // Whether to simplify heuristics before evaluating them, so that the size
+        // penalty in the fitness only counts structure that affects the result
+        [get, set]
+        pub simplify_before_evaluation: bool,
+        // If set, semantically equivalent heuristics reuse the (expansions, path length)
+        // of the first heuristic evaluated in their class
+        pub equivalence_classes: Option<EquivalenceClasses<(usize, usize)>>,
+        // If non-zero, the constants of this many of the fittest individuals are tuned
+        // every generation before selection (a memetic step)
+        [get, set]
+        pub memetic_top_k: usize,
+        pub constant_optimizer: ConstantOptimizer,
+        // The number of ADFs each random heuristic is generated with, see `heuristic::adf`
+        [get, set]
+        pub num_adfs: usize,
+        // The current generation, recorded in the lineage of new heuristics
+        [get]
+        pub generation: usize,
+        // Whether to reject new heuristics that `interval::analyze` finds a pair of
+        // cells of the map for, where they are not finite and non-negative, rather than
+        // spend a cycle on them
+        [get, set]
+        pub reject_unsafe_heuristics: bool,
+        // What heuristics are evaluated with. With the LLVM backend each generation is
+        // compiled into a single module, through `compile_cache`.
+        pub backend: Backend,
+        // The level each generation is compiled at, see `Jit::create_population`
+        #[cfg(feature = "jit")]
+        pub optimization_level: OptimizationLevel,
+        // Heuristics surviving into the next generation are not compiled again
+        #[cfg(feature = "jit")]
+        pub compile_cache: CompileCache,
+    }
 }
 
 impl GeneticAlgorithm {
@@ -99,6 +175,13 @@ impl GeneticAlgorithm {
             fastrand::seed(seed.unwrap());
         }
 
+        #[cfg(feature = "jit")]
+        let compile_cache = CompileCache::new(
+            COMPILE_CACHE_CAPACITY,
+            Some(map.features.clone()),
+            OptimizationLevel::None,
+        );
+
         GeneticAlgorithm {
             map,
             cycle,
@@ -111,33 +194,51 @@ impl GeneticAlgorithm {
             h_population: Vec::with_capacity(MAX_POPULATION_SIZE),
             i_population: Vec::with_capacity(MAX_POPULATION_SIZE),
             best_individuals: Vec::with_capacity(MAX_BEST_INDIVIDUALS + 1),
-            term_probs,
+            // The problems are solved with the feature tables of the map
+            term_probs: Some(
+                term_probs.unwrap_or_else(|| TermProbabilities::new(true).with_feature_terminals()),
+            ),
+            simplify_before_evaluation: false,
+            equivalence_classes: None,
+            memetic_top_k: 0,
+            constant_optimizer: ConstantOptimizer::default(),
+            num_adfs: 0,
+            generation: 0,
+            reject_unsafe_heuristics: false,
+            backend: Backend::default(),
+            #[cfg(feature = "jit")]
+            optimization_level: OptimizationLevel::None,
+            #[cfg(feature = "jit")]
+            compile_cache,
         }
     }
 
     pub fn run(&mut self) -> GeneticAlgorithmResult {
         let mut history = Vec::new();
 
-        for _ in 0..self.max_population_size {
-            let h = random_heuristic(fastrand::i32(1..=MAX_TREE_SIZE), &self.term_probs);
-            self.h_population.push(Heuristic::new(h));
+        // Top up a population loaded with `load_archive` with random heuristics
+        while self.h_population.len() < self.max_population_size {
+            let mut h = self.random_heuristic_with_adfs(fastrand::i32(1..=MAX_TREE_SIZE));
+            for _ in 1..MAX_UNSAFE_REJECTIONS {
+                if self.is_acceptable(&h) {
+                    break;
+                }
+                h = self.random_heuristic_with_adfs(fastrand::i32(1..=MAX_TREE_SIZE));
+            }
+            self.h_population.push(h);
         }
 
         let timer = Instant::now();
         let mut iter_count = 0;
         let mut next_log = timer.elapsed() + Duration::from_secs(10);
 
-        let mut generation_number = 0;
         while timer.elapsed() < self.time_limit {
             // Update the generation number
-            generation_number += 1;
+            self.generation += 1;
 
             // Solve the problem cycle with each heuristic in the population
-            self.i_population = self
-                .h_population
-                .iter()
-                .map(|heuristic| self.compute_individual(heuristic.clone()))
-                .collect();
+            self.i_population = self.evaluate_population(self.h_population.clone());
+            self.memetic_step();
 
             // Get the current unix time
             let now = SystemTime::now()
@@ -151,7 +252,7 @@ impl GeneticAlgorithm {
                     .iter()
                     .map(|individual| {
                         (
-                            individual.heuristic.root().to_string(),
+                            individual.heuristic.to_string(),
                             individual.fitness(self.baseline_expansions, self.baseline_path_len),
                             now,
                         )
@@ -173,19 +274,17 @@ impl GeneticAlgorithm {
             let next_population = self.get_next_population();
             self.h_population = next_population
                 .par_iter()
-                .map(|heuristic| {
-                    Heuristic::new(mutate_heuristic(heuristic.root(), &self.term_probs))
-                })
+                .map(|heuristic| self.acceptable_child(heuristic))
                 .collect();
         }
 
-        println!("{}", generation_number);
+        println!("{}", self.generation);
 
         GeneticAlgorithmResult {
             best_heuristics: self
                 .best_individuals
                 .iter()
-                .map(|i| i.heuristic.root().to_string())
+                .map(|i| simplify_heuristic(&i.heuristic).to_string())
                 .collect(),
             best_fitnesses: self
                 .best_individuals
@@ -193,20 +292,238 @@ impl GeneticAlgorithm {
                 .map(|i| i.fitness(self.baseline_expansions, self.baseline_path_len))
                 .collect(),
             history,
+            archive: self.archive().to_json(),
+        }
+    }
+
+    // Like `compute_individual`, but skips solving the cycle if an equivalent
+    // heuristic has already been evaluated
+    fn evaluate_individual(&mut self, heuristic: Heuristic) -> Individual {
+        self.evaluate_population(vec![heuristic]).pop().unwrap()
+    }
+
+    // The heuristic as it is evaluated, see `simplify_before_evaluation`
+    fn evaluated_form(&self, heuristic: Heuristic) -> Heuristic {
+        match self.simplify_before_evaluation {
+            true => simplify_heuristic(&heuristic),
+            false => heuristic,
+        }
+    }
+
+    // Like `evaluate_individual` for every heuristic of a generation, with the ones
+    // that need their cycle solved compiled together into a single module
+    fn evaluate_population(&mut self, heuristics: Vec<Heuristic>) -> Vec<Individual> {
+        let heuristics = heuristics
+            .into_iter()
+            .map(|heuristic| self.evaluated_form(heuristic))
+            .collect::<Vec<_>>();
+
+        // With semantic deduplication, only one heuristic per new class is solved
+        let fingerprints = self.equivalence_classes.as_ref().map(|classes| {
+            heuristics
+                .iter()
+                .map(|heuristic| classes.fingerprint(heuristic))
+                .collect::<Vec<_>>()
+        });
+        let unsolved = match (&self.equivalence_classes, &fingerprints) {
+            (Some(classes), Some(fingerprints)) => {
+                let mut new_classes = HashSet::new();
+                (0..heuristics.len())
+                    .filter(|&i| {
+                        classes.get(&fingerprints[i]).is_none()
+                            && new_classes.insert(&fingerprints[i])
+                    })
+                    .collect::<Vec<_>>()
+            }
+            _ => (0..heuristics.len()).collect(),
+        };
+
+        let results = self.solve_cycles(
+            &unsolved
+                .iter()
+                .map(|&i| heuristics[i].clone())
+                .collect::<Vec<_>>(),
+        );
+        let results = unsolved
+            .iter()
+            .copied()
+            .zip(results)
+            .collect::<HashMap<_, _>>();
+
+        let classes = self.equivalence_classes.as_mut();
+        let results = match (classes, fingerprints) {
+            (Some(classes), Some(fingerprints)) => {
+                for (&i, &result) in results.iter() {
+                    classes.insert(fingerprints[i].clone(), result);
+                }
+                fingerprints
+                    .iter()
+                    .map(|fingerprint| *classes.get(fingerprint).unwrap())
+                    .collect::<Vec<_>>()
+            }
+            _ => (0..heuristics.len()).map(|i| results[&i]).collect(),
+        };
+
+        heuristics
+            .into_iter()
+            .zip(results)
+            .map(|(heuristic, (expansions, path_len))| Individual {
+                heuristic,
+                expansions,
+                path_len,
+            })
+            .collect()
+    }
+
+    // The (expansions, path length) of each heuristic on the cycle
+    fn solve_cycles(&mut self, heuristics: &[Heuristic]) -> Vec<(usize, usize)> {
+        #[cfg(feature = "jit")]
+        if self.backend == Backend::Llvm {
+            let executors = self.compile_unsolved(heuristics);
+            return heuristics
+                .iter()
+                .zip(&executors)
+                .map(|(heuristic, executor)| {
+                    let mut cycle = self.cycle_solver(heuristic.clone());
+                    cycle.solve_cycle_with(executor);
+                    cycle_totals(&cycle)
+                })
+                .collect();
         }
+
+        heuristics
+            .iter()
+            .map(|heuristic| {
+                let mut cycle = self.cycle_solver(heuristic.clone());
+                cycle.solve_cycle();
+                cycle_totals(&cycle)
+            })
+            .collect()
+    }
+
+    fn cycle_solver(&self, heuristic: Heuristic) -> CycleSolver {
+        CycleSolver::from_cycle(self.cycle.clone(), self.map.clone(), heuristic)
+            .with_backend(self.backend)
+    }
+
+    #[cfg(feature = "jit")]
+    fn compile_unsolved(&mut self, heuristics: &[Heuristic]) -> Vec<CompiledHeuristic> {
+        // The cache is keyed by heuristic alone, so it is replaced when the level changes
+        if self.compile_cache.optimization() != self.optimization_level {
+            self.compile_cache = CompileCache::new(
+                COMPILE_CACHE_CAPACITY,
+                Some(self.map.features.clone()),
+                self.optimization_level,
+            );
+        }
+        self.compile_cache.get_population(heuristics)
     }
 
     fn compute_individual(&self, heuristic: Heuristic) -> Individual {
-        let mut cycle =
-            CycleSolver::from_cycle(self.cycle.clone(), self.map.clone(), heuristic.clone());
+        let heuristic = self.evaluated_form(heuristic);
+        let mut cycle = self.cycle_solver(heuristic.clone());
         cycle.solve_cycle();
+        let (expansions, path_len) = cycle_totals(&cycle);
         Individual {
             heuristic,
-            expansions: cycle.get_total_expansions_in_cycle(),
-            path_len: cycle.get_total_path_length_in_cycle(),
+            expansions,
+            path_len,
+        }
+    }
+
+    // Tunes the constants of the `memetic_top_k` fittest individuals of the current
+    // population, writing the results back so that the next generation inherits them
+    fn memetic_step(&mut self) {
+        let fitnesses = self
+            .i_population
+            .iter()
+            .map(|i| i.fitness(self.baseline_expansions, self.baseline_path_len))
+            .collect::<Vec<_>>();
+        let mut order = (0..self.i_population.len()).collect::<Vec<_>>();
+        order.sort_by(|&a, &b| {
+            fitnesses[a]
+                .partial_cmp(&fitnesses[b])
+                .unwrap_or(Ordering::Equal)
+        });
+
+        for idx in order.into_iter().take(self.memetic_top_k) {
+            let individual = self.optimize_individual(self.i_population[idx].clone());
+            self.h_population[idx] = individual.heuristic.clone();
+            self.i_population[idx] = individual;
         }
     }
 
+    // Holds the structure of the individual's heuristic fixed and tunes its
+    // constants against the fitness on the problem cycle
+    pub fn optimize_individual(&mut self, individual: Individual) -> Individual {
+        let (baseline_expansions, baseline_path_len) =
+            (self.baseline_expansions, self.baseline_path_len);
+        let optimizer = self.constant_optimizer.clone();
+        let heuristic = individual.heuristic.clone();
+
+        let mut best = individual;
+        let generation = self.generation;
+        optimizer.optimize(&heuristic, |h| {
+            let candidate =
+                self.evaluate_individual(h.clone().derived_from(&heuristic, generation));
+            let fitness = candidate.fitness(baseline_expansions, baseline_path_len);
+            if fitness < best.fitness(baseline_expansions, baseline_path_len) {
+                best = candidate;
+            }
+            fitness
+        });
+        best
+    }
+
+    // Whether the heuristic may enter the population, see `reject_unsafe_heuristics`
+    fn is_acceptable(&self, heuristic: &Heuristic) -> bool {
+        !self.reject_unsafe_heuristics
+            || !matches!(
+                analyze(heuristic, &self.map),
+                Err(AnalysisError::Counterexample(_))
+            )
+    }
+
+    // A mutation of `parent`, which survives unchanged if none of the children
+    // generated is acceptable
+    fn acceptable_child(&self, parent: &Heuristic) -> Heuristic {
+        for _ in 0..MAX_UNSAFE_REJECTIONS {
+            let child = mutate(parent, &self.term_probs).derived_from(parent, self.generation);
+            if self.is_acceptable(&child) {
+                return child;
+            }
+        }
+        parent.clone()
+    }
+
+    // A random main tree of size `hsize`, calling `num_adfs` freshly generated ADFs
+    fn random_heuristic_with_adfs(&self, hsize: i32) -> Heuristic {
+        let adfs = random_adfs(self.num_adfs, &self.term_probs);
+        let scope = Scope {
+            adfs: &adfs,
+            arity: 0,
+        };
+        let root = random_heuristic_in(hsize, &self.term_probs, &scope);
+        Heuristic::with_adfs(root, adfs)
+    }
+
+    // The best individuals found so far, with their fitness and the map they were
+    // evolved on
+    pub fn archive(&self) -> HeuristicArchive {
+        HeuristicArchive::new(
+            self.best_individuals
+                .iter()
+                .map(|i| {
+                    HeuristicRecord::new(
+                        &i.heuristic,
+                        self.map.name.clone(),
+                        Some(i.fitness(self.baseline_expansions, self.baseline_path_len)),
+                    )
+                })
+                .collect(),
+        )
+    }
+
     fn get_next_population(&self) -> Vec<Heuristic> {
         let mut selected = Vec::with_capacity(MAX_POPULATION_SIZE);
 
@@ -242,13 +559,81 @@ impl GeneticAlgorithm {
     }
 }
 
-#[pymethods]
+#[cfg_attr(feature = "python", pymethods)]
 impl GeneticAlgorithm {
+    pub fn enable_semantic_deduplication(
+        &mut self,
+        quantum: Option<f32>,
+    ) -> Result<(), NoPassableTiles> {
+        self.equivalence_classes = Some(EquivalenceClasses::new(
+            &self.map,
+            FINGERPRINT_SAMPLES,
+            quantum,
+        )?);
+        Ok(())
+    }
+
+    pub fn save_archive(&self, path: &str) -> Result<(), ArchiveError> {
+        self.archive().save(path)
+    }
+
+    // Seeds the population with the heuristics of an archive, fittest first. `run`
+    // fills up the rest of the population with random heuristics.
+    pub fn load_archive(&mut self, path: &str) -> Result<(), ArchiveError> {
+        let mut records = HeuristicArchive::load(path)?.records;
+        records.sort_by(|a, b| {
+            let (a, b) = (a.fitness.unwrap_or(f64::MAX), b.fitness.unwrap_or(f64::MAX));
+            a.partial_cmp(&b).unwrap_or(Ordering::Equal)
+        });
+        records.truncate(self.max_population_size);
+
+        self.h_population = HeuristicArchive::new(records).heuristics()?;
+        Ok(())
+    }
+
+    // 0 to 3, from no optimization to aggressive optimization
+    #[cfg(all(feature = "jit", feature = "python"))]
+    pub fn set_optimization_level(&mut self, level: u32) -> PyResult<()> {
+        self.optimization_level = match level {
+            0 => OptimizationLevel::None,
+            1 => OptimizationLevel::Less,
+            2 => OptimizationLevel::Default,
+            3 => OptimizationLevel::Aggressive,
+            _ => {
+                return Err(PyValueError::new_err(format!(
+                    "Invalid optimization level {}, expected 0-3",
+                    level
+                )))
+            }
+        };
+        Ok(())
+    }
+
+    // One of "interpreter", "vm", "llvm" and "cranelift", if compiled in
+    #[cfg(feature = "python")]
+    pub fn set_backend(&mut self, name: &str) -> PyResult<()> {
+        self.backend = match Backend::from_name(name) {
+            Some(backend) => backend,
+            None => {
+                return Err(PyValueError::new_err(format!(
+                    "Unknown or disabled backend {}",
+                    name
+                )))
+            }
+        };
+        Ok(())
+    }
+
+    pub fn optimize_constants(&mut self, heuristic: &Heuristic) -> Heuristic {
+        let individual = self.evaluate_individual(heuristic.clone());
+        self.optimize_individual(individual).heuristic
+    }
+
     pub fn initialize_ga(&mut self) {
         let mut h_population: Vec<Heuristic> = Vec::with_capacity(1000);
         for _ in 0..900 {
-            let h = random_heuristic(fastrand::i32(1..=7), &self.term_probs);
-            h_population.push(Heuristic::new(h));
+            let h = self.random_heuristic_with_adfs(fastrand::i32(1..=7));
+            h_population.push(h);
         }
         self.best_individuals = h_population
             .par_iter()
@@ -280,8 +665,8 @@ impl GeneticAlgorithm {
                 (0..100)
                     // .into_par_iter()
                     .map(|_| {
-                        let h = random_heuristic(fastrand::i32(1..=10), &self.term_probs);
-                        self.compute_individual(Heuristic::new(h))
+                        let h = self.random_heuristic_with_adfs(fastrand::i32(1..=10));
+                        self.compute_individual(h)
                     })
                     .collect::<Vec<_>>(),
             );
@@ -322,10 +707,10 @@ impl GeneticAlgorithm {
                 .zip(0..)
                 // .par_bridge()
                 .map(|(individual, i)| {
-                    Heuristic::new(mutate_heuristic(
-                        individual.heuristic.root(),
+                    mutate(
+                        &individual.heuristic,
                         &Some(probs[i / mutations_per_prob].clone()),
-                    ))
+                    )
                 })
                 .collect();
 
@@ -383,7 +768,7 @@ impl GeneticAlgorithm {
                     "Heuristic {:2.2}% expansions of baseline, {:2.2}% path len of baseline: {}",
                     100.0 * individual.expansions as f64 / self.baseline_expansions as f64,
                     100.0 * individual.path_len as f64 / self.baseline_path_len as f64,
-                    individual.heuristic.root()
+                    individual.heuristic
                 );
             }
             // println!("\n Iterations per second: {}", iter_count as f64 / 100.0);
@@ -403,7 +788,7 @@ impl GeneticAlgorithm {
                 .take(10)
                 .map(|individual| {
                     (
-                        individual.heuristic.root().to_string(),
+                        individual.heuristic.to_string(),
                         individual.fitness(self.baseline_expansions, self.baseline_path_len),
                     )
                 })
@@ -415,6 +800,14 @@ impl GeneticAlgorithm {
     }
 }
 
+// The (expansions, path length) of a solved cycle
+fn cycle_totals(cycle: &CycleSolver) -> (usize, usize) {
+    (
+        cycle.get_total_expansions_in_cycle(),
+        cycle.get_total_path_length_in_cycle(),
+    )
+}
+
 // while timer.elapsed() < self.time_limit {
 //     let h = random_heuristic(fastrand::i32(1..=7));
 //     let individual = self.add_individual(Heuristic { root: h });
@@ -1,11 +1,14 @@
 use std::time::{Duration, Instant};
 
 use priority_queue::PriorityQueue;
+#[cfg(feature = "python")]
 use pyo3::prelude::*;
 
 use crate::alife::search::cycle::{CycleSolver, ProblemCycle};
+use crate::alife::search::problem::ProblemResult;
 use crate::alife::sim::heuristic_result::HeuristicResult;
 use crate::constants::INITIAL_H_POPULATION_SIZE;
+use crate::heuristic::fingerprint::{EquivalenceClasses, NoPassableTiles, FINGERPRINT_SAMPLES};
 use crate::heuristic::mutator::mutate_heuristic;
 use crate::heuristic::parser::parse_heuristic;
 use crate::heuristic::util::random_heuristic;
@@ -31,15 +34,16 @@ pub struct Simulation {
     pub results: Vec<HeuristicResult>,
     // Whether or not to print verbose output
     verbose: bool,
+    // If set, semantically equivalent heuristics reuse the cycle results of the
+    // first heuristic evaluated in their class
+    equivalence_classes: Option<EquivalenceClasses<Vec<ProblemResult>>>,
 }
 
-#[pyclass]
+#[cfg_attr(feature = "python", pyclass(get_all))]
 pub struct SimulationResult {
     // Mapping from each heuristic to its score for the cycle
-    #[pyo3(get)]
     pub heuristics: Vec<HeuristicResult>,
     // The best heuristic found in terms of expansions per cycle
-    #[pyo3(get)]
     pub best: HeuristicResult,
 }
 
@@ -64,6 +68,7 @@ impl Simulation {
             results: Vec::new(),
             trackers: PriorityQueue::new(),
             verbose,
+            equivalence_classes: None,
         };
 
         if seed.is_some() {
@@ -73,6 +78,32 @@ impl Simulation {
         sim
     }
 
+    pub fn enable_semantic_deduplication(
+        &mut self,
+        quantum: Option<f32>,
+    ) -> Result<(), NoPassableTiles> {
+        self.equivalence_classes = Some(EquivalenceClasses::new(
+            &self.map,
+            FINGERPRINT_SAMPLES,
+            quantum,
+        )?);
+        Ok(())
+    }
+
+    // Solves the problem cycle with the given heuristic, unless an equivalent
+    // heuristic has already been solved
+    fn solve_cycle(&mut self, heuristic: &Heuristic) -> Vec<ProblemResult> {
+        let solve = || {
+            CycleSolver::from_cycle(self.cycle.clone(), self.map.clone(), heuristic.clone())
+                .solve_cycle()
+        };
+
+        match self.equivalence_classes.as_mut() {
+            Some(classes) => classes.get_or_insert_with(heuristic, solve),
+            None => solve(),
+        }
+    }
+
     pub fn run(&mut self) -> SimulationResult {
         let mut heuristic_id = 0;
 
@@ -92,10 +123,7 @@ impl Simulation {
                 println!("Seeding heuristic #{}", i);
             }
             let h = Heuristic::new(random_heuristic(-1, &None));
-            let mut cycle =
-                CycleSolver::from_cycle(self.cycle.clone(), self.map.clone(), h.clone());
-
-            let results = cycle.solve_cycle();
+            let results = self.solve_cycle(&h);
             let tracker = ExpansionTracker::new(results, self.expansion_bound, h.clone());
 
             // Get heuristic result, update best heuristic if necessary
@@ -137,12 +165,7 @@ impl Simulation {
             if tracker.consume_mutation() {
                 let new_h = mutate_heuristic(&tracker.get_heuristic().root, &None);
                 let heuristic = Heuristic::new(new_h);
-                let results = CycleSolver::from_cycle(
-                    self.cycle.clone(),
-                    self.map.clone(),
-                    heuristic.clone(),
-                )
-                .solve_cycle();
+                let results = self.solve_cycle(&heuristic);
                 let new_tracker = ExpansionTracker::new(results, self.expansion_bound, heuristic);
 
                 // Get heuristic result, update best if necessary
@@ -3,3 +3,22 @@ pub const EDGE_COST: f32 = 1.0;
 pub const INITIAL_H_POPULATION_SIZE: i32 = 40;
 pub const PROBLEM_CYCLE_LENGTH: usize = 500;
 pub const MUTATION_INTERVAL: usize = 1000;
+// Chance that a random subtree of size >= 5 is rooted at a conditional
+pub const CONDITIONAL_PROBABILITY: f64 = 0.1;
+// Chance that a random subtree calls one of the ADFs in scope, and that a random
+// leaf of an ADF body reads one of its parameters
+pub const CALL_PROBABILITY: f64 = 0.1;
+pub const ARGUMENT_PROBABILITY: f64 = 0.5;
+// Bounds on randomly generated ADFs
+pub const MAX_ADF_ARITY: usize = 3;
+pub const MAX_ADF_SIZE: i32 = 10;
+// Attempts at generating a child the interval analysis proves safe, when the GA
+// rejects unsafe heuristics, before the parent is kept instead
+pub const MAX_UNSAFE_REJECTIONS: usize = 10;
+// `CycleSolver` solves this many problems directly before deciding on tables, which
+// it uses when they traversed more edges on average than this ratio times the number
+// of passable cells
+pub const TABLE_SAMPLE_SIZE: usize = 8;
+pub const TABLE_TRAVERSAL_RATIO: f64 = 1.0;
+// The number of compiled heuristics the genetic algorithm keeps between generations
+pub const COMPILE_CACHE_CAPACITY: usize = 256;
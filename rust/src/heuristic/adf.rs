@@ -0,0 +1,157 @@
+use super::mutate_probs::TermProbabilities;
+use super::parser::HeuristicNode;
+use super::util::random_heuristic_in;
+use crate::constants::{MAX_ADF_ARITY, MAX_ADF_SIZE};
+
+// An automatically defined function: a sub-heuristic evolved alongside the main
+// tree, reading its parameters through `HeuristicNode::Argument`. The ADF at index
+// `i` of a heuristic may only call the ADFs before it, so calls never recurse.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Adf {
+    pub arity: usize,
+    pub body: HeuristicNode,
+}
+
+// What a tree may refer to besides terminals and numbers: the ADFs it may call,
+// and the number of parameters of the ADF it is the body of (0 for a main tree)
+#[derive(Debug, Clone, Copy, Default)]
+pub struct Scope<'a> {
+    pub adfs: &'a [Adf],
+    pub arity: usize,
+}
+
+impl<'a> Scope<'a> {
+    // The scope of the body of the ADF at `index`
+    pub fn of_adf(adfs: &'a [Adf], index: usize) -> Scope<'a> {
+        Scope {
+            adfs: &adfs[..index],
+            arity: adfs[index].arity,
+        }
+    }
+}
+
+pub fn random_adfs(count: usize, term_probs: &Option<TermProbabilities>) -> Vec<Adf> {
+    let mut adfs = Vec::with_capacity(count);
+    for _ in 0..count {
+        let scope = Scope {
+            adfs: &adfs,
+            arity: fastrand::usize(1..=MAX_ADF_ARITY),
+        };
+        let body = random_heuristic_in(fastrand::i32(1..=MAX_ADF_SIZE), term_probs, &scope);
+        adfs.push(Adf {
+            arity: scope.arity,
+            body,
+        });
+    }
+    adfs
+}
+
+// Replaces every call with the body of the callee, its parameters substituted by
+// the arguments of the call. The result is a plain tree the executors can run;
+// arguments used more than once become shared subtrees, which the `Jit` computes once.
+pub fn inline_calls(heuristic: &HeuristicNode, adfs: &[Adf]) -> HeuristicNode {
+    let inline = |h: &HeuristicNode| Box::new(inline_calls(h, adfs));
+
+    match heuristic {
+        HeuristicNode::Number(_) | HeuristicNode::Terminal(_) | HeuristicNode::Argument(_) => {
+            heuristic.clone()
+        }
+        HeuristicNode::Unary(rule, h) => HeuristicNode::Unary(*rule, inline(h)),
+        HeuristicNode::Binary(rule, h1, h2) => HeuristicNode::Binary(*rule, inline(h1), inline(h2)),
+        HeuristicNode::Conditional(rule, h1, h2, h3, h4) => {
+            HeuristicNode::Conditional(*rule, inline(h1), inline(h2), inline(h3), inline(h4))
+        }
+        HeuristicNode::Call(index, args) => {
+            let args = args
+                .iter()
+                .map(|arg| inline_calls(arg, adfs))
+                .collect::<Vec<_>>();
+            // The callee only calls earlier ADFs, which are inlined in turn
+            let body = inline_calls(&adfs[*index].body, &adfs[..*index]);
+            substitute_arguments(&body, &args)
+        }
+    }
+}
+
+fn substitute_arguments(heuristic: &HeuristicNode, args: &[HeuristicNode]) -> HeuristicNode {
+    let substitute = |h: &HeuristicNode| Box::new(substitute_arguments(h, args));
+
+    match heuristic {
+        HeuristicNode::Number(_) | HeuristicNode::Terminal(_) => heuristic.clone(),
+        HeuristicNode::Argument(index) => args[*index].clone(),
+        HeuristicNode::Unary(rule, h) => HeuristicNode::Unary(*rule, substitute(h)),
+        HeuristicNode::Binary(rule, h1, h2) => {
+            HeuristicNode::Binary(*rule, substitute(h1), substitute(h2))
+        }
+        HeuristicNode::Conditional(rule, h1, h2, h3, h4) => HeuristicNode::Conditional(
+            *rule,
+            substitute(h1),
+            substitute(h2),
+            substitute(h3),
+            substitute(h4),
+        ),
+        HeuristicNode::Call(index, call_args) => HeuristicNode::Call(
+            *index,
+            call_args
+                .iter()
+                .map(|arg| substitute_arguments(arg, args))
+                .collect(),
+        ),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::heuristic::parser::parse_heuristic;
+    use crate::heuristic::util::heuristic_size;
+
+    #[test]
+    fn test_inline_calls() {
+        let h = parse_heuristic(
+            "(defun adf0 2 (max arg0 arg1))
+             (defun adf1 1 (adf0 (sqr arg0) arg0))
+             (+ (adf1 deltaX) (adf0 1 y1))",
+        );
+        assert_eq!(
+            h.inlined(),
+            parse_heuristic("(+ (max (sqr deltaX) deltaX) (max 1 y1))").root
+        );
+    }
+
+    #[test]
+    fn test_inline_unused_argument() {
+        let h = parse_heuristic("(defun adf0 2 (neg arg1)) (adf0 x1 x2)");
+        assert_eq!(h.inlined(), parse_heuristic("(neg x2)").root);
+    }
+
+    #[test]
+    fn test_random_adfs() {
+        for _ in 0..100 {
+            let adfs = random_adfs(3, &None);
+            for (index, adf) in adfs.iter().enumerate() {
+                assert!((1..=MAX_ADF_ARITY).contains(&adf.arity));
+                assert!(heuristic_size(&adf.body) <= MAX_ADF_SIZE);
+                assert_valid(&adf.body, &Scope::of_adf(&adfs, index));
+            }
+        }
+    }
+
+    fn assert_valid(heuristic: &HeuristicNode, scope: &Scope) {
+        match heuristic {
+            HeuristicNode::Argument(index) => assert!(*index < scope.arity),
+            HeuristicNode::Call(index, args) => {
+                assert_eq!(args.len(), scope.adfs[*index].arity);
+                args.iter().for_each(|arg| assert_valid(arg, scope));
+            }
+            HeuristicNode::Number(_) | HeuristicNode::Terminal(_) => {}
+            HeuristicNode::Unary(_, h) => assert_valid(h, scope),
+            HeuristicNode::Binary(_, h1, h2) => {
+                [h1, h2].iter().for_each(|h| assert_valid(h, scope))
+            }
+            HeuristicNode::Conditional(_, h1, h2, h3, h4) => {
+                [h1, h2, h3, h4].iter().for_each(|h| assert_valid(h, scope))
+            }
+        }
+    }
+}
@@ -0,0 +1,328 @@
+use std::collections::{HashMap, HashSet};
+use std::fmt::Display;
+use std::fs;
+use std::time::Duration;
+
+#[cfg(feature = "python")]
+use pyo3::exceptions::{PyIOError, PyValueError};
+#[cfg(feature = "python")]
+use pyo3::PyErr;
+use serde::{Deserialize, Serialize};
+
+use super::parser::{try_parse_heuristic, HeuristicParseError};
+use super::Heuristic;
+
+// Bumped whenever the layout of a `HeuristicArchive` changes incompatibly
+pub const ARCHIVE_VERSION: u32 = 1;
+
+// A heuristic along with where it came from and how well it did
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct HeuristicRecord {
+    // As read by `parse_heuristic`, including any ADF definitions
+    pub heuristic: String,
+    pub size: usize,
+    // Milliseconds since the unix epoch
+    pub creation: u64,
+    pub parents: Vec<String>,
+    pub generation: usize,
+    // The name of the map the heuristic was evolved on
+    pub map: Option<String>,
+    pub fitness: Option<f64>,
+}
+
+impl HeuristicRecord {
+    pub fn new(
+        heuristic: &Heuristic,
+        map: Option<String>,
+        fitness: Option<f64>,
+    ) -> HeuristicRecord {
+        HeuristicRecord {
+            heuristic: heuristic.to_string(),
+            size: heuristic.size(),
+            creation: heuristic.creation.as_millis() as u64,
+            parents: heuristic.parents.clone(),
+            generation: heuristic.generation,
+            map,
+            fitness,
+        }
+    }
+
+    // Restores the heuristic, including its creation time and lineage
+    pub fn to_heuristic(&self) -> Result<Heuristic, ArchiveError> {
+        let mut heuristic = try_parse_heuristic(&self.heuristic)?;
+        heuristic.creation = Duration::from_millis(self.creation);
+        heuristic.parents = self.parents.clone();
+        heuristic.generation = self.generation;
+        Ok(heuristic)
+    }
+
+    // Records without a fitness are worse than any with one
+    fn is_better_than(&self, other: &HeuristicRecord) -> bool {
+        match (self.fitness, other.fitness) {
+            (Some(fitness), Some(other_fitness)) => fitness < other_fitness,
+            (Some(_), None) => true,
+            (None, _) => false,
+        }
+    }
+}
+
+// A versioned collection of heuristic records. Archives are stored as pretty-printed
+// JSON, one field per line, so that they can be compared with line-based diff tools.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct HeuristicArchive {
+    pub version: u32,
+    pub records: Vec<HeuristicRecord>,
+}
+
+// Read before the rest of an archive, so that other versions are reported as such
+// rather than as malformed
+#[derive(Deserialize)]
+struct ArchiveHeader {
+    version: u32,
+}
+
+impl Default for HeuristicArchive {
+    fn default() -> Self {
+        HeuristicArchive::new(Vec::new())
+    }
+}
+
+impl HeuristicArchive {
+    pub fn new(records: Vec<HeuristicRecord>) -> HeuristicArchive {
+        HeuristicArchive {
+            version: ARCHIVE_VERSION,
+            records,
+        }
+    }
+
+    pub fn to_json(&self) -> String {
+        serde_json::to_string_pretty(self).expect("Archives are always serializable")
+    }
+
+    pub fn from_json(json: &str) -> Result<HeuristicArchive, ArchiveError> {
+        let header: ArchiveHeader = serde_json::from_str(json)?;
+        if header.version != ARCHIVE_VERSION {
+            return Err(ArchiveError::UnsupportedVersion(header.version));
+        }
+        Ok(serde_json::from_str(json)?)
+    }
+
+    pub fn save(&self, path: &str) -> Result<(), ArchiveError> {
+        Ok(fs::write(path, self.to_json() + "\n")?)
+    }
+
+    pub fn load(path: &str) -> Result<HeuristicArchive, ArchiveError> {
+        HeuristicArchive::from_json(&fs::read_to_string(path)?)
+    }
+
+    pub fn heuristics(&self) -> Result<Vec<Heuristic>, ArchiveError> {
+        self.records
+            .iter()
+            .map(|record| record.to_heuristic())
+            .collect()
+    }
+
+    // Adds the records of `other` for heuristics not yet in this archive. For
+    // heuristics in both, the record with the better (lower) fitness is kept.
+    pub fn merge(&mut self, other: &HeuristicArchive) {
+        let mut indices = self
+            .records
+            .iter()
+            .enumerate()
+            .map(|(idx, record)| (record.heuristic.clone(), idx))
+            .collect::<HashMap<_, _>>();
+
+        for record in &other.records {
+            match indices.get(&record.heuristic) {
+                Some(&idx) if record.is_better_than(&self.records[idx]) => {
+                    self.records[idx] = record.clone();
+                }
+                Some(_) => {}
+                None => {
+                    indices.insert(record.heuristic.clone(), self.records.len());
+                    self.records.push(record.clone());
+                }
+            }
+        }
+    }
+
+    // The records whose heuristic is only in this archive, and those whose heuristic
+    // is only in `other`
+    pub fn diff<'a>(
+        &'a self,
+        other: &'a HeuristicArchive,
+    ) -> (Vec<&'a HeuristicRecord>, Vec<&'a HeuristicRecord>) {
+        let only_in = |records: &'a [HeuristicRecord], others: &[HeuristicRecord]| {
+            let others = others
+                .iter()
+                .map(|record| &record.heuristic)
+                .collect::<HashSet<_>>();
+            records
+                .iter()
+                .filter(|record| !others.contains(&record.heuristic))
+                .collect::<Vec<_>>()
+        };
+
+        (
+            only_in(&self.records, &other.records),
+            only_in(&other.records, &self.records),
+        )
+    }
+}
+
+// An archive that could not be read or written
+#[derive(Debug)]
+pub enum ArchiveError {
+    Io(std::io::Error),
+    Json(serde_json::Error),
+    UnsupportedVersion(u32),
+    Heuristic(HeuristicParseError),
+}
+
+impl Display for ArchiveError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            ArchiveError::Io(e) => write!(f, "could not access archive: {}", e),
+            ArchiveError::Json(e) => write!(f, "malformed archive: {}", e),
+            ArchiveError::UnsupportedVersion(version) => write!(
+                f,
+                "unsupported archive version {} (expected {})",
+                version, ARCHIVE_VERSION
+            ),
+            ArchiveError::Heuristic(e) => write!(f, "malformed heuristic in archive:\n{}", e),
+        }
+    }
+}
+
+impl std::error::Error for ArchiveError {}
+
+impl From<std::io::Error> for ArchiveError {
+    fn from(error: std::io::Error) -> Self {
+        ArchiveError::Io(error)
+    }
+}
+
+impl From<serde_json::Error> for ArchiveError {
+    fn from(error: serde_json::Error) -> Self {
+        ArchiveError::Json(error)
+    }
+}
+
+impl From<HeuristicParseError> for ArchiveError {
+    fn from(error: HeuristicParseError) -> Self {
+        ArchiveError::Heuristic(error)
+    }
+}
+
+#[cfg(feature = "python")]
+impl From<ArchiveError> for PyErr {
+    fn from(error: ArchiveError) -> Self {
+        match error {
+            ArchiveError::Io(_) => PyIOError::new_err(error.to_string()),
+            _ => PyValueError::new_err(error.to_string()),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::heuristic::parser::parse_heuristic;
+
+    fn record(input: &str, fitness: Option<f64>) -> HeuristicRecord {
+        HeuristicRecord::new(
+            &parse_heuristic(input),
+            Some("den312d".to_string()),
+            fitness,
+        )
+    }
+
+    #[test]
+    fn test_round_trip() {
+        let parent = parse_heuristic("(+ deltaX deltaY)");
+        let child = parse_heuristic("(defun adf0 1 (sqr arg0)) (+ (adf0 deltaX) deltaY)")
+            .derived_from(&parent, 7);
+        let archive = HeuristicArchive::new(vec![HeuristicRecord::new(&child, None, Some(1.5))]);
+
+        let restored = HeuristicArchive::from_json(&archive.to_json()).unwrap();
+        assert_eq!(restored, archive);
+
+        let heuristic = &restored.heuristics().unwrap()[0];
+        assert_eq!(heuristic.to_string(), child.to_string());
+        assert_eq!(heuristic.parents, vec!["(plus deltaX deltaY)".to_string()]);
+        assert_eq!(heuristic.generation, 7);
+        assert_eq!(heuristic.creation.as_millis(), child.creation.as_millis());
+    }
+
+    #[test]
+    fn test_save_and_load() {
+        let path = std::env::temp_dir().join(format!("archive-{}.json", std::process::id()));
+        let path = path.to_str().unwrap();
+        let archive = HeuristicArchive::new(vec![record("(max deltaX deltaY)", None)]);
+
+        archive.save(path).unwrap();
+        let loaded = HeuristicArchive::load(path);
+        fs::remove_file(path).unwrap();
+        assert_eq!(loaded.unwrap(), archive);
+    }
+
+    #[test]
+    fn test_unsupported_version() {
+        let json = r#"{ "version": 999, "records": "from the future" }"#;
+        assert!(matches!(
+            HeuristicArchive::from_json(json),
+            Err(ArchiveError::UnsupportedVersion(999))
+        ));
+    }
+
+    #[test]
+    fn test_malformed_heuristic() {
+        let mut archive = HeuristicArchive::new(vec![record("x1", None)]);
+        archive.records[0].heuristic = "(+ x1".to_string();
+        assert!(matches!(
+            archive.heuristics(),
+            Err(ArchiveError::Heuristic(_))
+        ));
+    }
+
+    #[test]
+    fn test_merge() {
+        let mut archive = HeuristicArchive::new(vec![
+            record("x1", Some(2.0)),
+            record("x2", None),
+            record("y1", Some(1.0)),
+        ]);
+        let other = HeuristicArchive::new(vec![
+            record("x1", Some(1.0)),
+            record("x2", Some(3.0)),
+            record("y1", Some(4.0)),
+            record("y2", None),
+        ]);
+        archive.merge(&other);
+
+        let fitnesses = archive
+            .records
+            .iter()
+            .map(|record| (record.heuristic.as_str(), record.fitness))
+            .collect::<Vec<_>>();
+        assert_eq!(
+            fitnesses,
+            vec![
+                ("x1", Some(1.0)),
+                ("x2", Some(3.0)),
+                ("y1", Some(1.0)),
+                ("y2", None)
+            ]
+        );
+    }
+
+    #[test]
+    fn test_diff() {
+        let archive = HeuristicArchive::new(vec![record("x1", None), record("x2", None)]);
+        let other = HeuristicArchive::new(vec![record("x2", Some(1.0)), record("y1", None)]);
+
+        let (removed, added) = archive.diff(&other);
+        assert_eq!(removed, vec![&archive.records[0]]);
+        assert_eq!(added, vec![&other.records[1]]);
+    }
+}
@@ -0,0 +1,821 @@
+#[cfg(feature = "python")]
+use pyo3::{exceptions::PyValueError, PyErr};
+use std::collections::{BTreeMap, HashMap};
+use std::f32::consts::SQRT_2;
+use std::fmt::{Display, Write};
+
+use super::parser::{HeuristicNode, Rule};
+use super::util::FEATURE_TERMINALS;
+use super::Heuristic;
+use crate::map::features::MapFeatures;
+
+// Generators of standalone source code for heuristics, so that they can be shipped
+// without this crate: a C function and a Rust function, both with the signature of
+// `jit::HeuristicFunc`. The tree is lowered like the `Jit` lowers it, one variable
+// per distinct subtree, and evaluates bit for bit like every executer, provided
+// the C is compiled without floating point contraction (`-ffp-contract=off`) or
+// fast math, against the math library Rust uses. Map feature terminals read copies
+// of the feature tables, so the generated code only holds for the map they were
+// taken from.
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Language {
+    C,
+    Rust,
+}
+
+// Heuristics with map feature terminals can only be generated with feature tables
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct MissingFeatureTables;
+
+impl Display for MissingFeatureTables {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "the heuristic uses map feature terminals, which need a map"
+        )
+    }
+}
+
+impl std::error::Error for MissingFeatureTables {}
+
+#[cfg(feature = "python")]
+impl From<MissingFeatureTables> for PyErr {
+    fn from(error: MissingFeatureTables) -> Self {
+        PyValueError::new_err(error.to_string())
+    }
+}
+
+pub fn heuristic_to_c(
+    heuristic: &Heuristic,
+    name: &str,
+    features: Option<&MapFeatures>,
+) -> Result<String, MissingFeatureTables> {
+    SourceBuilder::new(Language::C, name, features).function(heuristic)
+}
+
+pub fn heuristic_to_rust(
+    heuristic: &Heuristic,
+    name: &str,
+    features: Option<&MapFeatures>,
+) -> Result<String, MissingFeatureTables> {
+    SourceBuilder::new(Language::Rust, name, features).function(heuristic)
+}
+
+fn uses_feature_terminals(node: &HeuristicNode) -> bool {
+    match node {
+        HeuristicNode::Terminal(rule) => FEATURE_TERMINALS.contains(rule),
+        HeuristicNode::Unary(_, h) => uses_feature_terminals(h),
+        HeuristicNode::Binary(_, h1, h2) => {
+            uses_feature_terminals(h1) || uses_feature_terminals(h2)
+        }
+        HeuristicNode::Conditional(_, a, b, h1, h2) => [a, b, h1, h2]
+            .iter()
+            .any(|node| uses_feature_terminals(node)),
+        HeuristicNode::Call(_, arguments) => arguments.iter().any(uses_feature_terminals),
+        HeuristicNode::Number(_) | HeuristicNode::Argument(_) => false,
+    }
+}
+
+struct SourceBuilder<'a> {
+    language: Language,
+    name: &'a str,
+    features: Option<&'a MapFeatures>,
+    // The statements emitted so far, one per line
+    body: String,
+    // The variables of the subtrees emitted so far, keyed by the subtree
+    values: HashMap<String, String>,
+    num_variables: usize,
+    // The feature tables read by the body, by their name in C
+    tables: BTreeMap<&'static str, &'a [f32]>,
+    uses_line_obstacles: bool,
+}
+
+impl<'a> SourceBuilder<'a> {
+    fn new(language: Language, name: &'a str, features: Option<&'a MapFeatures>) -> Self {
+        SourceBuilder {
+            language,
+            name,
+            features,
+            body: String::new(),
+            values: HashMap::new(),
+            num_variables: 0,
+            tables: BTreeMap::new(),
+            uses_line_obstacles: false,
+        }
+    }
+
+    fn function(mut self, heuristic: &Heuristic) -> Result<String, MissingFeatureTables> {
+        let root = heuristic.inlined();
+        if self.features.is_none() && uses_feature_terminals(&root) {
+            return Err(MissingFeatureTables);
+        }
+        let value = self.build(&root);
+
+        let mut source = String::from("// Generated from the heuristic\n");
+        for line in heuristic.to_string().lines() {
+            writeln!(source, "//   {}", line).unwrap();
+        }
+        match self.language {
+            Language::C => {
+                source.push_str("#include <float.h>\n#include <math.h>\n");
+                if self.uses_line_obstacles {
+                    source.push_str("#include <stdlib.h>\n");
+                    source.push('\n');
+                    source.push_str(&self.line_obstacles_function());
+                }
+                source.push('\n');
+                writeln!(
+                    source,
+                    "float {}(float x1, float y1, float x2, float y2) {{",
+                    self.name
+                )
+                .unwrap();
+            }
+            Language::Rust => {
+                writeln!(
+                    source,
+                    "pub extern \"C\" fn {}(x1: f32, y1: f32, x2: f32, y2: f32) -> f32 {{",
+                    self.name
+                )
+                .unwrap();
+                if self.uses_line_obstacles {
+                    source.push_str(&self.line_obstacles_function());
+                }
+            }
+        }
+        for (name, table) in &self.tables {
+            source.push_str(&self.table(name, table, "    "));
+        }
+        source.push_str(&self.body);
+
+        // As reported by `clamp_heuristic_value`
+        match self.language {
+            Language::C => writeln!(
+                source,
+                "    return isnan({0}) ? FLT_MAX : ({0} > 0.0f ? fminf({0}, FLT_MAX) : 0.0f);",
+                value
+            ),
+            Language::Rust => writeln!(
+                source,
+                "    if {0}.is_nan() {{\n        f32::MAX\n    }} else if {0} > 0.0 {{\n        \
+                 {0}.min(f32::MAX)\n    }} else {{\n        0.0\n    }}",
+                value
+            ),
+        }
+        .unwrap();
+        source.push_str("}\n");
+        Ok(source)
+    }
+
+    // Binds `expression` to a new variable
+    fn emit(&mut self, expression: String) -> String {
+        let variable = format!("v{}", self.num_variables);
+        self.num_variables += 1;
+        match self.language {
+            Language::C => writeln!(self.body, "    const float {} = {};", variable, expression),
+            Language::Rust => writeln!(self.body, "    let {} = {};", variable, expression),
+        }
+        .unwrap();
+        variable
+    }
+
+    // Structurally identical subtrees are computed once, like in the `Jit`
+    fn build(&mut self, node: &HeuristicNode) -> String {
+        let key = node.to_string();
+        if let Some(value) = self.values.get(&key) {
+            return value.clone();
+        }
+
+        let value = match node {
+            HeuristicNode::Number(num) => return self.literal(*num),
+            HeuristicNode::Terminal(rule) => match rule {
+                Rule::x1 | Rule::y1 | Rule::x2 | Rule::y2 => return format!("{:?}", rule),
+                _ => self.build_terminal(*rule),
+            },
+            HeuristicNode::Unary(rule, h) => {
+                let value = self.build(h);
+                self.unary(*rule, &value)
+            }
+            HeuristicNode::Binary(rule, h1, h2) => {
+                let (value1, value2) = (self.build(h1), self.build(h2));
+                self.binary(*rule, &value1, &value2)
+            }
+            HeuristicNode::Conditional(rule, h1, h2, h3, h4) => {
+                let (value1, value2) = (self.build(h1), self.build(h2));
+                let (value3, value4) = (self.build(h3), self.build(h4));
+                // Ordered, so that NaN takes the else branch like in the interpreter
+                match (rule, self.language) {
+                    (Rule::ifle, Language::C) => {
+                        format!("{} <= {} ? {} : {}", value1, value2, value3, value4)
+                    }
+                    (Rule::ifle, Language::Rust) => {
+                        format!(
+                            "if {} <= {} {{ {} }} else {{ {} }}",
+                            value1, value2, value3, value4
+                        )
+                    }
+                    _ => unreachable!("{:?}", rule),
+                }
+            }
+            HeuristicNode::Call(..) | HeuristicNode::Argument(_) => {
+                unreachable!("ADF calls are inlined before building")
+            }
+        };
+        let variable = self.emit(value);
+        self.values.insert(key, variable.clone());
+        variable
+    }
+
+    fn build_terminal(&mut self, rule: Rule) -> String {
+        let delta = |builder: &mut Self, rule| builder.build(&HeuristicNode::Terminal(rule));
+        match rule {
+            Rule::deltaX | Rule::deltaY => {
+                let (start, end) = match rule {
+                    Rule::deltaX => ("x1", "x2"),
+                    _ => ("y1", "y2"),
+                };
+                match self.language {
+                    Language::C => format!("fabsf({} - {})", end, start),
+                    Language::Rust => format!("({} - {}).abs()", end, start),
+                }
+            }
+            Rule::manhattan => {
+                let (deltax, deltay) = (delta(self, Rule::deltaX), delta(self, Rule::deltaY));
+                format!("{} + {}", deltax, deltay)
+            }
+            Rule::euclid => {
+                let (deltax, deltay) = (delta(self, Rule::deltaX), delta(self, Rule::deltaY));
+                let sum = format!("({0} * {0} + {1} * {1})", deltax, deltay);
+                match self.language {
+                    Language::C => format!("sqrtf{}", sum),
+                    Language::Rust => format!("{}.sqrt()", sum),
+                }
+            }
+            Rule::octile => {
+                let (deltax, deltay) = (delta(self, Rule::deltaX), delta(self, Rule::deltaY));
+                let max = self.binary(Rule::max, &deltax, &deltay);
+                let max = self.emit(max);
+                let min = self.binary(Rule::min, &deltax, &deltay);
+                let min = self.emit(min);
+                format!("{} + {} * {}", max, self.literal(SQRT_2 - 1.0), min)
+            }
+            Rule::cheby => {
+                let (deltax, deltay) = (delta(self, Rule::deltaX), delta(self, Rule::deltaY));
+                self.binary(Rule::max, &deltax, &deltay)
+            }
+            Rule::lineObstacles => {
+                self.feature_tables();
+                self.uses_line_obstacles = true;
+                format!("{}(x1, y1, x2, y2)", self.line_obstacles_name())
+            }
+            Rule::wallDist => {
+                let table = self.use_table("wall_distances", |features| &features.wall_distances);
+                format!("{}[{}]", table, self.cell_index("x1", "y1"))
+            }
+            Rule::sameRoom => {
+                let table = self.use_table("rooms", |features| &features.rooms);
+                let room1 = format!("{}[{}]", table, self.cell_index("x1", "y1"));
+                let room2 = format!("{}[{}]", table, self.cell_index("x2", "y2"));
+                let (one, zero) = (self.literal(1.0), self.literal(0.0));
+                match self.language {
+                    Language::C => format!("{} == {} ? {} : {}", room1, room2, one, zero),
+                    Language::Rust => {
+                        format!(
+                            "if {} == {} {{ {} }} else {{ {} }}",
+                            room1, room2, one, zero
+                        )
+                    }
+                }
+            }
+            Rule::rowDensity => {
+                let table = self.use_table("row_densities", |features| &features.row_densities);
+                let n = self.feature_tables().n;
+                format!("{}[{}]", table, self.clamped_index("x1", n))
+            }
+            Rule::colDensity => {
+                let table =
+                    self.use_table("column_densities", |features| &features.column_densities);
+                let m = self.feature_tables().m;
+                format!("{}[{}]", table, self.clamped_index("y1", m))
+            }
+            _ => unreachable!("{:?}", rule),
+        }
+    }
+
+    fn unary(&self, rule: Rule, value: &str) -> String {
+        match (rule, self.language) {
+            (Rule::neg, _) => format!("-{}", value),
+            (Rule::sqr, _) => format!("{0} * {0}", value),
+            (Rule::recip, _) => format!("{} / {}", self.literal(1.0), value),
+            (Rule::abs, Language::C) => format!("fabsf({})", value),
+            (Rule::abs, Language::Rust) => format!("{}.abs()", value),
+            (Rule::sqrt, Language::C) => format!("copysignf(sqrtf(fabsf({0})), {0})", value),
+            (Rule::sqrt, Language::Rust) => format!("{0}.abs().sqrt().copysign({0})", value),
+            (Rule::log, Language::C) => format!("logf(fabsf({}))", value),
+            (Rule::log, Language::Rust) => format!("{}.abs().ln()", value),
+            (Rule::exp, Language::C) => format!("expf({})", value),
+            (Rule::exp, Language::Rust) => format!("{}.exp()", value),
+            (Rule::floor, Language::C) => format!("floorf({})", value),
+            (Rule::floor, Language::Rust) => format!("{}.floor()", value),
+            (Rule::ceil, Language::C) => format!("ceilf({})", value),
+            (Rule::ceil, Language::Rust) => format!("{}.ceil()", value),
+            _ => unreachable!("{:?}", rule),
+        }
+    }
+
+    // `max` and `min` are written out as `llvm.maxnum` and `llvm.minnum` are lowered
+    // in the `Jit`: a NaN first operand gives the second, and ties such as 0 and -0
+    // give the first
+    fn binary(&self, rule: Rule, value1: &str, value2: &str) -> String {
+        match (rule, self.language) {
+            (Rule::plus, _) => format!("{} + {}", value1, value2),
+            (Rule::minus, _) => format!("{} - {}", value1, value2),
+            (Rule::mul, _) => format!("{} * {}", value1, value2),
+            (Rule::div, _) => format!("{} / {}", value1, value2),
+            (Rule::max | Rule::min, _) => {
+                let comparison = match rule {
+                    Rule::max => ">",
+                    _ => "<",
+                };
+                match self.language {
+                    Language::C => format!(
+                        "isnan({0}) || {1} {2} {0} ? {1} : {0}",
+                        value1, value2, comparison
+                    ),
+                    Language::Rust => format!(
+                        "if {0}.is_nan() || {1} {2} {0} {{ {1} }} else {{ {0} }}",
+                        value1, value2, comparison
+                    ),
+                }
+            }
+            (Rule::pow, Language::C) => format!("powf(fabsf({}), {})", value1, value2),
+            (Rule::pow, Language::Rust) => format!("{}.abs().powf({})", value1, value2),
+            _ => unreachable!("{:?}", rule),
+        }
+    }
+
+    // Round trips exactly, as both languages round decimal literals to the nearest
+    // float. Negative literals are bracketed, as they may be the receiver of a method
+    // in Rust and the operand of a negation in C.
+    fn literal(&self, value: f32) -> String {
+        let literal = match (self.language, value.is_finite()) {
+            (Language::C, true) => format!("{:?}f", value.abs()),
+            (Language::Rust, true) => format!("{:?}f32", value.abs()),
+            (Language::C, false) if value.is_nan() => return "NAN".to_string(),
+            (Language::Rust, false) if value.is_nan() => return "f32::NAN".to_string(),
+            (Language::C, false) => "INFINITY".to_string(),
+            (Language::Rust, false) => "f32::INFINITY".to_string(),
+        };
+        match value.is_sign_negative() {
+            true => format!("(-{})", literal),
+            false => literal,
+        }
+    }
+
+    // Checked to be set by `function`, before anything is built
+    fn feature_tables(&self) -> &'a MapFeatures {
+        self.features.unwrap()
+    }
+
+    // The name of the table, which is declared at the top of the function
+    fn use_table(&mut self, name: &'static str, table: fn(&MapFeatures) -> &Vec<f32>) -> String {
+        let table = table(self.feature_tables());
+        self.tables.insert(name, table);
+        self.table_name(name)
+    }
+
+    fn table_name(&self, name: &str) -> String {
+        match self.language {
+            Language::C => name.to_string(),
+            Language::Rust => name.to_uppercase(),
+        }
+    }
+
+    fn table(&self, name: &str, table: &[f32], indent: &str) -> String {
+        let items = table.iter().map(|&value| self.literal(value));
+        match self.language {
+            Language::C => format!(
+                "{}static const float {}[{}] = {{{}}};\n",
+                indent,
+                name,
+                table.len(),
+                array_items(items, indent)
+            ),
+            Language::Rust => format!(
+                "{}static {}: [f32; {}] = [{}];\n",
+                indent,
+                self.table_name(name),
+                table.len(),
+                array_items(items, indent)
+            ),
+        }
+    }
+
+    // Truncates a coordinate to an index into a table of `len` entries, clamping
+    // it like `MapFeatures` does
+    fn clamped_index(&self, value: &str, len: usize) -> String {
+        let last = self.literal((len - 1) as f32);
+        match self.language {
+            Language::C => format!("(int)fminf(fmaxf({}, 0.0f), {})", value, last),
+            Language::Rust => format!("{}.max(0.0).min({}) as usize", value, last),
+        }
+    }
+
+    fn cell_index(&self, x: &str, y: &str) -> String {
+        let features = self.feature_tables();
+        let row = self.clamped_index(x, features.n);
+        let column = self.clamped_index(y, features.m);
+        match self.language {
+            Language::C => format!("{} * {} + {}", row, features.m, column),
+            Language::Rust => format!("({}) * {} + ({})", row, features.m, column),
+        }
+    }
+
+    fn line_obstacles_name(&self) -> String {
+        match self.language {
+            Language::C => format!("{}_line_obstacles", self.name),
+            Language::Rust => "line_obstacles".to_string(),
+        }
+    }
+
+    // `MapFeatures::line_obstacles`, over a copy of the obstacles of the map. In
+    // Rust it is nested in the heuristic's function, in C a static function.
+    fn line_obstacles_function(&self) -> String {
+        let features = self.feature_tables();
+        let obstacles = features.obstacles.iter();
+        let (last_row, last_column) = (
+            self.literal((features.n - 1) as f32),
+            self.literal((features.m - 1) as f32),
+        );
+        match self.language {
+            Language::C => {
+                let obstacles = obstacles.map(|&obstacle| (obstacle as u8).to_string());
+                format!(
+                    "static float {name}(float x1, float y1, float x2, float y2) {{
+    static const unsigned char obstacles[{len}] = {{{obstacles}}};
+    long x = (long)fminf(fmaxf(x1, 0.0f), {last_row});
+    long y = (long)fminf(fmaxf(y1, 0.0f), {last_column});
+    const long x_end = (long)fminf(fmaxf(x2, 0.0f), {last_row});
+    const long y_end = (long)fminf(fmaxf(y2, 0.0f), {last_column});
+    const long dx = labs(x_end - x), dy = -labs(y_end - y);
+    const long sx = (x_end > x) - (x_end < x), sy = (y_end > y) - (y_end < y);
+    long error = dx + dy;
+
+    int count = 0;
+    for (;;) {{
+        if (obstacles[x * {m} + y]) {{
+            count++;
+        }}
+        if (x == x_end && y == y_end) {{
+            break;
+        }}
+
+        const long error2 = 2 * error;
+        if (error2 >= dy) {{
+            error += dy;
+            x += sx;
+        }}
+        if (error2 <= dx) {{
+            error += dx;
+            y += sy;
+        }}
+    }}
+
+    return (float)count;
+}}
+",
+                    name = self.line_obstacles_name(),
+                    len = features.obstacles.len(),
+                    obstacles = array_items(obstacles, ""),
+                    m = features.m,
+                )
+            }
+            Language::Rust => {
+                let obstacles = obstacles.map(|obstacle| obstacle.to_string());
+                format!(
+                    "    fn line_obstacles(x1: f32, y1: f32, x2: f32, y2: f32) -> f32 {{
+        static OBSTACLES: [bool; {len}] = [{obstacles}];
+        let row = |x: f32| x.max(0.0).min({last_row}) as i64;
+        let column = |y: f32| y.max(0.0).min({last_column}) as i64;
+        let (mut x, mut y) = (row(x1), column(y1));
+        let (x2, y2) = (row(x2), column(y2));
+        let (dx, dy) = ((x2 - x).abs(), -(y2 - y).abs());
+        let (sx, sy) = ((x2 - x).signum(), (y2 - y).signum());
+        let mut error = dx + dy;
+
+        let mut count = 0;
+        loop {{
+            if OBSTACLES[(x * {m} + y) as usize] {{
+                count += 1;
+            }}
+            if x == x2 && y == y2 {{
+                break;
+            }}
+
+            let error2 = 2 * error;
+            if error2 >= dy {{
+                error += dy;
+                x += sx;
+            }}
+            if error2 <= dx {{
+                error += dx;
+                y += sy;
+            }}
+        }}
+
+        count as f32
+    }}
+",
+                    len = features.obstacles.len(),
+                    obstacles = array_items(obstacles, "    "),
+                    m = features.m,
+                )
+            }
+        }
+    }
+}
+
+// The items of an array literal, eight to a line below the declaration at `indent`
+fn array_items(items: impl Iterator<Item = String>, indent: &str) -> String {
+    let items = items.collect::<Vec<_>>();
+    let lines = items
+        .chunks(8)
+        .map(|chunk| format!("{}    {},", indent, chunk.join(", ")))
+        .collect::<Vec<_>>();
+    format!("\n{}\n{}", lines.join("\n"), indent)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    #[cfg(feature = "jit")]
+    use crate::heuristic::executors::jit::Jit;
+    use crate::heuristic::executors::{interpreter::Interpreter, HeuristicExecuter};
+    use crate::heuristic::mutate_probs::TermProbabilities;
+    use crate::heuristic::parser::parse_heuristic;
+    use crate::heuristic::util::random_heuristic;
+    use crate::map::util::Map;
+    #[cfg(feature = "jit")]
+    use inkwell::OptimizationLevel;
+
+    use std::process::Command;
+
+    use test_case::test_case;
+
+    #[test]
+    fn test_to_rust() {
+        let h = parse_heuristic("(+ (sqr deltaX) (max deltaX 2))");
+        assert_eq!(
+            heuristic_to_rust(&h, "h", None).unwrap(),
+            "// Generated from the heuristic
+//   (plus (sqr deltaX) (max deltaX 2))
+pub extern \"C\" fn h(x1: f32, y1: f32, x2: f32, y2: f32) -> f32 {
+    let v0 = (x2 - x1).abs();
+    let v1 = v0 * v0;
+    let v2 = if v0.is_nan() || 2.0f32 > v0 { 2.0f32 } else { v0 };
+    let v3 = v1 + v2;
+    if v3.is_nan() {
+        f32::MAX
+    } else if v3 > 0.0 {
+        v3.min(f32::MAX)
+    } else {
+        0.0
+    }
+}
+"
+        );
+    }
+
+    #[test]
+    fn test_to_c() {
+        let h = parse_heuristic("(defun adf0 1 (sqrt arg0)) (ifle x1 -1.5 (adf0 y1) wallDist)");
+        let features = Map::walled().features;
+        let c = heuristic_to_c(&h, "h", Some(&features)).unwrap();
+        assert!(c.starts_with(
+            "// Generated from the heuristic
+//   (defun adf0 1 (sqrt arg0))
+//   (ifle x1 -1.5 (adf0 y1) wallDist)
+#include <float.h>
+#include <math.h>
+
+float h(float x1, float y1, float x2, float y2) {
+    static const float wall_distances[20] = {
+        "
+        ));
+        assert!(c.ends_with(
+            "    const float v0 = copysignf(sqrtf(fabsf(y1)), y1);
+    const float v1 = wall_distances[(int)fminf(fmaxf(x1, 0.0f), 3.0f) * 5 + (int)fminf(fmaxf(y1, 0.0f), 4.0f)];
+    const float v2 = x1 <= (-1.5f) ? v0 : v1;
+    return isnan(v2) ? FLT_MAX : (v2 > 0.0f ? fminf(v2, FLT_MAX) : 0.0f);
+}
+"
+        ));
+    }
+
+    #[test_case("(+ x1 wallDist)", false; "Feature terminal")]
+    #[test_case("(defun adf0 1 (* arg0 sameRoom)) (adf0 x1)", false; "Feature terminal in an ADF")]
+    #[test_case("(defun adf0 1 (* arg0 y2)) (adf0 x1)", true; "No feature terminal")]
+    fn test_without_feature_tables(heuristic: &str, generated: bool) {
+        let h = parse_heuristic(heuristic);
+        assert_eq!(heuristic_to_c(&h, "h", None).is_ok(), generated);
+        assert_eq!(heuristic_to_rust(&h, "h", None).is_ok(), generated);
+    }
+
+    #[test_case(1.5, "1.5f", "1.5f32")]
+    #[test_case(-3.0, "(-3.0f)", "(-3.0f32)")]
+    #[test_case(-0.0, "(-0.0f)", "(-0.0f32)")]
+    #[test_case(1e-7, "1e-7f", "1e-7f32")]
+    #[test_case(f32::NEG_INFINITY, "(-INFINITY)", "(-f32::INFINITY)")]
+    fn test_literal(value: f32, c: &str, rust: &str) {
+        assert_eq!(SourceBuilder::new(Language::C, "h", None).literal(value), c);
+        assert_eq!(
+            SourceBuilder::new(Language::Rust, "h", None).literal(value),
+            rust
+        );
+    }
+
+    // Compiles `source` with `command` into a program in a fresh directory, and runs
+    // it for its standard output
+    fn compile_and_run(source: &str, file: &str, command: &[&str]) -> String {
+        let directory =
+            std::env::temp_dir().join(format!("heuristic_codegen_{}_{}", std::process::id(), file));
+        std::fs::create_dir_all(&directory).unwrap();
+        let (source_path, program) = (directory.join(file), directory.join("program"));
+        std::fs::write(&source_path, source).unwrap();
+
+        let compilation = Command::new(command[0])
+            .args(&command[1..])
+            .arg(&source_path)
+            .arg("-o")
+            .arg(&program)
+            .args(if file.ends_with(".c") {
+                &["-lm"][..]
+            } else {
+                &[]
+            })
+            .output()
+            .unwrap_or_else(|error| panic!("Could not run {}: {}", command[0], error));
+        assert!(
+            compilation.status.success(),
+            "{}",
+            String::from_utf8_lossy(&compilation.stderr)
+        );
+        let output = Command::new(&program).output().unwrap();
+        std::fs::remove_dir_all(&directory).unwrap();
+        String::from_utf8(output.stdout).unwrap()
+    }
+
+    // The generated functions of random heuristics, compiled into a program that
+    // prints their values over random points, must agree bit for bit with the
+    // interpreter and the `Jit`, including the map feature terminals and out of map
+    // points. Needs `rustc` and a C compiler, overridden with `RUSTC` and `CC`.
+    #[test_case("rust")]
+    #[test_case("c")]
+    fn test_agrees_with_interpreter(language: &str) {
+        let compiler = match language {
+            "rust" => std::env::var("RUSTC").unwrap_or_else(|_| "rustc".to_string()),
+            _ => std::env::var("CC").unwrap_or_else(|_| "cc".to_string()),
+        };
+        let map = Map::walled();
+        let term_probs = Some(TermProbabilities::new(true).with_feature_terminals());
+        let heuristics = (0..40)
+            .map(|_| Heuristic::new(random_heuristic(fastrand::i32(1..=30), &term_probs)))
+            .collect::<Vec<_>>();
+        let points = (0..100)
+            .map(|_| {
+                let (x1, x2) = (fastrand::i32(-1..=5) as f32, fastrand::i32(-1..=5) as f32);
+                let (y1, y2) = (fastrand::i32(-1..=6) as f32, fastrand::i32(-1..=6) as f32);
+                (x1, y1, x2, y2)
+            })
+            .collect::<Vec<_>>();
+        let names = (0..heuristics.len())
+            .map(|index| format!("heuristic{}", index))
+            .collect::<Vec<_>>();
+        // Without suffixes, which the points are written with in each language
+        let point_items = |suffix: &str| {
+            points
+                .iter()
+                .map(|point| {
+                    let (x1, y1, x2, y2) = point;
+                    [x1, y1, x2, y2]
+                        .map(|value| format!("{:?}{}", value, suffix))
+                        .join(", ")
+                })
+                .collect::<Vec<_>>()
+        };
+
+        let mut source = String::new();
+        let output = match language {
+            "rust" => {
+                for (heuristic, name) in heuristics.iter().zip(&names) {
+                    source += &heuristic_to_rust(heuristic, name, Some(&map.features)).unwrap();
+                }
+                let point_items = point_items("f32")
+                    .into_iter()
+                    .map(|point| format!("({})", point));
+                write!(
+                    source,
+                    "static POINTS: [(f32, f32, f32, f32); {}] = [{}];
+fn main() {{
+    let functions: [extern \"C\" fn(f32, f32, f32, f32) -> f32; {}] = [{}];
+    for function in functions {{
+        for (x1, y1, x2, y2) in POINTS {{
+            println!(\"{{}}\", function(x1, y1, x2, y2).to_bits());
+        }}
+    }}
+}}
+",
+                    points.len(),
+                    point_items.collect::<Vec<_>>().join(", "),
+                    names.len(),
+                    names.join(", ")
+                )
+                .unwrap();
+                compile_and_run(&source, "heuristics.rs", &[&compiler, "--edition=2021"])
+            }
+            _ => {
+                for (heuristic, name) in heuristics.iter().zip(&names) {
+                    source += &heuristic_to_c(heuristic, name, Some(&map.features)).unwrap();
+                }
+                write!(
+                    source,
+                    "#include <stdio.h>
+#include <string.h>
+static const float points[{}][4] = {{{}}};
+static float (*const functions[])(float, float, float, float) = {{{}}};
+int main(void) {{
+    for (unsigned i = 0; i < sizeof(functions) / sizeof(functions[0]); i++) {{
+        for (unsigned j = 0; j < sizeof(points) / sizeof(points[0]); j++) {{
+            const float *p = points[j];
+            const float value = functions[i](p[0], p[1], p[2], p[3]);
+            unsigned bits;
+            memcpy(&bits, &value, sizeof(bits));
+            printf(\"%u\\n\", bits);
+        }}
+    }}
+    return 0;
+}}
+",
+                    points.len(),
+                    point_items("f")
+                        .into_iter()
+                        .map(|point| format!("{{{}}}", point))
+                        .collect::<Vec<_>>()
+                        .join(", "),
+                    names.join(", ")
+                )
+                .unwrap();
+                compile_and_run(
+                    &source,
+                    "heuristics.c",
+                    // Without builtins, so that GCC does not fold the calls to
+                    // the math library with its own rounding
+                    &[
+                        &compiler,
+                        "-std=c99",
+                        "-O2",
+                        "-ffp-contract=off",
+                        "-fno-builtin",
+                    ],
+                )
+            }
+        };
+
+        #[cfg(feature = "jit")]
+        let context = inkwell::context::Context::create();
+        #[cfg(feature = "jit")]
+        let mut jits = Jit::create_population(
+            &heuristics,
+            &context,
+            Some(map.features.clone()),
+            OptimizationLevel::Default,
+        )
+        .into_iter();
+        let mut values = output.lines();
+        for heuristic in &heuristics {
+            let interpreter = Interpreter::create_with_features(heuristic, map.features.clone());
+            #[cfg(feature = "jit")]
+            let jit = jits.next().unwrap();
+            for &(x1, y1, x2, y2) in &points {
+                let value = values.next().unwrap().parse::<u32>().unwrap();
+                assert_eq!(
+                    value,
+                    interpreter.execute(x1, y1, x2, y2).to_bits(),
+                    "{} at {:?}",
+                    heuristic,
+                    (x1, y1, x2, y2)
+                );
+                #[cfg(feature = "jit")]
+                assert_eq!(
+                    value,
+                    jit.execute(x1, y1, x2, y2).to_bits(),
+                    "{} at {:?} with the Jit",
+                    heuristic,
+                    (x1, y1, x2, y2)
+                );
+            }
+        }
+        assert!(values.next().is_none());
+    }
+}
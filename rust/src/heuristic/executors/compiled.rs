@@ -0,0 +1,281 @@
+use std::cmp::Reverse;
+use std::collections::HashMap;
+use std::sync::Arc;
+
+use inkwell::{context::Context, OptimizationLevel};
+use priority_queue::PriorityQueue;
+
+use crate::heuristic::{
+    executors::{jit::Jit, HeuristicExecuter},
+    Heuristic,
+};
+use crate::map::features::MapFeatures;
+
+// The functions of one module from `Jit::create_population`, together with the
+// context they were compiled in
+struct CompiledModule {
+    // Declared before the context, so that they are dropped before it
+    jits: Vec<Jit<'static>>,
+    _context: Box<Context>,
+}
+
+// The compiled code is never modified after compilation, and the reference counted
+// execution engine inside the `Jit`s is only touched when they are dropped, which
+// happens once, on whichever thread drops the last `Arc` to the module
+unsafe impl Send for CompiledModule {}
+unsafe impl Sync for CompiledModule {}
+
+impl CompiledModule {
+    fn compile(
+        heuristics: &[Heuristic],
+        features: Option<Arc<MapFeatures>>,
+        optimization: OptimizationLevel,
+    ) -> CompiledModule {
+        let context = Box::new(Context::create());
+        // The context is boxed, so it stays in place when the module is moved, and
+        // the `Jit`s borrowing it are dropped first
+        let context_ref = unsafe { &*(context.as_ref() as *const Context) };
+        let jits = Jit::create_population(heuristics, context_ref, features, optimization);
+        CompiledModule {
+            jits,
+            _context: context,
+        }
+    }
+}
+
+// A `Jit` that owns its context, so that it can be stored, cached and shared between
+// threads. Clones share the compiled code.
+#[derive(Clone)]
+pub struct CompiledHeuristic {
+    module: Arc<CompiledModule>,
+    index: usize,
+}
+
+impl HeuristicExecuter for CompiledHeuristic {
+    fn create(heuristic: &Heuristic) -> Self {
+        Self::compile(heuristic, None)
+    }
+
+    fn create_with_features(heuristic: &Heuristic, features: Arc<MapFeatures>) -> Self {
+        Self::compile(heuristic, Some(features))
+    }
+
+    fn execute(&self, x1: f32, y1: f32, x2: f32, y2: f32) -> f32 {
+        self.jit().execute(x1, y1, x2, y2)
+    }
+
+    fn execute_batch(&self, x1: &[f32], y1: &[f32], x2: f32, y2: f32, out: &mut [f32]) {
+        self.jit().execute_batch(x1, y1, x2, y2, out)
+    }
+}
+
+impl CompiledHeuristic {
+    fn compile(heuristic: &Heuristic, features: Option<Arc<MapFeatures>>) -> CompiledHeuristic {
+        let mut compiled = Self::compile_population(
+            std::slice::from_ref(heuristic),
+            features,
+            OptimizationLevel::None,
+        );
+        compiled.pop().unwrap()
+    }
+
+    // See `Jit::create_population`. The module is freed once all of the returned
+    // heuristics are dropped.
+    pub fn compile_population(
+        heuristics: &[Heuristic],
+        features: Option<Arc<MapFeatures>>,
+        optimization: OptimizationLevel,
+    ) -> Vec<CompiledHeuristic> {
+        let module = Arc::new(CompiledModule::compile(heuristics, features, optimization));
+        (0..heuristics.len())
+            .map(|index| CompiledHeuristic {
+                module: module.clone(),
+                index,
+            })
+            .collect()
+    }
+
+    fn jit(&self) -> &Jit<'static> {
+        &self.module.jits[self.index]
+    }
+}
+
+// Compiled heuristics keyed by their string form, so that a heuristic surviving
+// into a new generation is not compiled again. Once more than `capacity` are
+// cached, the least recently used are evicted.
+pub struct CompileCache {
+    capacity: usize,
+    features: Option<Arc<MapFeatures>>,
+    optimization: OptimizationLevel,
+    entries: HashMap<String, CompiledHeuristic>,
+    // The keys of `entries` by the time they were last used, oldest first
+    recency: PriorityQueue<String, Reverse<u64>>,
+    time: u64,
+    pub hits: usize,
+    pub misses: usize,
+}
+
+impl CompileCache {
+    pub fn new(
+        capacity: usize,
+        features: Option<Arc<MapFeatures>>,
+        optimization: OptimizationLevel,
+    ) -> CompileCache {
+        CompileCache {
+            capacity,
+            features,
+            optimization,
+            entries: HashMap::new(),
+            recency: PriorityQueue::new(),
+            time: 0,
+            hits: 0,
+            misses: 0,
+        }
+    }
+
+    pub fn get(&mut self, heuristic: &Heuristic) -> CompiledHeuristic {
+        self.get_population(std::slice::from_ref(heuristic))
+            .pop()
+            .unwrap()
+    }
+
+    // Like `get` for each heuristic, with all those not cached compiled together
+    // into a single module
+    pub fn get_population(&mut self, heuristics: &[Heuristic]) -> Vec<CompiledHeuristic> {
+        let keys = heuristics
+            .iter()
+            .map(|heuristic| heuristic.to_string())
+            .collect::<Vec<_>>();
+
+        let mut missing = HashMap::new();
+        for (key, heuristic) in keys.iter().zip(heuristics) {
+            if !self.entries.contains_key(key) {
+                missing.entry(key).or_insert(heuristic);
+            }
+        }
+        let (missing_keys, missing_heuristics): (Vec<_>, Vec<_>) = missing
+            .into_iter()
+            .map(|(key, heuristic)| (key.clone(), heuristic.clone()))
+            .unzip();
+        if !missing_heuristics.is_empty() {
+            let compiled = CompiledHeuristic::compile_population(
+                &missing_heuristics,
+                self.features.clone(),
+                self.optimization,
+            );
+            self.entries.extend(missing_keys.into_iter().zip(compiled));
+        }
+        self.misses += missing_heuristics.len();
+        self.hits += heuristics.len() - missing_heuristics.len();
+
+        let population = keys
+            .into_iter()
+            .map(|key| {
+                let compiled = self.entries[&key].clone();
+                self.time += 1;
+                self.recency.push(key, Reverse(self.time));
+                compiled
+            })
+            .collect();
+
+        while self.entries.len() > self.capacity {
+            let (key, _) = self.recency.pop().unwrap();
+            self.entries.remove(&key);
+        }
+        population
+    }
+
+    pub fn optimization(&self) -> OptimizationLevel {
+        self.optimization
+    }
+
+    pub fn contains(&self, heuristic: &Heuristic) -> bool {
+        self.entries.contains_key(&heuristic.to_string())
+    }
+
+    pub fn len(&self) -> usize {
+        self.entries.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.entries.is_empty()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::heuristic::parser::parse_heuristic;
+
+    use test_case::test_case;
+
+    fn assert_send_sync<T: Send + Sync>() {}
+
+    #[test]
+    fn test_send_sync() {
+        assert_send_sync::<CompiledHeuristic>();
+        assert_send_sync::<CompileCache>();
+    }
+
+    #[test_case("(+ x1 (* y1 (- x2 y2)))", 11.0)]
+    #[test_case("(ifle x1 y1 (neg x2) (sqrt (- y2 x1)))", 0.0)]
+    #[test_case("(defun adf0 2 (- arg0 arg1)) (adf0 x2 (sqr x1))", 8.0)]
+    fn test_execute_on_threads(input: &str, expected: f32) {
+        let compiled = CompiledHeuristic::create(&parse_heuristic(input));
+        let values = std::thread::scope(|scope| {
+            let threads = (0..4)
+                .map(|_| scope.spawn(|| compiled.execute(1.0, 2.0, 9.0, 4.0)))
+                .collect::<Vec<_>>();
+            threads
+                .into_iter()
+                .map(|thread| thread.join().unwrap())
+                .collect::<Vec<_>>()
+        });
+        assert_eq!(values, vec![expected; 4]);
+
+        // The code outlives the handle it was compiled for
+        let clone = compiled.clone();
+        drop(compiled);
+        assert_eq!(clone.execute(1.0, 2.0, 9.0, 4.0), expected);
+    }
+
+    #[test]
+    fn test_cache_hits() {
+        let mut cache = CompileCache::new(4, None, OptimizationLevel::None);
+        let heuristics = ["(+ x1 y1)", "(* x1 y1)", "(+ x1 y1)"]
+            .map(parse_heuristic)
+            .to_vec();
+
+        let compiled = cache.get_population(&heuristics);
+        assert_eq!((cache.hits, cache.misses, cache.len()), (1, 2, 2));
+        assert_eq!(compiled[0].execute(2.0, 3.0, 0.0, 0.0), 5.0);
+        assert_eq!(compiled[1].execute(2.0, 3.0, 0.0, 0.0), 6.0);
+        assert_eq!(compiled[2].execute(2.0, 3.0, 0.0, 0.0), 5.0);
+
+        cache.get(&heuristics[1]);
+        assert_eq!((cache.hits, cache.misses, cache.len()), (2, 2, 2));
+    }
+
+    #[test]
+    fn test_cache_eviction() {
+        let mut cache = CompileCache::new(2, None, OptimizationLevel::None);
+        let (h1, h2, h3) = (
+            parse_heuristic("x1"),
+            parse_heuristic("y1"),
+            parse_heuristic("x2"),
+        );
+
+        cache.get(&h1);
+        cache.get(&h2);
+        cache.get(&h1);
+        // `h2` is now the least recently used
+        let compiled = cache.get(&h3);
+        assert!(cache.contains(&h1) && !cache.contains(&h2) && cache.contains(&h3));
+        assert_eq!(cache.len(), 2);
+        assert_eq!(compiled.execute(0.0, 0.0, 7.0, 0.0), 7.0);
+
+        cache.get(&h2);
+        assert!(!cache.contains(&h1) && cache.contains(&h2) && cache.contains(&h3));
+        assert_eq!(cache.misses, 4);
+    }
+}
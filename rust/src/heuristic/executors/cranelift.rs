@@ -0,0 +1,494 @@
+use std::collections::HashMap;
+use std::f32::consts::SQRT_2;
+use std::sync::Arc;
+
+use cranelift_codegen::ir::{
+    condcodes::FloatCC, types, AbiParam, FuncRef, InstBuilder, MemFlags, Signature, Type, Value,
+};
+use cranelift_frontend::{FunctionBuilder, FunctionBuilderContext};
+use cranelift_jit::{JITBuilder, JITModule};
+use cranelift_module::{default_libcall_names, Linkage, Module};
+
+use crate::heuristic::{
+    executors::{line_obstacles, HeuristicExecuter},
+    parser::{HeuristicNode, Rule},
+    Heuristic,
+};
+use crate::map::features::MapFeatures;
+
+type HeuristicFunc = unsafe extern "C" fn(f32, f32, f32, f32) -> f32;
+
+// Compiles heuristics like the `Jit`, but with Cranelift, which generates less
+// optimized code in a fraction of the time and without linking against LLVM
+pub struct Cranelift {
+    // Only taken when dropped, to free the compiled code
+    module: Option<JITModule>,
+    function: HeuristicFunc,
+    // The compiled function holds raw pointers into these tables
+    _features: Option<Arc<MapFeatures>>,
+}
+
+// The module is never touched after compilation, other than to free its memory
+// once the `Cranelift` is dropped, and the compiled function has no state of its own
+unsafe impl Send for Cranelift {}
+unsafe impl Sync for Cranelift {}
+
+impl Drop for Cranelift {
+    fn drop(&mut self) {
+        if let Some(module) = self.module.take() {
+            unsafe { module.free_memory() };
+        }
+    }
+}
+
+impl HeuristicExecuter for Cranelift {
+    fn create(heuristic: &Heuristic) -> Self {
+        Self::compile(heuristic, None)
+    }
+
+    fn create_with_features(heuristic: &Heuristic, features: Arc<MapFeatures>) -> Self {
+        Self::compile(heuristic, Some(features))
+    }
+
+    fn execute(&self, x1: f32, y1: f32, x2: f32, y2: f32) -> f32 {
+        unsafe { (self.function)(x1, y1, x2, y2) }
+    }
+}
+
+impl Cranelift {
+    fn compile(heuristic: &Heuristic, features: Option<Arc<MapFeatures>>) -> Cranelift {
+        // Operations without a Cranelift instruction call back into the same Rust
+        // functions the interpreter uses, so that they round identically
+        let mut jit_builder =
+            JITBuilder::with_flags(&[("opt_level", "speed")], default_libcall_names()).unwrap();
+        jit_builder.symbol("heuristic_log", log as *const u8);
+        jit_builder.symbol("heuristic_exp", exp as *const u8);
+        jit_builder.symbol("heuristic_pow", pow as *const u8);
+        jit_builder.symbol("line_obstacles", line_obstacles as *const u8);
+        let mut module = JITModule::new(jit_builder);
+
+        let pointer_type = module.target_config().pointer_type();
+        let unary_signature = signature(&module, &[types::F32]);
+        let binary_signature = signature(&module, &[types::F32; 2]);
+        let line_obstacles_signature = signature(
+            &module,
+            &[pointer_type, types::F32, types::F32, types::F32, types::F32],
+        );
+        let mut declare_import = |name: &str, signature: &Signature| {
+            module
+                .declare_function(name, Linkage::Import, signature)
+                .unwrap()
+        };
+        let log_id = declare_import("heuristic_log", &unary_signature);
+        let exp_id = declare_import("heuristic_exp", &unary_signature);
+        let pow_id = declare_import("heuristic_pow", &binary_signature);
+        let line_obstacles_id = declare_import("line_obstacles", &line_obstacles_signature);
+
+        let mut context = module.make_context();
+        context.func.signature = signature(&module, &[types::F32; 4]);
+        let function_id = module
+            .declare_function("execute", Linkage::Local, &context.func.signature)
+            .unwrap();
+
+        let mut function_builder_context = FunctionBuilderContext::new();
+        {
+            let mut builder =
+                FunctionBuilder::new(&mut context.func, &mut function_builder_context);
+            let entry = builder.create_block();
+            builder.append_block_params_for_function_params(entry);
+            builder.switch_to_block(entry);
+            builder.seal_block(entry);
+
+            let coordinates = builder.block_params(entry);
+            let coordinates = [
+                coordinates[0],
+                coordinates[1],
+                coordinates[2],
+                coordinates[3],
+            ];
+            let functions = Functions {
+                log: module.declare_func_in_func(log_id, builder.func),
+                exp: module.declare_func_in_func(exp_id, builder.func),
+                pow: module.declare_func_in_func(pow_id, builder.func),
+                line_obstacles: module.declare_func_in_func(line_obstacles_id, builder.func),
+            };
+
+            let mut recursive_builder = RecursiveBuilder {
+                builder,
+                coordinates,
+                functions,
+                features: features.as_deref(),
+                pointer_type,
+                values: HashMap::new(),
+            };
+            let value = recursive_builder.build_output(&heuristic.inlined());
+            recursive_builder.builder.ins().return_(&[value]);
+            recursive_builder.builder.finalize();
+        }
+
+        module.define_function(function_id, &mut context).unwrap();
+        module.clear_context(&mut context);
+        module.finalize_definitions().unwrap();
+
+        let code = module.get_finalized_function(function_id);
+        Cranelift {
+            module: Some(module),
+            function: unsafe { std::mem::transmute::<*const u8, HeuristicFunc>(code) },
+            _features: features,
+        }
+    }
+}
+
+fn signature(module: &JITModule, params: &[Type]) -> Signature {
+    let mut signature = module.make_signature();
+    signature
+        .params
+        .extend(params.iter().map(|&param| AbiParam::new(param)));
+    signature.returns.push(AbiParam::new(types::F32));
+    signature
+}
+
+extern "C" fn log(value: f32) -> f32 {
+    value.ln()
+}
+
+extern "C" fn exp(value: f32) -> f32 {
+    value.exp()
+}
+
+extern "C" fn pow(base: f32, exponent: f32) -> f32 {
+    base.powf(exponent)
+}
+
+// The imported functions, as referenced from the function being built
+struct Functions {
+    log: FuncRef,
+    exp: FuncRef,
+    pow: FuncRef,
+    line_obstacles: FuncRef,
+}
+
+// Lowers a heuristic the way the `Jit`'s `RecursiveBuilder` does
+struct RecursiveBuilder<'a> {
+    builder: FunctionBuilder<'a>,
+    // x1, y1, x2 and y2
+    coordinates: [Value; 4],
+    functions: Functions,
+    features: Option<&'a MapFeatures>,
+    pointer_type: Type,
+    // The values emitted so far, keyed by the subtree that computes them
+    values: HashMap<String, Value>,
+}
+
+impl<'a> RecursiveBuilder<'a> {
+    // The value of the heuristic, as reported by `clamp_heuristic_value`
+    fn build_output(&mut self, node: &HeuristicNode) -> Value {
+        let value = self.build(node);
+        let zero = self.builder.ins().f32const(0.0);
+        let max = self.builder.ins().f32const(f32::MAX);
+
+        let is_nan = self.builder.ins().fcmp(FloatCC::Unordered, value, value);
+        let is_positive = self.builder.ins().fcmp(FloatCC::GreaterThan, value, zero);
+        let finite = self.build_min(value, max);
+        let clamped = self.builder.ins().select(is_positive, finite, zero);
+        self.builder.ins().select(is_nan, max, clamped)
+    }
+
+    // Structurally identical subtrees are emitted once and their value reused,
+    // which is valid as the function is a single basic block
+    fn build(&mut self, node: &HeuristicNode) -> Value {
+        let key = node.to_string();
+        if let Some(value) = self.values.get(&key) {
+            return *value;
+        }
+
+        let value = match node {
+            HeuristicNode::Number(num) => self.builder.ins().f32const(*num),
+            HeuristicNode::Terminal(rule) => self.build_terminal(*rule),
+            HeuristicNode::Unary(rule, h) => self.build_unary(*rule, h),
+            HeuristicNode::Binary(rule, h1, h2) => self.build_binary(*rule, h1, h2),
+            HeuristicNode::Conditional(rule, h1, h2, h3, h4) => {
+                self.build_conditional(*rule, h1, h2, h3, h4)
+            }
+            HeuristicNode::Call(..) | HeuristicNode::Argument(_) => {
+                unreachable!("ADF calls are inlined before building")
+            }
+        };
+        self.values.insert(key, value);
+        value
+    }
+
+    fn build_terminal(&mut self, rule: Rule) -> Value {
+        let [x1, y1, x2, y2] = self.coordinates;
+        match rule {
+            Rule::x1 => x1,
+            Rule::y1 => y1,
+            Rule::x2 => x2,
+            Rule::y2 => y2,
+            Rule::deltaX => {
+                let deltax = self.builder.ins().fsub(x2, x1);
+                self.builder.ins().fabs(deltax)
+            }
+            Rule::deltaY => {
+                let deltay = self.builder.ins().fsub(y2, y1);
+                self.builder.ins().fabs(deltay)
+            }
+            Rule::manhattan => {
+                let deltax = self.build_terminal(Rule::deltaX);
+                let deltay = self.build_terminal(Rule::deltaY);
+                self.builder.ins().fadd(deltax, deltay)
+            }
+            Rule::euclid => {
+                let deltax = self.build_terminal(Rule::deltaX);
+                let deltay = self.build_terminal(Rule::deltaY);
+                let deltax2 = self.builder.ins().fmul(deltax, deltax);
+                let deltay2 = self.builder.ins().fmul(deltay, deltay);
+                let sum = self.builder.ins().fadd(deltax2, deltay2);
+                self.builder.ins().sqrt(sum)
+            }
+            Rule::octile => {
+                let deltax = self.build_terminal(Rule::deltaX);
+                let deltay = self.build_terminal(Rule::deltaY);
+                let max = self.build_max(deltax, deltay);
+                let min = self.build_min(deltax, deltay);
+                let factor = self.builder.ins().f32const(SQRT_2 - 1.0);
+                let diagonal = self.builder.ins().fmul(factor, min);
+                self.builder.ins().fadd(max, diagonal)
+            }
+            Rule::cheby => {
+                let deltax = self.build_terminal(Rule::deltaX);
+                let deltay = self.build_terminal(Rule::deltaY);
+                self.build_max(deltax, deltay)
+            }
+            Rule::lineObstacles => {
+                let features = self.feature_tables() as *const MapFeatures as usize;
+                let features = self.build_pointer(features);
+                let call = self
+                    .builder
+                    .ins()
+                    .call(self.functions.line_obstacles, &[features, x1, y1, x2, y2]);
+                self.builder.inst_results(call)[0]
+            }
+            Rule::wallDist => {
+                let tables = self.feature_tables();
+                let cell = self.build_cell_index(x1, y1);
+                self.build_table_load(&tables.wall_distances, cell)
+            }
+            Rule::sameRoom => {
+                let tables = self.feature_tables();
+                let cell1 = self.build_cell_index(x1, y1);
+                let cell2 = self.build_cell_index(x2, y2);
+                let room1 = self.build_table_load(&tables.rooms, cell1);
+                let room2 = self.build_table_load(&tables.rooms, cell2);
+                let same = self.builder.ins().fcmp(FloatCC::Equal, room1, room2);
+                let one = self.builder.ins().f32const(1.0);
+                let zero = self.builder.ins().f32const(0.0);
+                self.builder.ins().select(same, one, zero)
+            }
+            Rule::rowDensity => {
+                let tables = self.feature_tables();
+                let row = self.build_clamped_index(x1, tables.n);
+                self.build_table_load(&tables.row_densities, row)
+            }
+            Rule::colDensity => {
+                let tables = self.feature_tables();
+                let column = self.build_clamped_index(y1, tables.m);
+                self.build_table_load(&tables.column_densities, column)
+            }
+            _ => {
+                unreachable!("{:?}", rule);
+            }
+        }
+    }
+
+    fn feature_tables(&self) -> &'a MapFeatures {
+        self.features
+            .expect("Map feature terminals need a Cranelift created with feature tables")
+    }
+
+    fn build_pointer(&mut self, address: usize) -> Value {
+        self.builder.ins().iconst(self.pointer_type, address as i64)
+    }
+
+    // Truncates a coordinate to an index into a table of `len` entries, clamping
+    // it like `MapFeatures` does
+    fn build_clamped_index(&mut self, value: Value, len: usize) -> Value {
+        let zero = self.builder.ins().f32const(0.0);
+        let last = self.builder.ins().f32const((len - 1) as f32);
+        let clamped = self.build_max(value, zero);
+        let clamped = self.build_min(clamped, last);
+        self.builder
+            .ins()
+            .fcvt_to_sint_sat(self.pointer_type, clamped)
+    }
+
+    fn build_cell_index(&mut self, x: Value, y: Value) -> Value {
+        let tables = self.feature_tables();
+        let row = self.build_clamped_index(x, tables.n);
+        let column = self.build_clamped_index(y, tables.m);
+        let offset = self.builder.ins().imul_imm(row, tables.m as i64);
+        self.builder.ins().iadd(offset, column)
+    }
+
+    fn build_table_load(&mut self, table: &[f32], index: Value) -> Value {
+        let table = self.build_pointer(table.as_ptr() as usize);
+        let offset = self.builder.ins().imul_imm(index, 4);
+        let entry = self.builder.ins().iadd(table, offset);
+        self.builder
+            .ins()
+            .load(types::F32, MemFlags::trusted().with_readonly(), entry, 0)
+    }
+
+    fn build_call(&mut self, function: FuncRef, args: &[Value]) -> Value {
+        let call = self.builder.ins().call(function, args);
+        self.builder.inst_results(call)[0]
+    }
+
+    // Cranelift's `fmax` and `fmin` propagate NaN, while `f32::max` and `llvm.maxnum`
+    // return the other operand, and the first one on ties such as 0 and -0
+    fn build_max(&mut self, value1: Value, value2: Value) -> Value {
+        let is_nan = self.builder.ins().fcmp(FloatCC::Unordered, value1, value1);
+        let greater = self
+            .builder
+            .ins()
+            .fcmp(FloatCC::GreaterThan, value2, value1);
+        let max = self.builder.ins().select(greater, value2, value1);
+        self.builder.ins().select(is_nan, value2, max)
+    }
+
+    fn build_min(&mut self, value1: Value, value2: Value) -> Value {
+        let is_nan = self.builder.ins().fcmp(FloatCC::Unordered, value1, value1);
+        let less = self.builder.ins().fcmp(FloatCC::LessThan, value2, value1);
+        let min = self.builder.ins().select(less, value2, value1);
+        self.builder.ins().select(is_nan, value2, min)
+    }
+
+    fn build_unary(&mut self, rule: Rule, h: &HeuristicNode) -> Value {
+        let result = self.build(h);
+        match rule {
+            Rule::neg => self.builder.ins().fneg(result),
+            Rule::abs => self.builder.ins().fabs(result),
+            Rule::sqrt => {
+                let abs = self.builder.ins().fabs(result);
+                let sqrt = self.builder.ins().sqrt(abs);
+                self.builder.ins().fcopysign(sqrt, result)
+            }
+            Rule::sqr => self.builder.ins().fmul(result, result),
+            Rule::log => {
+                let abs = self.builder.ins().fabs(result);
+                self.build_call(self.functions.log, &[abs])
+            }
+            Rule::exp => self.build_call(self.functions.exp, &[result]),
+            Rule::floor => self.builder.ins().floor(result),
+            Rule::ceil => self.builder.ins().ceil(result),
+            Rule::recip => {
+                let one = self.builder.ins().f32const(1.0);
+                self.builder.ins().fdiv(one, result)
+            }
+            _ => {
+                unreachable!("{:?}", rule);
+            }
+        }
+    }
+
+    fn build_binary(&mut self, rule: Rule, h1: &HeuristicNode, h2: &HeuristicNode) -> Value {
+        let result1 = self.build(h1);
+        let result2 = self.build(h2);
+        match rule {
+            Rule::plus => self.builder.ins().fadd(result1, result2),
+            Rule::minus => self.builder.ins().fsub(result1, result2),
+            Rule::mul => self.builder.ins().fmul(result1, result2),
+            Rule::div => self.builder.ins().fdiv(result1, result2),
+            Rule::max => self.build_max(result1, result2),
+            Rule::min => self.build_min(result1, result2),
+            Rule::pow => {
+                let abs = self.builder.ins().fabs(result1);
+                self.build_call(self.functions.pow, &[abs, result2])
+            }
+            _ => {
+                unreachable!("{:?}", rule);
+            }
+        }
+    }
+
+    // Both branches are computed, which is safe as heuristics have no side effects
+    fn build_conditional(
+        &mut self,
+        rule: Rule,
+        h1: &HeuristicNode,
+        h2: &HeuristicNode,
+        h3: &HeuristicNode,
+        h4: &HeuristicNode,
+    ) -> Value {
+        let (result1, result2) = (self.build(h1), self.build(h2));
+        let (result3, result4) = (self.build(h3), self.build(h4));
+        let condition = match rule {
+            // Ordered, so that NaN takes the else branch like in the interpreter
+            Rule::ifle => FloatCC::LessThanOrEqual,
+            _ => {
+                unreachable!("{:?}", rule);
+            }
+        };
+        let condition = self.builder.ins().fcmp(condition, result1, result2);
+        self.builder.ins().select(condition, result3, result4)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::heuristic::executors::interpreter::Interpreter;
+    use crate::heuristic::mutate_probs::TermProbabilities;
+    use crate::heuristic::parser::parse_heuristic;
+    use crate::heuristic::util::random_heuristic;
+    use crate::map::util::{Map, Tile};
+
+    use test_case::test_case;
+
+    #[test_case("(+ x1 (* y1 (- x2 y2)))", 11.0)]
+    #[test_case("(ifle x1 y1 (neg x2) (sqrt (- y2 x1)))", 0.0)]
+    #[test_case("(max (log (- x1 x1)) (pow -2 y1))", 4.0)]
+    #[test_case("(defun adf0 2 (- arg0 arg1)) (adf0 x2 (sqr x1))", 8.0)]
+    fn test_execute(input: &str, expected: f32) {
+        let cranelift = Cranelift::create(&parse_heuristic(input));
+        assert_eq!(cranelift.execute(1.0, 2.0, 9.0, 4.0), expected);
+    }
+
+    #[test]
+    fn test_execute_on_threads() {
+        let cranelift = Cranelift::create(&parse_heuristic("(/ manhattan x1)"));
+        std::thread::scope(|scope| {
+            for x1 in 1..=4 {
+                let cranelift = &cranelift;
+                scope.spawn(move || {
+                    let value = cranelift.execute(x1 as f32, 0.0, 0.0, 0.0);
+                    assert_eq!(value, 1.0);
+                });
+            }
+        });
+    }
+
+    #[test]
+    fn test_agrees_with_interpreter() {
+        let map = Map::from(6, 7, vec![Tile::Passable; 6 * 7]);
+
+        let term_probs = Some(TermProbabilities::new(true).with_feature_terminals());
+        for _ in 0..200 {
+            let heuristic = Heuristic::new(random_heuristic(fastrand::i32(1..=30), &term_probs));
+            let interpreter = Interpreter::create_with_features(&heuristic, map.features.clone());
+            let cranelift = Cranelift::create_with_features(&heuristic, map.features.clone());
+
+            for _ in 0..50 {
+                let (x1, x2) = (fastrand::usize(0..6) as f32, fastrand::usize(0..6) as f32);
+                let (y1, y2) = (fastrand::usize(0..7) as f32, fastrand::usize(0..7) as f32);
+                assert_eq!(
+                    interpreter.execute(x1, y1, x2, y2).to_bits(),
+                    cranelift.execute(x1, y1, x2, y2).to_bits(),
+                    "{} at {:?}",
+                    heuristic,
+                    (x1, y1, x2, y2)
+                );
+            }
+        }
+    }
+}
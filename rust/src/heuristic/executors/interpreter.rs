@@ -1,87 +1,149 @@
+use std::f32::consts::SQRT_2;
+use std::sync::Arc;
+
 use crate::heuristic::{
-    executors::HeuristicExecuter,
+    executors::{clamp_heuristic_value, HeuristicExecuter},
     parser::{HeuristicNode, Rule},
     Heuristic,
 };
+use crate::map::features::MapFeatures;
 
 pub struct Interpreter {
     node: HeuristicNode,
+    features: Option<Arc<MapFeatures>>,
 }
 
 impl HeuristicExecuter for Interpreter {
     fn create(heuristic: &Heuristic) -> Self {
         Interpreter {
-            node: heuristic.root.clone(),
+            node: heuristic.inlined(),
+            features: None,
         }
     }
 
-    fn execute(&self, x1: f32, y1: f32, x2: f32, y2: f32) -> f32 {
-        let executor = RecursiveExecutor { x1, y1, x2, y2 };
-        let val = executor.evaluate_node(&self.node);
-        match val.is_nan() {
-            true => f32::MAX,
-            false => val,
+    fn create_with_features(heuristic: &Heuristic, features: Arc<MapFeatures>) -> Self {
+        Interpreter {
+            node: heuristic.inlined(),
+            features: Some(features),
         }
     }
+
+    fn execute(&self, x1: f32, y1: f32, x2: f32, y2: f32) -> f32 {
+        let executor = RecursiveExecutor {
+            x1,
+            y1,
+            x2,
+            y2,
+            features: self.features.as_deref(),
+        };
+        clamp_heuristic_value(executor.evaluate_node(&self.node))
+    }
 }
 
-struct RecursiveExecutor {
+struct RecursiveExecutor<'a> {
     x1: f32,
     y1: f32,
     x2: f32,
     y2: f32,
+    features: Option<&'a MapFeatures>,
 }
 
-impl RecursiveExecutor {
+impl RecursiveExecutor<'_> {
     fn evaluate_node(&self, node: &HeuristicNode) -> f32 {
         match node {
-            HeuristicNode::Number(num) => *num as f32,
-            HeuristicNode::Terminal(rule) => self.evaluate_terminal(*rule),
-            HeuristicNode::Unary(rule, h) => self.evaluate_unary(*rule, h),
-            HeuristicNode::Binary(rule, h1, h2) => self.evaluate_binary(*rule, h1, h2),
+            HeuristicNode::Number(num) => *num,
+            HeuristicNode::Terminal(rule) => {
+                evaluate_terminal(*rule, (self.x1, self.y1, self.x2, self.y2), self.features)
+            }
+            HeuristicNode::Unary(rule, h) => evaluate_unary(*rule, self.evaluate_node(h)),
+            HeuristicNode::Binary(rule, h1, h2) => {
+                evaluate_binary(*rule, self.evaluate_node(h1), self.evaluate_node(h2))
+            }
+            // Only the branch taken is evaluated
+            HeuristicNode::Conditional(rule, h1, h2, h3, h4) => {
+                match evaluate_condition(*rule, self.evaluate_node(h1), self.evaluate_node(h2)) {
+                    true => self.evaluate_node(h3),
+                    false => self.evaluate_node(h4),
+                }
+            }
+            HeuristicNode::Call(..) | HeuristicNode::Argument(_) => {
+                unreachable!("ADF calls are inlined on creation")
+            }
+        }
+    }
+}
+
+// The semantics of the individual operations, shared with the `Vm`
+
+pub fn evaluate_terminal(
+    rule: Rule,
+    (x1, y1, x2, y2): (f32, f32, f32, f32),
+    features: Option<&MapFeatures>,
+) -> f32 {
+    let features =
+        || features.expect("Map feature terminals need an executer created with feature tables");
+    let (dx, dy) = ((x2 - x1).abs(), (y2 - y1).abs());
+
+    match rule {
+        Rule::x1 => x1,
+        Rule::y1 => y1,
+        Rule::x2 => x2,
+        Rule::y2 => y2,
+        Rule::deltaX => dx,
+        Rule::deltaY => dy,
+        Rule::manhattan => dx + dy,
+        Rule::euclid => (dx * dx + dy * dy).sqrt(),
+        Rule::octile => dx.max(dy) + (SQRT_2 - 1.0) * dx.min(dy),
+        Rule::cheby => dx.max(dy),
+        Rule::lineObstacles => features().line_obstacles(x1, y1, x2, y2),
+        Rule::wallDist => features().wall_distance(x1, y1),
+        Rule::sameRoom => features().same_room(x1, y1, x2, y2),
+        Rule::rowDensity => features().row_density(x1),
+        Rule::colDensity => features().column_density(y1),
+        _ => {
+            unreachable!("{:?}", rule);
         }
     }
+}
 
-    fn evaluate_terminal(&self, rule: Rule) -> f32 {
-        match rule {
-            Rule::x1 => self.x1,
-            Rule::y1 => self.y1,
-            Rule::x2 => self.x2,
-            Rule::y2 => self.y2,
-            Rule::deltaX => (self.x2 - self.x1).abs(),
-            Rule::deltaY => (self.y2 - self.y1).abs(),
-            _ => {
-                unreachable!("{:?}", rule);
-            }
+pub fn evaluate_unary(rule: Rule, value: f32) -> f32 {
+    match rule {
+        Rule::neg => -value,
+        Rule::abs => value.abs(),
+        Rule::sqrt => value.signum() * value.abs().sqrt(),
+        Rule::sqr => value * value,
+        Rule::log => value.abs().ln(),
+        Rule::exp => value.exp(),
+        Rule::floor => value.floor(),
+        Rule::ceil => value.ceil(),
+        Rule::recip => 1.0 / value,
+        _ => {
+            unreachable!("{:?}", rule);
         }
     }
+}
 
-    fn evaluate_unary(&self, rule: Rule, h: &HeuristicNode) -> f32 {
-        let result = self.evaluate_node(h);
-        match rule {
-            Rule::neg => -result,
-            Rule::abs => result.abs(),
-            Rule::sqrt => result.signum() * result.abs().sqrt(),
-            Rule::sqr => result * result,
-            _ => {
-                unreachable!("{:?}", rule);
-            }
+pub fn evaluate_binary(rule: Rule, value1: f32, value2: f32) -> f32 {
+    match rule {
+        Rule::plus => value1 + value2,
+        Rule::minus => value1 - value2,
+        Rule::mul => value1 * value2,
+        Rule::div => value1 / value2,
+        Rule::max => value1.max(value2),
+        Rule::min => value1.min(value2),
+        Rule::pow => value1.abs().powf(value2),
+        _ => {
+            unreachable!("{:?}", rule);
         }
     }
+}
 
-    fn evaluate_binary(&self, rule: Rule, h1: &HeuristicNode, h2: &HeuristicNode) -> f32 {
-        let result1 = self.evaluate_node(h1);
-        let result2 = self.evaluate_node(h2);
-        match rule {
-            Rule::plus => result1 + result2,
-            Rule::minus => result1 - result2,
-            Rule::mul => result1 * result2,
-            Rule::div => result1 / result2,
-            Rule::max => result1.max(result2),
-            Rule::min => result1.min(result2),
-            _ => {
-                unreachable!("{:?}", rule);
-            }
+// Whether a conditional takes its first branch
+pub fn evaluate_condition(rule: Rule, value1: f32, value2: f32) -> bool {
+    match rule {
+        Rule::ifle => value1 <= value2,
+        _ => {
+            unreachable!("{:?}", rule);
         }
     }
 }
@@ -1,60 +1,88 @@
 #![allow(clippy::too_many_arguments)]
 
+use std::cell::RefCell;
+use std::collections::HashMap;
+use std::f32::consts::SQRT_2;
+use std::sync::Arc;
+
 use inkwell::intrinsics::Intrinsic;
+use inkwell::module::Linkage;
+use inkwell::types::{IntType, PointerType};
+use inkwell::values::{IntValue, PointerValue};
 use inkwell::{
     builder::Builder, context::Context, execution_engine::JitFunction, types::FloatType,
-    values::FloatValue, values::FunctionValue, OptimizationLevel,
+    values::FloatValue, values::FunctionValue, AddressSpace, FloatPredicate, IntPredicate,
+    OptimizationLevel,
 };
 
 use crate::heuristic::{
+    executors::{assert_batch_lengths, line_obstacles},
     parser::{HeuristicNode, Rule},
     Heuristic,
 };
+use crate::map::features::MapFeatures;
 
 type HeuristicFunc = unsafe extern "C" fn(f32, f32, f32, f32) -> f32;
+// Takes the `x1` and `y1` arrays, the goal, the output array and their length
+type BatchHeuristicFunc = unsafe extern "C" fn(*const f32, *const f32, f32, f32, *mut f32, u64);
 
 pub struct Jit<'a> {
     // context: Context,
     // module: Module<'a>,
     function: JitFunction<'a, HeuristicFunc>,
+    batch_function: JitFunction<'a, BatchHeuristicFunc>,
+    // The compiled function holds raw pointers into these tables
+    _features: Option<Arc<MapFeatures>>,
 }
 
 // pre-initialize other LLVM steps? (first profile)
 
 impl<'a> Jit<'a> {
+    // Heuristics using map feature terminals must be created with `create_with_features`
     pub fn create(heuristic: &Heuristic, context: &'a Context) -> Self {
+        Self::compile(heuristic, context, None)
+    }
+
+    pub fn create_with_features(
+        heuristic: &Heuristic,
+        context: &'a Context,
+        features: Arc<MapFeatures>,
+    ) -> Self {
+        Self::compile(heuristic, context, Some(features))
+    }
+
+    fn compile(
+        heuristic: &Heuristic,
+        context: &'a Context,
+        features: Option<Arc<MapFeatures>>,
+    ) -> Self {
+        let mut jits = Self::create_population(
+            std::slice::from_ref(heuristic),
+            context,
+            features,
+            OptimizationLevel::None,
+        );
+        jits.pop().unwrap()
+    }
+
+    // Compiles every heuristic into its own function of a single module, so that a
+    // whole generation pays for one execution engine. The returned handles are in
+    // the order of `heuristics`, and keep the engine alive between them.
+    pub fn create_population(
+        heuristics: &[Heuristic],
+        context: &'a Context,
+        features: Option<Arc<MapFeatures>>,
+        optimization: OptimizationLevel,
+    ) -> Vec<Self> {
         // let context = context::Context::create();
         // let mut module: Module;
-        let module = context.create_module("heuristic");
+        let module = context.create_module("population");
 
         let builder = context.create_builder();
 
-        // Less is just as good as Aggressive, but 3x slower to compile than None (about 50% faster runtime performance)
-        let execution_engine = module
-            // .create_jit_execution_engine(OptimizationLevel::Less)
-            .create_jit_execution_engine(OptimizationLevel::None)
-            .unwrap();
+        let execution_engine = module.create_jit_execution_engine(optimization).unwrap();
 
         let f32_type = context.f32_type();
-        let fn_type = f32_type.fn_type(
-            &[
-                f32_type.into(),
-                f32_type.into(),
-                f32_type.into(),
-                f32_type.into(),
-            ],
-            false,
-        );
-
-        let function = module.add_function("execute", fn_type, None);
-        let basic_block = context.append_basic_block(function, "entry");
-
-        builder.position_at_end(basic_block);
-
-        let x1 = function.get_nth_param(0).unwrap().into_float_value();
-        let y1 = function.get_nth_param(1).unwrap().into_float_value();
-        let x2 = function.get_nth_param(2).unwrap().into_float_value();
-        let y2 = function.get_nth_param(3).unwrap().into_float_value();
 
         let abs_intrinsic = Intrinsic::find("llvm.fabs.f32").unwrap();
         assert!(abs_intrinsic.get_declaration(&module, &[]).is_none());
@@ -95,31 +123,206 @@ impl<'a> Jit<'a> {
             )
             .unwrap();
 
+        let log_intrinsic = Intrinsic::find("llvm.log.f32").unwrap();
+        assert!(log_intrinsic.get_declaration(&module, &[]).is_none());
+        let log_fn = log_intrinsic
+            .get_declaration(&module, &[context.f32_type().into()])
+            .unwrap();
+
+        let exp_intrinsic = Intrinsic::find("llvm.exp.f32").unwrap();
+        assert!(exp_intrinsic.get_declaration(&module, &[]).is_none());
+        let exp_fn = exp_intrinsic
+            .get_declaration(&module, &[context.f32_type().into()])
+            .unwrap();
+
+        let floor_intrinsic = Intrinsic::find("llvm.floor.f32").unwrap();
+        assert!(floor_intrinsic.get_declaration(&module, &[]).is_none());
+        let floor_fn = floor_intrinsic
+            .get_declaration(&module, &[context.f32_type().into()])
+            .unwrap();
+
+        let ceil_intrinsic = Intrinsic::find("llvm.ceil.f32").unwrap();
+        assert!(ceil_intrinsic.get_declaration(&module, &[]).is_none());
+        let ceil_fn = ceil_intrinsic
+            .get_declaration(&module, &[context.f32_type().into()])
+            .unwrap();
+
+        let pow_intrinsic = Intrinsic::find("llvm.pow.f32").unwrap();
+        assert!(pow_intrinsic.get_declaration(&module, &[]).is_none());
+        let pow_fn = pow_intrinsic
+            .get_declaration(
+                &module,
+                &[context.f32_type().into(), context.f32_type().into()],
+            )
+            .unwrap();
+
         {
-            let recursive_builder = RecursiveBuilder::new(
-                // context,
-                //  &module,
-                &builder,
-                f32_type,
-                &abs_fn,
-                &copysign_fn,
-                &sqrt_fn,
-                &min_fn,
-                &max_fn,
-                &x1,
-                &y1,
-                &x2,
-                &y2,
+            // The tables are baked into the function as constant pointers, while
+            // lines are walked by calling back into `MapFeatures::line_obstacles`
+            let feature_tables = features.as_deref().map(|features| {
+                let ptr_type = context.ptr_type(AddressSpace::default());
+                let line_obstacles_fn = module.add_function(
+                    "line_obstacles",
+                    f32_type.fn_type(
+                        &[
+                            ptr_type.into(),
+                            f32_type.into(),
+                            f32_type.into(),
+                            f32_type.into(),
+                            f32_type.into(),
+                        ],
+                        false,
+                    ),
+                    Some(Linkage::External),
+                );
+                execution_engine
+                    .add_global_mapping(&line_obstacles_fn, line_obstacles as *const () as usize);
+
+                FeatureTables {
+                    features,
+                    line_obstacles_fn,
+                    i64_type: context.i64_type(),
+                    ptr_type,
+                }
+            });
+
+            let fn_type = f32_type.fn_type(
+                &[
+                    f32_type.into(),
+                    f32_type.into(),
+                    f32_type.into(),
+                    f32_type.into(),
+                ],
+                false,
             );
-            let return_value = recursive_builder.build(&heuristic.root);
-            let _ = builder.build_return(Some(&return_value));
-        }
 
-        Jit {
-            // context,
-            // module,
-            function: unsafe { execution_engine.get_function("execute") }.unwrap(),
+            let ptr_type = context.ptr_type(AddressSpace::default());
+            let i64_type = context.i64_type();
+            let batch_fn_type = context.void_type().fn_type(
+                &[
+                    ptr_type.into(),
+                    ptr_type.into(),
+                    f32_type.into(),
+                    f32_type.into(),
+                    ptr_type.into(),
+                    i64_type.into(),
+                ],
+                false,
+            );
+
+            for (index, heuristic) in heuristics.iter().enumerate() {
+                let function = module.add_function(&format!("execute{}", index), fn_type, None);
+                let basic_block = context.append_basic_block(function, "entry");
+
+                builder.position_at_end(basic_block);
+
+                let x1 = function.get_nth_param(0).unwrap().into_float_value();
+                let y1 = function.get_nth_param(1).unwrap().into_float_value();
+                let x2 = function.get_nth_param(2).unwrap().into_float_value();
+                let y2 = function.get_nth_param(3).unwrap().into_float_value();
+
+                let recursive_builder = RecursiveBuilder::new(
+                    // context,
+                    //  &module,
+                    &builder,
+                    f32_type,
+                    &abs_fn,
+                    &copysign_fn,
+                    &sqrt_fn,
+                    &min_fn,
+                    &max_fn,
+                    &log_fn,
+                    &exp_fn,
+                    &floor_fn,
+                    &ceil_fn,
+                    &pow_fn,
+                    &x1,
+                    &y1,
+                    &x2,
+                    &y2,
+                    feature_tables.as_ref(),
+                );
+                let node = heuristic.inlined();
+                let return_value = recursive_builder.build_output(&node);
+                let _ = builder.build_return(Some(&return_value));
+
+                // The heuristic is emitted again in the body of a loop over the points,
+                // rather than called, so that nothing is paid per point but the loads
+                // and the store
+                let batch_function =
+                    module.add_function(&format!("execute_batch{}", index), batch_fn_type, None);
+                let entry = context.append_basic_block(batch_function, "entry");
+                let body = context.append_basic_block(batch_function, "body");
+                let exit = context.append_basic_block(batch_function, "exit");
+
+                let x1s = batch_function
+                    .get_nth_param(0)
+                    .unwrap()
+                    .into_pointer_value();
+                let y1s = batch_function
+                    .get_nth_param(1)
+                    .unwrap()
+                    .into_pointer_value();
+                let batch_x2 = batch_function.get_nth_param(2).unwrap().into_float_value();
+                let batch_y2 = batch_function.get_nth_param(3).unwrap().into_float_value();
+                let out = batch_function
+                    .get_nth_param(4)
+                    .unwrap()
+                    .into_pointer_value();
+                let len = batch_function.get_nth_param(5).unwrap().into_int_value();
+
+                builder.position_at_end(entry);
+                let empty = builder
+                    .build_int_compare(IntPredicate::EQ, len, i64_type.const_zero(), "empty")
+                    .unwrap();
+                let _ = builder.build_conditional_branch(empty, exit, body);
+
+                builder.position_at_end(body);
+                let counter = builder.build_phi(i64_type, "counter").unwrap();
+                let i = counter.as_basic_value().into_int_value();
+                let element = |array, name| unsafe {
+                    builder.build_gep(f32_type, array, &[i], name).unwrap()
+                };
+                let batch_x1 = builder
+                    .build_load(f32_type, element(x1s, "x1"), "x1")
+                    .unwrap()
+                    .into_float_value();
+                let batch_y1 = builder
+                    .build_load(f32_type, element(y1s, "y1"), "y1")
+                    .unwrap()
+                    .into_float_value();
+                let value = recursive_builder
+                    .with_coordinates(&batch_x1, &batch_y1, &batch_x2, &batch_y2)
+                    .build_output(&node);
+                let _ = builder.build_store(element(out, "out"), value);
+
+                let next = builder
+                    .build_int_add(i, i64_type.const_int(1, false), "next")
+                    .unwrap();
+                counter.add_incoming(&[(&i64_type.const_zero(), entry), (&next, body)]);
+                let done = builder
+                    .build_int_compare(IntPredicate::EQ, next, len, "done")
+                    .unwrap();
+                let _ = builder.build_conditional_branch(done, exit, body);
+
+                builder.position_at_end(exit);
+                let _ = builder.build_return(None);
+            }
         }
+
+        (0..heuristics.len())
+            .map(|index| Jit {
+                // context,
+                // module,
+                function: unsafe { execution_engine.get_function(&format!("execute{}", index)) }
+                    .unwrap(),
+                batch_function: unsafe {
+                    execution_engine.get_function(&format!("execute_batch{}", index))
+                }
+                .unwrap(),
+                _features: features.clone(),
+            })
+            .collect()
     }
 
     pub fn execute(&self, x1: f32, y1: f32, x2: f32, y2: f32) -> f32 {
@@ -129,6 +332,44 @@ impl<'a> Jit<'a> {
     pub fn get_raw(&self) -> HeuristicFunc {
         unsafe { self.function.as_raw() }
     }
+
+    // See `HeuristicExecuter::execute_batch`
+    pub fn execute_batch(&self, x1: &[f32], y1: &[f32], x2: f32, y2: f32, out: &mut [f32]) {
+        execute_raw_batch(self.get_raw_batch(), x1, y1, x2, y2, out);
+    }
+
+    pub fn get_raw_batch(&self) -> BatchHeuristicFunc {
+        unsafe { self.batch_function.as_raw() }
+    }
+}
+
+// Calls a function from `Jit::get_raw_batch`, which must not outlive its `Jit`
+pub fn execute_raw_batch(
+    function: BatchHeuristicFunc,
+    x1: &[f32],
+    y1: &[f32],
+    x2: f32,
+    y2: f32,
+    out: &mut [f32],
+) {
+    assert_batch_lengths(x1, y1, out);
+    unsafe {
+        function(
+            x1.as_ptr(),
+            y1.as_ptr(),
+            x2,
+            y2,
+            out.as_mut_ptr(),
+            out.len() as u64,
+        )
+    }
+}
+
+struct FeatureTables<'a> {
+    features: &'a MapFeatures,
+    line_obstacles_fn: FunctionValue<'a>,
+    i64_type: IntType<'a>,
+    ptr_type: PointerType<'a>,
 }
 
 struct RecursiveBuilder<'a> {
@@ -141,10 +382,18 @@ struct RecursiveBuilder<'a> {
     sqrt_fn: &'a FunctionValue<'a>,
     min_fn: &'a FunctionValue<'a>,
     max_fn: &'a FunctionValue<'a>,
+    log_fn: &'a FunctionValue<'a>,
+    exp_fn: &'a FunctionValue<'a>,
+    floor_fn: &'a FunctionValue<'a>,
+    ceil_fn: &'a FunctionValue<'a>,
+    pow_fn: &'a FunctionValue<'a>,
     x1: &'a FloatValue<'a>,
     y1: &'a FloatValue<'a>,
     x2: &'a FloatValue<'a>,
     y2: &'a FloatValue<'a>,
+    feature_tables: Option<&'a FeatureTables<'a>>,
+    // The values emitted so far, keyed by the subtree that computes them
+    values: RefCell<HashMap<String, FloatValue<'a>>>,
 }
 
 impl<'a> RecursiveBuilder<'a> {
@@ -158,10 +407,16 @@ impl<'a> RecursiveBuilder<'a> {
         sqrt_fn: &'a FunctionValue<'a>,
         min_fn: &'a FunctionValue<'a>,
         max_fn: &'a FunctionValue<'a>,
+        log_fn: &'a FunctionValue<'a>,
+        exp_fn: &'a FunctionValue<'a>,
+        floor_fn: &'a FunctionValue<'a>,
+        ceil_fn: &'a FunctionValue<'a>,
+        pow_fn: &'a FunctionValue<'a>,
         x1: &'a FloatValue<'a>,
         y1: &'a FloatValue<'a>,
         x2: &'a FloatValue<'a>,
         y2: &'a FloatValue<'a>,
+        feature_tables: Option<&'a FeatureTables<'a>>,
     ) -> Self {
         RecursiveBuilder {
             // context,
@@ -173,23 +428,90 @@ impl<'a> RecursiveBuilder<'a> {
             sqrt_fn,
             min_fn,
             max_fn,
+            log_fn,
+            exp_fn,
+            floor_fn,
+            ceil_fn,
+            pow_fn,
+            x1,
+            y1,
+            x2,
+            y2,
+            feature_tables,
+            values: RefCell::new(HashMap::new()),
+        }
+    }
+
+    // A builder emitting into the same function and module, but reading the given
+    // coordinates, e.g. those of one point of a batch
+    pub fn with_coordinates(
+        &self,
+        x1: &'a FloatValue<'a>,
+        y1: &'a FloatValue<'a>,
+        x2: &'a FloatValue<'a>,
+        y2: &'a FloatValue<'a>,
+    ) -> Self {
+        RecursiveBuilder {
             x1,
             y1,
             x2,
             y2,
+            values: RefCell::new(HashMap::new()),
+            ..*self
         }
     }
 
-    pub fn build(&self, node: &HeuristicNode) -> FloatValue {
-        match node {
+    // The value of the heuristic, as reported by `clamp_heuristic_value`
+    pub fn build_output(&self, node: &HeuristicNode) -> FloatValue<'a> {
+        let value = self.build(node);
+        let zero = self.f32_type.const_float(0.0);
+        let max = self.f32_type.const_float(f32::MAX as f64);
+
+        let is_nan = self
+            .builder
+            .build_float_compare(FloatPredicate::UNO, value, value, "is_nan")
+            .unwrap();
+        let is_positive = self
+            .builder
+            .build_float_compare(FloatPredicate::OGT, value, zero, "is_positive")
+            .unwrap();
+        let finite = self.build_intrinsic_call(self.min_fn, &[value, max], "finite");
+        let clamped = self
+            .builder
+            .build_select(is_positive, finite, zero, "clamped")
+            .unwrap()
+            .into_float_value();
+        self.builder
+            .build_select(is_nan, max, clamped, "output")
+            .unwrap()
+            .into_float_value()
+    }
+
+    // Structurally identical subtrees are emitted once and their value reused,
+    // which is valid as the function is a single basic block
+    pub fn build(&self, node: &HeuristicNode) -> FloatValue<'a> {
+        let key = node.to_string();
+        if let Some(value) = self.values.borrow().get(&key) {
+            return *value;
+        }
+
+        let value = match node {
             HeuristicNode::Number(num) => self.f32_type.const_float(*num as f64),
             HeuristicNode::Terminal(rule) => self.build_terminal(*rule),
             HeuristicNode::Unary(rule, h) => self.build_unary(*rule, h),
             HeuristicNode::Binary(rule, h1, h2) => self.build_binary(*rule, h1, h2),
-        }
+            HeuristicNode::Conditional(rule, h1, h2, h3, h4) => {
+                self.build_conditional(*rule, h1, h2, h3, h4)
+            }
+            HeuristicNode::Call(..) | HeuristicNode::Argument(_) => {
+                unreachable!("ADF calls are inlined before building")
+            }
+        };
+        self.values.borrow_mut().insert(key, value);
+        value
     }
 
-    fn build_terminal(&self, rule: Rule) -> FloatValue {
+    fn build_terminal(&self, rule: Rule) -> FloatValue<'a> {
         match rule {
             Rule::x1 => *self.x1,
             Rule::y1 => *self.y1,
@@ -219,13 +541,165 @@ impl<'a> RecursiveBuilder<'a> {
                     .unwrap();
                 abs.try_as_basic_value().left().unwrap().into_float_value()
             }
+            Rule::manhattan => {
+                let deltax = self.build_terminal(Rule::deltaX);
+                let deltay = self.build_terminal(Rule::deltaY);
+                self.builder
+                    .build_float_add(deltax, deltay, "manhattan")
+                    .unwrap()
+            }
+            Rule::euclid => {
+                let deltax = self.build_terminal(Rule::deltaX);
+                let deltay = self.build_terminal(Rule::deltaY);
+                let deltax2 = self.builder.build_float_mul(deltax, deltax, "sqr").unwrap();
+                let deltay2 = self.builder.build_float_mul(deltay, deltay, "sqr").unwrap();
+                let sum = self
+                    .builder
+                    .build_float_add(deltax2, deltay2, "plus")
+                    .unwrap();
+                self.build_intrinsic_call(self.sqrt_fn, &[sum], "euclid")
+            }
+            Rule::octile => {
+                let deltax = self.build_terminal(Rule::deltaX);
+                let deltay = self.build_terminal(Rule::deltaY);
+                let max = self.build_intrinsic_call(self.max_fn, &[deltax, deltay], "max");
+                let min = self.build_intrinsic_call(self.min_fn, &[deltax, deltay], "min");
+                let diagonal = self
+                    .builder
+                    .build_float_mul(
+                        self.f32_type.const_float((SQRT_2 - 1.0) as f64),
+                        min,
+                        "diagonal",
+                    )
+                    .unwrap();
+                self.builder
+                    .build_float_add(max, diagonal, "octile")
+                    .unwrap()
+            }
+            Rule::cheby => {
+                let deltax = self.build_terminal(Rule::deltaX);
+                let deltay = self.build_terminal(Rule::deltaY);
+                self.build_intrinsic_call(self.max_fn, &[deltax, deltay], "cheby")
+            }
+            Rule::lineObstacles => {
+                let tables = self.feature_tables();
+                let features = self.build_pointer(tables.features as *const MapFeatures as usize);
+                let call = self
+                    .builder
+                    .build_call(
+                        tables.line_obstacles_fn,
+                        &[
+                            features.into(),
+                            (*self.x1).into(),
+                            (*self.y1).into(),
+                            (*self.x2).into(),
+                            (*self.y2).into(),
+                        ],
+                        "lineObstacles",
+                    )
+                    .unwrap();
+                call.try_as_basic_value().left().unwrap().into_float_value()
+            }
+            Rule::wallDist => {
+                let tables = self.feature_tables();
+                let cell = self.build_cell_index(*self.x1, *self.y1);
+                self.build_table_load(&tables.features.wall_distances, cell, "wallDist")
+            }
+            Rule::sameRoom => {
+                let tables = self.feature_tables();
+                let cell1 = self.build_cell_index(*self.x1, *self.y1);
+                let cell2 = self.build_cell_index(*self.x2, *self.y2);
+                let room1 = self.build_table_load(&tables.features.rooms, cell1, "room");
+                let room2 = self.build_table_load(&tables.features.rooms, cell2, "room");
+                let same = self
+                    .builder
+                    .build_float_compare(FloatPredicate::OEQ, room1, room2, "same")
+                    .unwrap();
+                self.builder
+                    .build_select(
+                        same,
+                        self.f32_type.const_float(1.0),
+                        self.f32_type.const_float(0.0),
+                        "sameRoom",
+                    )
+                    .unwrap()
+                    .into_float_value()
+            }
+            Rule::rowDensity => {
+                let tables = self.feature_tables();
+                let row = self.build_clamped_index(*self.x1, tables.features.n);
+                self.build_table_load(&tables.features.row_densities, row, "rowDensity")
+            }
+            Rule::colDensity => {
+                let tables = self.feature_tables();
+                let column = self.build_clamped_index(*self.y1, tables.features.m);
+                self.build_table_load(&tables.features.column_densities, column, "colDensity")
+            }
             _ => {
                 unreachable!("{:?}", rule);
             }
         }
     }
 
-    fn build_unary(&self, rule: Rule, h: &HeuristicNode) -> FloatValue {
+    fn feature_tables(&self) -> &'a FeatureTables<'a> {
+        self.feature_tables
+            .expect("Map feature terminals need a Jit created with feature tables")
+    }
+
+    fn build_pointer(&self, address: usize) -> PointerValue<'a> {
+        let tables = self.feature_tables();
+        let address = tables.i64_type.const_int(address as u64, false);
+        self.builder
+            .build_int_to_ptr(address, tables.ptr_type, "table")
+            .unwrap()
+    }
+
+    // Truncates a coordinate to an index into a table of `len` entries, clamping
+    // it like `MapFeatures` does
+    fn build_clamped_index(&self, value: FloatValue<'a>, len: usize) -> IntValue<'a> {
+        let zero = self.f32_type.const_float(0.0);
+        let last = self.f32_type.const_float((len - 1) as f64);
+        let clamped = self.build_intrinsic_call(self.max_fn, &[value, zero], "clamp");
+        let clamped = self.build_intrinsic_call(self.min_fn, &[clamped, last], "clamp");
+        self.builder
+            .build_float_to_signed_int(clamped, self.feature_tables().i64_type, "index")
+            .unwrap()
+    }
+
+    fn build_cell_index(&self, x: FloatValue<'a>, y: FloatValue<'a>) -> IntValue<'a> {
+        let tables = self.feature_tables();
+        let row = self.build_clamped_index(x, tables.features.n);
+        let column = self.build_clamped_index(y, tables.features.m);
+        let width = tables.i64_type.const_int(tables.features.m as u64, false);
+        let offset = self.builder.build_int_mul(row, width, "offset").unwrap();
+        self.builder.build_int_add(offset, column, "cell").unwrap()
+    }
+
+    fn build_table_load(&self, table: &[f32], index: IntValue<'a>, name: &str) -> FloatValue<'a> {
+        let table = self.build_pointer(table.as_ptr() as usize);
+        let entry = unsafe {
+            self.builder
+                .build_gep(self.f32_type, table, &[index], "entry")
+                .unwrap()
+        };
+        self.builder
+            .build_load(self.f32_type, entry, name)
+            .unwrap()
+            .into_float_value()
+    }
+
+    fn build_intrinsic_call(
+        &self,
+        function: &FunctionValue<'a>,
+        args: &[FloatValue<'a>],
+        name: &str,
+    ) -> FloatValue<'a> {
+        let args = args.iter().map(|arg| (*arg).into()).collect::<Vec<_>>();
+        let call = self.builder.build_call(*function, &args, name).unwrap();
+        call.try_as_basic_value().left().unwrap().into_float_value()
+    }
+
+    fn build_unary(&self, rule: Rule, h: &HeuristicNode) -> FloatValue<'a> {
         let result = self.build(h);
         match rule {
             Rule::neg => self.builder.build_float_neg(result, "neg").unwrap(),
@@ -260,13 +734,24 @@ impl<'a> RecursiveBuilder<'a> {
                     .into_float_value()
             }
             Rule::sqr => self.builder.build_float_mul(result, result, "sqr").unwrap(),
+            Rule::log => {
+                let abs = self.build_intrinsic_call(self.abs_fn, &[result], "abs");
+                self.build_intrinsic_call(self.log_fn, &[abs], "log")
+            }
+            Rule::exp => self.build_intrinsic_call(self.exp_fn, &[result], "exp"),
+            Rule::floor => self.build_intrinsic_call(self.floor_fn, &[result], "floor"),
+            Rule::ceil => self.build_intrinsic_call(self.ceil_fn, &[result], "ceil"),
+            Rule::recip => self
+                .builder
+                .build_float_div(self.f32_type.const_float(1.0), result, "recip")
+                .unwrap(),
             _ => {
                 unreachable!("{:?}", rule);
             }
         }
     }
 
-    fn build_binary(&self, rule: Rule, h1: &HeuristicNode, h2: &HeuristicNode) -> FloatValue {
+    fn build_binary(&self, rule: Rule, h1: &HeuristicNode, h2: &HeuristicNode) -> FloatValue<'a> {
         let result1 = self.build(h1);
         let result2 = self.build(h2);
         match rule {
@@ -300,9 +785,41 @@ impl<'a> RecursiveBuilder<'a> {
                     .unwrap();
                 min.try_as_basic_value().left().unwrap().into_float_value()
             }
+            Rule::pow => {
+                let abs = self.build_intrinsic_call(self.abs_fn, &[result1], "abs");
+                self.build_intrinsic_call(self.pow_fn, &[abs, result2], "pow")
+            }
             _ => {
                 unreachable!("{:?}", rule);
             }
         }
     }
+
+    // Both branches are computed, which is safe as heuristics have no side effects
+    fn build_conditional(
+        &self,
+        rule: Rule,
+        h1: &HeuristicNode,
+        h2: &HeuristicNode,
+        h3: &HeuristicNode,
+        h4: &HeuristicNode,
+    ) -> FloatValue<'a> {
+        let (result1, result2) = (self.build(h1), self.build(h2));
+        let (result3, result4) = (self.build(h3), self.build(h4));
+        let predicate = match rule {
+            // Ordered, so that NaN takes the else branch like in the interpreter
+            Rule::ifle => FloatPredicate::OLE,
+            _ => {
+                unreachable!("{:?}", rule);
+            }
+        };
+        let condition = self
+            .builder
+            .build_float_compare(predicate, result1, result2, "condition")
+            .unwrap();
+        self.builder
+            .build_select(condition, result3, result4, &format!("{:?}", rule))
+            .unwrap()
+            .into_float_value()
+    }
 }
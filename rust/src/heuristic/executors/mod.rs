@@ -1,26 +1,143 @@
+#[cfg(feature = "jit")]
+pub mod compiled;
+#[cfg(feature = "cranelift")]
+pub mod cranelift;
 pub mod interpreter;
+#[cfg(feature = "jit")]
 pub mod jit;
+pub mod table;
+pub mod vm;
+
+use std::sync::Arc;
 
 use crate::heuristic::Heuristic;
+use crate::map::features::MapFeatures;
 
+// All executers implement the same total semantics, so that every heuristic
+// evaluates to the same value whichever executer runs it:
+// - `sqrt`, `log` and `pow` act on the magnitude of their (base) argument, with
+//   `sqrt` restoring the sign afterwards, so they never produce NaN themselves
+// - `log 0`, `recip 0`, division by zero and overflowing `exp`, `sqr` and `pow`
+//   produce infinities, which propagate like any other value
+// - NaN only arises from undefined combinations of infinities (e.g. `inf - inf`);
+//   `ifle` takes its else branch when either compared value is NaN
+// - The result is passed through `clamp_heuristic_value`, so search only ever sees
+//   estimates in `[0, f32::MAX]`
 pub trait HeuristicExecuter {
+    // Executers created without feature tables panic on map feature terminals
     fn create(heuristic: &Heuristic) -> Self;
 
+    fn create_with_features(heuristic: &Heuristic, features: Arc<MapFeatures>) -> Self;
+
     fn execute(&self, x1: f32, y1: f32, x2: f32, y2: f32) -> f32;
+
+    // Evaluates the heuristic from each point `(x1[i], y1[i])` to the single goal
+    // `(x2, y2)` into `out[i]`, e.g. for all neighbours of an expanded state
+    fn execute_batch(&self, x1: &[f32], y1: &[f32], x2: f32, y2: f32, out: &mut [f32]) {
+        assert_batch_lengths(x1, y1, out);
+        for ((value, &x1), &y1) in out.iter_mut().zip(x1).zip(y1) {
+            *value = self.execute(x1, y1, x2, y2);
+        }
+    }
+}
+
+// The executers a heuristic can be evaluated with, chosen at runtime
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Backend {
+    Interpreter,
+    Vm,
+    // The `Jit`, through `compiled::CompiledHeuristic`
+    #[cfg(feature = "jit")]
+    Llvm,
+    #[cfg(feature = "cranelift")]
+    Cranelift,
+}
+
+impl Backend {
+    // The backends compiled in, e.g. for Python, or `None` for any other name
+    pub fn from_name(name: &str) -> Option<Backend> {
+        match name {
+            "interpreter" => Some(Backend::Interpreter),
+            "vm" => Some(Backend::Vm),
+            #[cfg(feature = "jit")]
+            "llvm" => Some(Backend::Llvm),
+            #[cfg(feature = "cranelift")]
+            "cranelift" => Some(Backend::Cranelift),
+            _ => None,
+        }
+    }
+}
+
+// The backend with the fastest code of those compiled in
+impl Default for Backend {
+    fn default() -> Self {
+        #[cfg(feature = "jit")]
+        let backend = Backend::Llvm;
+        #[cfg(all(feature = "cranelift", not(feature = "jit")))]
+        let backend = Backend::Cranelift;
+        #[cfg(not(any(feature = "jit", feature = "cranelift")))]
+        let backend = Backend::Vm;
+        backend
+    }
+}
+
+// Called from compiled heuristics, with `features` pointing at the tables owned by
+// the executer
+#[cfg(any(feature = "jit", feature = "cranelift"))]
+extern "C" fn line_obstacles(
+    features: *const MapFeatures,
+    x1: f32,
+    y1: f32,
+    x2: f32,
+    y2: f32,
+) -> f32 {
+    unsafe { (*features).line_obstacles(x1, y1, x2, y2) }
+}
+
+pub fn assert_batch_lengths(x1: &[f32], y1: &[f32], out: &[f32]) {
+    assert!(
+        x1.len() == out.len() && y1.len() == out.len(),
+        "Batches of {} and {} points for {} values",
+        x1.len(),
+        y1.len(),
+        out.len()
+    );
+}
+
+// What every executer reports for the value a heuristic computes: NaN is the worst
+// possible estimate, `f32::MAX`, as is +inf, while negative values (including -0
+// and -inf) are reported as 0, the best possible estimate
+pub fn clamp_heuristic_value(value: f32) -> f32 {
+    if value.is_nan() {
+        f32::MAX
+    } else if value > 0.0 {
+        value.min(f32::MAX)
+    } else {
+        0.0
+    }
 }
 
 #[cfg(test)]
 mod tests {
+    #[cfg(feature = "cranelift")]
+    use super::cranelift::Cranelift;
     use super::interpreter::Interpreter;
+    #[cfg(feature = "jit")]
     use super::jit::Jit;
-    use super::HeuristicExecuter;
-    use crate::heuristic::parser::{HeuristicNode, Rule};
+    use super::vm::Vm;
+    use super::{clamp_heuristic_value, Backend, HeuristicExecuter};
+    use crate::heuristic::parser::{parse_heuristic, HeuristicNode, Rule};
+    use crate::heuristic::mutate_probs::TermProbabilities;
+    use crate::heuristic::util::random_heuristic;
     use crate::heuristic::Heuristic;
+    use crate::map::util::{Map, Tile};
 
     use test_case::test_case;
 
-    #[test_case ( HeuristicNode::Number(1), (1.0, 2.0, 3.0, 4.0), 1.0)]
-    #[test_case ( HeuristicNode::Number(9), (1.0, 2.0, 3.0, 4.0), 9.0)]
+    #[test_case ( HeuristicNode::Number(1.0), (1.0, 2.0, 3.0, 4.0), 1.0)]
+    #[test_case ( HeuristicNode::Number(9.0), (1.0, 2.0, 3.0, 4.0), 9.0)]
+    #[test_case ( HeuristicNode::Number(-3.0), (1.0, 2.0, 3.0, 4.0), -3.0)]
+    #[test_case ( HeuristicNode::Number(1.625), (1.0, 2.0, 3.0, 4.0), 1.625)]
     #[test_case( HeuristicNode::Terminal(Rule::x1), (1.0, 2.0, 3.0, 4.0), 1.0)]
     #[test_case( HeuristicNode::Terminal(Rule::x2), (1.0, 2.0, 3.0, 4.0), 3.0)]
     #[test_case( HeuristicNode::Terminal(Rule::y1), (1.0, 2.0, 3.0, 4.0), 2.0)]
@@ -29,6 +146,11 @@ mod tests {
     #[test_case( HeuristicNode::Terminal(Rule::deltaX), (3.0, 2.0, 1.0, 5.0), 2.0)]
     #[test_case( HeuristicNode::Terminal(Rule::deltaY), (1.0, 2.0, 3.0, 5.0), 3.0)]
     #[test_case( HeuristicNode::Terminal(Rule::deltaY), (3.0, 2.0, 1.0, 5.0), 3.0)]
+    #[test_case( HeuristicNode::Terminal(Rule::manhattan), (3.0, 2.0, 1.0, 5.0), 5.0)]
+    #[test_case( HeuristicNode::Terminal(Rule::euclid), (3.0, 2.0, 1.0, 5.0), 13.0f32.sqrt())]
+    #[test_case( HeuristicNode::Terminal(Rule::euclid), (1.0, 1.0, 4.0, 5.0), 5.0)]
+    #[test_case( HeuristicNode::Terminal(Rule::octile), (3.0, 2.0, 1.0, 5.0), 3.0 + (std::f32::consts::SQRT_2 - 1.0) * 2.0)]
+    #[test_case( HeuristicNode::Terminal(Rule::cheby), (3.0, 2.0, 1.0, 5.0), 3.0)]
     #[test_case( HeuristicNode::Unary(Rule::neg, Box::new(HeuristicNode::Terminal(Rule::x1))), (1.0, 2.0, 3.0, 4.0), -1.0)]
     #[test_case( HeuristicNode::Unary(Rule::abs, Box::new(HeuristicNode::Terminal(Rule::x1))), (1.0, 2.0, 3.0, 4.0), 1.0)]
     #[test_case( HeuristicNode::Unary(Rule::sqrt, Box::new(HeuristicNode::Terminal(Rule::x1))), (1.0, 2.0, 3.0, 4.0), 1.0)]
@@ -39,23 +161,260 @@ mod tests {
     #[test_case( HeuristicNode::Binary(Rule::div, Box::new(HeuristicNode::Terminal(Rule::x1)), Box::new(HeuristicNode::Terminal(Rule::y1))), (1.0, 2.0, 3.0, 4.0), 0.5)]
     #[test_case( HeuristicNode::Binary(Rule::max, Box::new(HeuristicNode::Terminal(Rule::x1)), Box::new(HeuristicNode::Terminal(Rule::y2))), (1.0, 2.0, 3.0, 4.0), 4.0)]
     #[test_case( HeuristicNode::Binary(Rule::min, Box::new(HeuristicNode::Terminal(Rule::x1)), Box::new(HeuristicNode::Terminal(Rule::y2))), (1.0, 2.0, 3.0, 4.0), 1.0)]
+    #[test_case( HeuristicNode::Unary(Rule::log, Box::new(HeuristicNode::Terminal(Rule::y2))), (1.0, 2.0, 3.0, 4.0), 4.0f32.ln())]
+    #[test_case( HeuristicNode::Unary(Rule::log, Box::new(HeuristicNode::Number(-1.0))), (1.0, 2.0, 3.0, 4.0), 0.0)]
+    #[test_case( HeuristicNode::Unary(Rule::log, Box::new(HeuristicNode::Number(0.0))), (1.0, 2.0, 3.0, 4.0), f32::NEG_INFINITY)]
+    #[test_case( HeuristicNode::Unary(Rule::exp, Box::new(HeuristicNode::Terminal(Rule::x1))), (1.0, 2.0, 3.0, 4.0), 1.0f32.exp())]
+    #[test_case( HeuristicNode::Unary(Rule::floor, Box::new(HeuristicNode::Number(-1.5))), (1.0, 2.0, 3.0, 4.0), -2.0)]
+    #[test_case( HeuristicNode::Unary(Rule::ceil, Box::new(HeuristicNode::Number(-1.5))), (1.0, 2.0, 3.0, 4.0), -1.0)]
+    #[test_case( HeuristicNode::Unary(Rule::recip, Box::new(HeuristicNode::Terminal(Rule::y2))), (1.0, 2.0, 3.0, 4.0), 0.25)]
+    #[test_case( HeuristicNode::Unary(Rule::recip, Box::new(HeuristicNode::Number(0.0))), (1.0, 2.0, 3.0, 4.0), f32::INFINITY)]
+    #[test_case( HeuristicNode::Binary(Rule::pow, Box::new(HeuristicNode::Terminal(Rule::y1)), Box::new(HeuristicNode::Terminal(Rule::x2))), (1.0, 2.0, 3.0, 4.0), 8.0)]
+    #[test_case( HeuristicNode::Binary(Rule::pow, Box::new(HeuristicNode::Number(-2.0)), Box::new(HeuristicNode::Number(0.5))), (1.0, 2.0, 3.0, 4.0), 2.0f32.sqrt())]
+    #[test_case( HeuristicNode::Conditional(Rule::ifle, Box::new(HeuristicNode::Terminal(Rule::deltaX)), Box::new(HeuristicNode::Terminal(Rule::deltaY)), Box::new(HeuristicNode::Terminal(Rule::x1)), Box::new(HeuristicNode::Terminal(Rule::y1))), (1.0, 2.0, 3.0, 5.0), 1.0)]
+    #[test_case( HeuristicNode::Conditional(Rule::ifle, Box::new(HeuristicNode::Terminal(Rule::deltaY)), Box::new(HeuristicNode::Terminal(Rule::deltaX)), Box::new(HeuristicNode::Terminal(Rule::x1)), Box::new(HeuristicNode::Terminal(Rule::y1))), (1.0, 2.0, 3.0, 5.0), 2.0)]
+    #[test_case( HeuristicNode::Conditional(Rule::ifle, Box::new(HeuristicNode::Terminal(Rule::x1)), Box::new(HeuristicNode::Terminal(Rule::x1)), Box::new(HeuristicNode::Terminal(Rule::x1)), Box::new(HeuristicNode::Terminal(Rule::y1))), (1.0, 2.0, 3.0, 5.0), 1.0)]
+    #[test_case( HeuristicNode::Conditional(Rule::ifle, Box::new(HeuristicNode::Binary(Rule::minus, Box::new(HeuristicNode::Unary(Rule::recip, Box::new(HeuristicNode::Number(0.0)))), Box::new(HeuristicNode::Unary(Rule::recip, Box::new(HeuristicNode::Number(0.0)))))), Box::new(HeuristicNode::Number(0.0)), Box::new(HeuristicNode::Terminal(Rule::x1)), Box::new(HeuristicNode::Terminal(Rule::y1))), (1.0, 2.0, 3.0, 5.0), 2.0)]
     fn test_evaluate_heuristic(
         heuristic: HeuristicNode,
         (x1, y1, x2, y2): (f32, f32, f32, f32),
         expected: f32,
     ) {
         let heuristic = Heuristic::new(heuristic);
+        let expected = clamp_heuristic_value(expected);
         {
             let interpreter = Interpreter::create(&heuristic);
             let result = interpreter.execute(x1, y1, x2, y2);
             assert_eq!(result, expected);
         }
 
+        {
+            let vm = Vm::create(&heuristic);
+            let result = vm.execute(x1, y1, x2, y2);
+            assert_eq!(result, expected);
+        }
+
+        #[cfg(feature = "jit")]
         {
             let context = inkwell::context::Context::create();
             let jit = Jit::create(&heuristic, &context);
             let result = jit.execute(x1, y1, x2, y2);
             assert_eq!(result, expected);
         }
+
+        #[cfg(feature = "cranelift")]
+        {
+            let cranelift = Cranelift::create(&heuristic);
+            let result = cranelift.execute(x1, y1, x2, y2);
+            assert_eq!(result, expected);
+        }
+    }
+
+    // On `Map::walled`
+    #[test_case(Rule::lineObstacles, (1.0, 0.0, 1.0, 4.0), 1.0)]
+    #[test_case(Rule::lineObstacles, (3.0, 0.0, 3.0, 4.0), 0.0)]
+    #[test_case(Rule::wallDist, (1.0, 1.0, 0.0, 0.0), 1.0)]
+    #[test_case(Rule::wallDist, (0.0, 2.0, 0.0, 0.0), 0.0)]
+    #[test_case(Rule::wallDist, (-3.0, 9.0, 0.0, 0.0), 1.0)]
+    #[test_case(Rule::sameRoom, (0.0, 0.0, 3.0, 1.0), 1.0)]
+    #[test_case(Rule::sameRoom, (0.0, 0.0, 3.0, 3.0), 0.0)]
+    #[test_case(Rule::rowDensity, (2.0, 0.0, 0.0, 0.0), 0.2)]
+    #[test_case(Rule::rowDensity, (3.0, 0.0, 0.0, 0.0), 0.0)]
+    #[test_case(Rule::colDensity, (0.0, 2.0, 0.0, 0.0), 0.75)]
+    fn test_evaluate_feature(feature: Rule, (x1, y1, x2, y2): (f32, f32, f32, f32), expected: f32) {
+        let features = Map::walled().features;
+        let heuristic = Heuristic::new(HeuristicNode::Terminal(feature));
+
+        {
+            let interpreter = Interpreter::create_with_features(&heuristic, features.clone());
+            let result = interpreter.execute(x1, y1, x2, y2);
+            assert_eq!(result, expected);
+        }
+
+        {
+            let vm = Vm::create_with_features(&heuristic, features.clone());
+            let result = vm.execute(x1, y1, x2, y2);
+            assert_eq!(result, expected);
+        }
+
+        #[cfg(feature = "jit")]
+        {
+            let context = inkwell::context::Context::create();
+            let jit = Jit::create_with_features(&heuristic, &context, features.clone());
+            let result = jit.execute(x1, y1, x2, y2);
+            assert_eq!(result, expected);
+        }
+
+        #[cfg(feature = "cranelift")]
+        {
+            let cranelift = Cranelift::create_with_features(&heuristic, features);
+            let result = cranelift.execute(x1, y1, x2, y2);
+            assert_eq!(result, expected);
+        }
+    }
+
+    #[test_case("(defun adf0 2 (- arg0 arg1)) (adf0 x2 x1)", (1.0, 2.0, 3.0, 5.0), 2.0)]
+    #[test_case("(defun adf0 1 (sqr arg0)) (defun adf1 2 (+ (adf0 arg0) (adf0 arg1))) (sqrt (adf1 deltaX deltaY))", (1.0, 2.0, 4.0, 6.0), 5.0)]
+    #[test_case("(defun adf0 0 7) (ifle x1 y1 (adf0) 0)", (1.0, 2.0, 3.0, 5.0), 7.0)]
+    fn test_evaluate_adfs(input: &str, (x1, y1, x2, y2): (f32, f32, f32, f32), expected: f32) {
+        let heuristic = parse_heuristic(input);
+        {
+            let interpreter = Interpreter::create(&heuristic);
+            let result = interpreter.execute(x1, y1, x2, y2);
+            assert_eq!(result, expected);
+        }
+
+        {
+            let vm = Vm::create(&heuristic);
+            let result = vm.execute(x1, y1, x2, y2);
+            assert_eq!(result, expected);
+        }
+
+        #[cfg(feature = "jit")]
+        {
+            let context = inkwell::context::Context::create();
+            let jit = Jit::create(&heuristic, &context);
+            let result = jit.execute(x1, y1, x2, y2);
+            assert_eq!(result, expected);
+        }
+
+        #[cfg(feature = "cranelift")]
+        {
+            let cranelift = Cranelift::create(&heuristic);
+            let result = cranelift.execute(x1, y1, x2, y2);
+            assert_eq!(result, expected);
+        }
+    }
+
+    #[test_case("vm", Some(Backend::Vm))]
+    #[test_case("interpreter", Some(Backend::Interpreter))]
+    #[cfg_attr(feature = "cranelift", test_case("cranelift", Some(Backend::Cranelift)))]
+    #[test_case("Vm", None)]
+    fn test_backend_from_name(name: &str, expected: Option<Backend>) {
+        assert_eq!(Backend::from_name(name), expected);
+    }
+
+    #[test_case(2.5, 2.5)]
+    #[test_case(-2.5, 0.0)]
+    #[test_case(-0.0, 0.0)]
+    #[test_case(f32::INFINITY, f32::MAX)]
+    #[test_case(f32::NEG_INFINITY, 0.0)]
+    #[test_case(f32::NAN, f32::MAX)]
+    fn test_clamp_heuristic_value(value: f32, expected: f32) {
+        assert_eq!(clamp_heuristic_value(value).to_bits(), expected.to_bits());
+    }
+
+    // Differential test of random heuristics, which are likely to hit non-finite and
+    // negative values somewhere in the map
+    #[test]
+    #[cfg(feature = "jit")]
+    fn test_executers_agree() {
+        let map = Map::from(6, 7, vec![Tile::Passable; 6 * 7]);
+        let context = inkwell::context::Context::create();
+
+        let term_probs = Some(TermProbabilities::new(true).with_feature_terminals());
+        for _ in 0..200 {
+            let heuristic = Heuristic::new(random_heuristic(fastrand::i32(1..=30), &term_probs));
+            let interpreter = Interpreter::create_with_features(&heuristic, map.features.clone());
+            let jit = Jit::create_with_features(&heuristic, &context, map.features.clone());
+
+            for _ in 0..50 {
+                let (x1, x2) = (fastrand::usize(0..6) as f32, fastrand::usize(0..6) as f32);
+                let (y1, y2) = (fastrand::usize(0..7) as f32, fastrand::usize(0..7) as f32);
+                assert_eq!(
+                    interpreter.execute(x1, y1, x2, y2).to_bits(),
+                    jit.execute(x1, y1, x2, y2).to_bits(),
+                    "{} at {:?}",
+                    heuristic,
+                    (x1, y1, x2, y2)
+                );
+            }
+        }
+    }
+
+    // Every point of a batch must get the value it gets on its own, including the
+    // points where random heuristics produce non-finite and negative values
+    #[test]
+    fn test_execute_batch() {
+        let map = Map::from(6, 7, vec![Tile::Passable; 6 * 7]);
+        #[cfg(feature = "jit")]
+        let context = inkwell::context::Context::create();
+        let (x1, y1): (Vec<_>, Vec<_>) = (0..6 * 7)
+            .map(|pos| ((pos / 7) as f32, (pos % 7) as f32))
+            .unzip();
+        let bits = |values: &[f32]| values.iter().map(|value| value.to_bits()).collect::<Vec<_>>();
+
+        let term_probs = Some(TermProbabilities::new(true).with_feature_terminals());
+        for _ in 0..50 {
+            let heuristic = Heuristic::new(random_heuristic(fastrand::i32(1..=30), &term_probs));
+            let interpreter = Interpreter::create_with_features(&heuristic, map.features.clone());
+            let vm = Vm::create_with_features(&heuristic, map.features.clone());
+            #[cfg(feature = "jit")]
+            let jit = Jit::create_with_features(&heuristic, &context, map.features.clone());
+            #[cfg(feature = "cranelift")]
+            let cranelift = Cranelift::create_with_features(&heuristic, map.features.clone());
+
+            let (x2, y2) = (fastrand::usize(0..6) as f32, fastrand::usize(0..7) as f32);
+            let expected = x1
+                .iter()
+                .zip(&y1)
+                .map(|(&x1, &y1)| interpreter.execute(x1, y1, x2, y2))
+                .collect::<Vec<_>>();
+
+            let mut out = vec![0.0; x1.len()];
+            interpreter.execute_batch(&x1, &y1, x2, y2, &mut out);
+            assert_eq!(bits(&out), bits(&expected), "{}", heuristic);
+
+            let mut out = vec![0.0; x1.len()];
+            vm.execute_batch(&x1, &y1, x2, y2, &mut out);
+            assert_eq!(bits(&out), bits(&expected), "{}", heuristic);
+
+            #[cfg(feature = "jit")]
+            {
+                let mut out = vec![0.0; x1.len()];
+                jit.execute_batch(&x1, &y1, x2, y2, &mut out);
+                assert_eq!(bits(&out), bits(&expected), "{}", heuristic);
+
+                jit.execute_batch(&[], &[], x2, y2, &mut []);
+            }
+
+            #[cfg(feature = "cranelift")]
+            {
+                let mut out = vec![0.0; x1.len()];
+                cranelift.execute_batch(&x1, &y1, x2, y2, &mut out);
+                assert_eq!(bits(&out), bits(&expected), "{}", heuristic);
+            }
+        }
+    }
+
+    // Each function of a population module must compute its own heuristic
+    #[cfg(feature = "jit")]
+    #[test_case(inkwell::OptimizationLevel::None)]
+    #[test_case(inkwell::OptimizationLevel::Aggressive)]
+    fn test_create_population(optimization: inkwell::OptimizationLevel) {
+        let map = Map::from(6, 7, vec![Tile::Passable; 6 * 7]);
+        let context = inkwell::context::Context::create();
+        let term_probs = Some(TermProbabilities::new(true).with_feature_terminals());
+        let heuristics = (0..20)
+            .map(|_| Heuristic::new(random_heuristic(fastrand::i32(1..=30), &term_probs)))
+            .collect::<Vec<_>>();
+        let jits =
+            Jit::create_population(&heuristics, &context, Some(map.features.clone()), optimization);
+        assert_eq!(jits.len(), heuristics.len());
+
+        for (heuristic, jit) in heuristics.iter().zip(&jits) {
+            let interpreter = Interpreter::create_with_features(heuristic, map.features.clone());
+            for _ in 0..20 {
+                let (x1, x2) = (fastrand::usize(0..6) as f32, fastrand::usize(0..6) as f32);
+                let (y1, y2) = (fastrand::usize(0..7) as f32, fastrand::usize(0..7) as f32);
+                assert_eq!(
+                    interpreter.execute(x1, y1, x2, y2).to_bits(),
+                    jit.execute(x1, y1, x2, y2).to_bits(),
+                    "{} at {:?}",
+                    heuristic,
+                    (x1, y1, x2, y2)
+                );
+            }
+        }
     }
 }
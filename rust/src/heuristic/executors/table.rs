@@ -0,0 +1,113 @@
+use rayon::prelude::*;
+
+use crate::map::util::{Map, Tile};
+
+// Passable cells are evaluated in batches of this many, each batch on one thread
+const TABULATION_BATCH_SIZE: usize = 1024;
+
+// The values of a heuristic from every passable cell of a map to a single goal, so
+// that a search towards that goal only has to look them up
+pub struct HeuristicTable {
+    pub goal: usize,
+    // Indexed like `Map::map`, with `f32::MAX` for cells that are not passable
+    values: Vec<f32>,
+    m: usize,
+}
+
+impl HeuristicTable {
+    // Evaluates the heuristic through `execute_batch`, which has the form of
+    // `HeuristicExecuter::execute_batch`
+    pub fn tabulate(
+        map: &Map,
+        goal: usize,
+        execute_batch: impl Fn(&[f32], &[f32], f32, f32, &mut [f32]) + Sync,
+    ) -> HeuristicTable {
+        let (gx, gy) = map.ind2sub(goal);
+        let (gx, gy) = (gx as f32, gy as f32);
+        let passable = (0..map.map.len())
+            .filter(|&pos| map.map[pos] == Tile::Passable)
+            .collect::<Vec<_>>();
+
+        let batches = passable
+            .par_chunks(TABULATION_BATCH_SIZE)
+            .map(|positions| {
+                let (x1, y1): (Vec<_>, Vec<_>) = positions
+                    .iter()
+                    .map(|&pos| {
+                        let (x, y) = map.ind2sub(pos);
+                        (x as f32, y as f32)
+                    })
+                    .unzip();
+                let mut out = vec![0.0; positions.len()];
+                execute_batch(&x1, &y1, gx, gy, &mut out);
+                out
+            })
+            .collect::<Vec<_>>();
+
+        let mut values = vec![f32::MAX; map.map.len()];
+        for (&pos, value) in passable.iter().zip(batches.into_iter().flatten()) {
+            values[pos] = value;
+        }
+
+        HeuristicTable {
+            goal,
+            values,
+            m: map.m,
+        }
+    }
+
+    pub fn get(&self, x1: f32, y1: f32) -> f32 {
+        self.values[x1 as usize * self.m + y1 as usize]
+    }
+
+    // Can replace `HeuristicExecuter::execute_batch` in searches towards `goal`
+    pub fn execute_batch(&self, x1: &[f32], y1: &[f32], out: &mut [f32]) {
+        for ((value, &x1), &y1) in out.iter_mut().zip(x1).zip(y1) {
+            *value = self.get(x1, y1);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::alife::search::problem::Problem;
+    use crate::heuristic::executors::vm::Vm;
+    use crate::heuristic::executors::HeuristicExecuter;
+    use crate::heuristic::parser::parse_heuristic;
+
+    #[test]
+    fn test_tabulate() {
+        let map = Map::walled();
+        let vm = Vm::create(&parse_heuristic("(+ (* 10 x1) y1)"));
+        let goal = map.sub2ind(3, 4);
+        let table = HeuristicTable::tabulate(&map, goal, |x1, y1, x2, y2, out| {
+            vm.execute_batch(x1, y1, x2, y2, out)
+        });
+
+        assert_eq!(table.goal, goal);
+        assert_eq!(table.get(0.0, 0.0), 0.0);
+        assert_eq!(table.get(2.0, 3.0), 23.0);
+        assert_eq!(table.get(3.0, 2.0), 32.0);
+        assert_eq!(table.get(1.0, 2.0), f32::MAX);
+    }
+
+    // Searching with the table must behave exactly like evaluating directly
+    #[test]
+    fn test_solve_with_table() {
+        let map = Map::walled();
+        let vm = Vm::create(&parse_heuristic("(* 2 manhattan)"));
+        let problem = Problem::new(map.sub2ind(0, 0), map.sub2ind(0, 4));
+
+        let direct = problem.solve(&map, |x1, y1, x2, y2| vm.execute(x1, y1, x2, y2));
+        let table = HeuristicTable::tabulate(&map, problem.goal, |x1, y1, x2, y2, out| {
+            vm.execute_batch(x1, y1, x2, y2, out)
+        });
+        let tabulated =
+            problem.solve_batched(&map, |x1, y1, _, _, out| table.execute_batch(x1, y1, out));
+
+        assert!(tabulated.solved);
+        assert_eq!(tabulated.expansions, direct.expansions);
+        assert_eq!(tabulated.solution_path, direct.solution_path);
+    }
+}
@@ -0,0 +1,306 @@
+use std::sync::Arc;
+
+use crate::heuristic::{
+    executors::{
+        assert_batch_lengths, clamp_heuristic_value,
+        interpreter::{evaluate_binary, evaluate_condition, evaluate_terminal, evaluate_unary},
+        HeuristicExecuter,
+    },
+    parser::{HeuristicNode, Rule},
+    Heuristic,
+};
+use crate::map::features::MapFeatures;
+
+// Programs needing at most this many stack slots run on a stack allocated array,
+// deeper ones on the heap
+const INLINE_STACK_SIZE: usize = 64;
+
+// A single postfix operation. Each operation pops its operands from the stack and
+// pushes its result.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Instruction {
+    Number(f32),
+    Terminal(Rule),
+    Unary(Rule),
+    Binary(Rule),
+    // Pops both compared values and both branches, which are always evaluated
+    Conditional(Rule),
+}
+
+// Sits between the `Interpreter` and the `Jit`: compiling is a single walk over the
+// tree, while executing avoids the pointer chasing and recursion of the interpreter
+pub struct Vm {
+    code: Vec<Instruction>,
+    stack_size: usize,
+    features: Option<Arc<MapFeatures>>,
+}
+
+impl HeuristicExecuter for Vm {
+    fn create(heuristic: &Heuristic) -> Self {
+        Self::compile(heuristic, None)
+    }
+
+    fn create_with_features(heuristic: &Heuristic, features: Arc<MapFeatures>) -> Self {
+        Self::compile(heuristic, Some(features))
+    }
+
+    fn execute(&self, x1: f32, y1: f32, x2: f32, y2: f32) -> f32 {
+        let coordinates = (x1, y1, x2, y2);
+        let value = match self.stack_size <= INLINE_STACK_SIZE {
+            true => self.run(&mut [0.0; INLINE_STACK_SIZE], coordinates),
+            false => self.run(&mut vec![0.0; self.stack_size], coordinates),
+        };
+        clamp_heuristic_value(value)
+    }
+
+    // Runs the program once over the whole batch, with each stack slot holding one
+    // value per point, so that the dispatch is amortized over the batch
+    fn execute_batch(&self, x1: &[f32], y1: &[f32], x2: f32, y2: f32, out: &mut [f32]) {
+        assert_batch_lengths(x1, y1, out);
+        let n = out.len();
+        let mut stack = vec![0.0; self.stack_size * n];
+        self.run_batch(&mut stack, n, (x1, y1, x2, y2));
+        for (value, &result) in out.iter_mut().zip(&stack[..n]) {
+            *value = clamp_heuristic_value(result);
+        }
+    }
+}
+
+impl Vm {
+    fn compile(heuristic: &Heuristic, features: Option<Arc<MapFeatures>>) -> Vm {
+        let mut code = Vec::new();
+        let stack_size = compile_node(&heuristic.inlined(), &mut code);
+        Vm {
+            code,
+            stack_size,
+            features,
+        }
+    }
+
+    pub fn code(&self) -> &[Instruction] {
+        &self.code
+    }
+
+    fn run(&self, stack: &mut [f32], coordinates: (f32, f32, f32, f32)) -> f32 {
+        let features = self.features.as_deref();
+        // The number of values on the stack
+        let mut top = 0;
+
+        for instruction in &self.code {
+            match *instruction {
+                Instruction::Number(num) => {
+                    stack[top] = num;
+                    top += 1;
+                }
+                Instruction::Terminal(rule) => {
+                    stack[top] = evaluate_terminal(rule, coordinates, features);
+                    top += 1;
+                }
+                Instruction::Unary(rule) => {
+                    stack[top - 1] = evaluate_unary(rule, stack[top - 1]);
+                }
+                Instruction::Binary(rule) => {
+                    top -= 1;
+                    stack[top - 1] = evaluate_binary(rule, stack[top - 1], stack[top]);
+                }
+                Instruction::Conditional(rule) => {
+                    top -= 3;
+                    stack[top - 1] = match evaluate_condition(rule, stack[top - 1], stack[top]) {
+                        true => stack[top + 1],
+                        false => stack[top + 2],
+                    };
+                }
+            }
+        }
+
+        debug_assert_eq!(top, 1);
+        stack[0]
+    }
+
+    // Like `run`, with slot `i` of the stack being `stack[i * n..(i + 1) * n]`
+    fn run_batch(&self, stack: &mut [f32], n: usize, (x1, y1, x2, y2): (&[f32], &[f32], f32, f32)) {
+        let features = self.features.as_deref();
+        let mut top = 0;
+
+        for instruction in &self.code {
+            match *instruction {
+                Instruction::Number(num) => {
+                    stack[top * n..(top + 1) * n].fill(num);
+                    top += 1;
+                }
+                Instruction::Terminal(rule) => {
+                    let values = &mut stack[top * n..(top + 1) * n];
+                    for ((value, &x1), &y1) in values.iter_mut().zip(x1).zip(y1) {
+                        *value = evaluate_terminal(rule, (x1, y1, x2, y2), features);
+                    }
+                    top += 1;
+                }
+                Instruction::Unary(rule) => {
+                    for value in &mut stack[(top - 1) * n..top * n] {
+                        *value = evaluate_unary(rule, *value);
+                    }
+                }
+                Instruction::Binary(rule) => {
+                    top -= 1;
+                    let (values1, values2) = stack[(top - 1) * n..(top + 1) * n].split_at_mut(n);
+                    for (value1, &value2) in values1.iter_mut().zip(values2.iter()) {
+                        *value1 = evaluate_binary(rule, *value1, value2);
+                    }
+                }
+                Instruction::Conditional(rule) => {
+                    top -= 3;
+                    let (values1, rest) = stack[(top - 1) * n..(top + 3) * n].split_at_mut(n);
+                    let (values2, rest) = rest.split_at(n);
+                    let (values3, values4) = rest.split_at(n);
+                    let branches = values2.iter().zip(values3).zip(values4);
+                    for (value1, ((&value2, &value3), &value4)) in values1.iter_mut().zip(branches) {
+                        *value1 = match evaluate_condition(rule, *value1, value2) {
+                            true => value3,
+                            false => value4,
+                        };
+                    }
+                }
+            }
+        }
+
+        debug_assert_eq!(top, 1);
+    }
+}
+
+// Appends the postfix code of `node` to `code`, returning the number of stack slots
+// it needs
+fn compile_node(node: &HeuristicNode, code: &mut Vec<Instruction>) -> usize {
+    match node {
+        HeuristicNode::Number(num) => {
+            code.push(Instruction::Number(*num));
+            1
+        }
+        HeuristicNode::Terminal(rule) => {
+            code.push(Instruction::Terminal(*rule));
+            1
+        }
+        HeuristicNode::Unary(rule, h) => {
+            let size = compile_node(h, code);
+            code.push(Instruction::Unary(*rule));
+            size
+        }
+        HeuristicNode::Binary(rule, h1, h2) => {
+            let size1 = compile_node(h1, code);
+            let size2 = compile_node(h2, code);
+            code.push(Instruction::Binary(*rule));
+            size1.max(1 + size2)
+        }
+        HeuristicNode::Conditional(rule, h1, h2, h3, h4) => {
+            // Operand i is computed on top of the i values before it
+            let size = [h1, h2, h3, h4]
+                .into_iter()
+                .enumerate()
+                .map(|(i, h)| i + compile_node(h, code))
+                .max()
+                .unwrap();
+            code.push(Instruction::Conditional(*rule));
+            size
+        }
+        HeuristicNode::Call(..) | HeuristicNode::Argument(_) => {
+            unreachable!("ADF calls are inlined on creation")
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::heuristic::executors::interpreter::Interpreter;
+    use crate::heuristic::parser::parse_heuristic;
+    use crate::heuristic::mutate_probs::TermProbabilities;
+    use crate::heuristic::util::random_heuristic;
+    use crate::map::util::{Map, Tile};
+
+    use test_case::test_case;
+
+    #[test_case("x1", 1)]
+    #[test_case("(+ x1 (* y1 (- x2 y2)))", 4)]
+    #[test_case("(+ (* y1 (- x2 y2)) x1)", 3)]
+    #[test_case("(ifle x1 y1 x2 (+ y2 1))", 5)]
+    #[test_case("(defun adf0 2 (- arg0 arg1)) (adf0 x2 (sqr x1))", 2)]
+    fn test_stack_size(input: &str, expected: usize) {
+        let vm = Vm::create(&parse_heuristic(input));
+        assert_eq!(vm.stack_size, expected);
+    }
+
+    #[test]
+    fn test_code() {
+        let vm = Vm::create(&parse_heuristic("(ifle x1 2 (neg y1) (* x2 y2))"));
+        assert_eq!(
+            vm.code(),
+            [
+                Instruction::Terminal(Rule::x1),
+                Instruction::Number(2.0),
+                Instruction::Terminal(Rule::y1),
+                Instruction::Unary(Rule::neg),
+                Instruction::Terminal(Rule::x2),
+                Instruction::Terminal(Rule::y2),
+                Instruction::Binary(Rule::mul),
+                Instruction::Conditional(Rule::ifle),
+            ]
+        );
+        assert_eq!(vm.execute(1.0, 2.0, 3.0, 4.0), 0.0);
+        assert_eq!(vm.execute(3.0, 2.0, 3.0, 4.0), 12.0);
+    }
+
+    // Deep enough to run on the heap allocated stack
+    #[test]
+    fn test_deep_stack() {
+        let depth = 2 * INLINE_STACK_SIZE;
+        let input = "(+ 1 ".repeat(depth) + "x1" + &")".repeat(depth);
+        let vm = Vm::create(&parse_heuristic(&input));
+        assert_eq!(vm.stack_size, depth + 1);
+        assert_eq!(vm.execute(2.0, 0.0, 0.0, 0.0), depth as f32 + 2.0);
+    }
+
+    #[test]
+    fn test_agrees_with_interpreter() {
+        let map = Map::from(6, 7, vec![Tile::Passable; 6 * 7]);
+
+        let term_probs = Some(TermProbabilities::new(true).with_feature_terminals());
+        for _ in 0..200 {
+            let heuristic = Heuristic::new(random_heuristic(fastrand::i32(1..=30), &term_probs));
+            let interpreter = Interpreter::create_with_features(&heuristic, map.features.clone());
+            let vm = Vm::create_with_features(&heuristic, map.features.clone());
+
+            for _ in 0..50 {
+                let (x1, x2) = (fastrand::usize(0..6) as f32, fastrand::usize(0..6) as f32);
+                let (y1, y2) = (fastrand::usize(0..7) as f32, fastrand::usize(0..7) as f32);
+                assert_eq!(
+                    interpreter.execute(x1, y1, x2, y2).to_bits(),
+                    vm.execute(x1, y1, x2, y2).to_bits(),
+                    "{} at {:?}",
+                    heuristic,
+                    (x1, y1, x2, y2)
+                );
+            }
+        }
+    }
+
+    #[test_case("(+ x1 (* y1 (- x2 y2)))")]
+    #[test_case("(ifle x1 y1 (neg x2) (sqrt (- y2 x1)))")]
+    #[test_case("(max (recip (- x1 x2)) (log y1))")]
+    fn test_execute_batch(input: &str) {
+        let vm = Vm::create(&parse_heuristic(input));
+        let (x1, y1) = ([0.0, 1.0, 2.0, 3.0, -1.0], [4.0, 3.0, 2.0, 1.0, 0.5]);
+        let mut out = [0.0; 5];
+        vm.execute_batch(&x1, &y1, 2.0, 1.0, &mut out);
+        for i in 0..5 {
+            assert_eq!(out[i].to_bits(), vm.execute(x1[i], y1[i], 2.0, 1.0).to_bits());
+        }
+
+        vm.execute_batch(&[], &[], 2.0, 1.0, &mut []);
+    }
+
+    #[test]
+    #[should_panic]
+    fn test_execute_batch_lengths() {
+        let vm = Vm::create(&parse_heuristic("x1"));
+        vm.execute_batch(&[1.0, 2.0], &[1.0], 0.0, 0.0, &mut [0.0; 2]);
+    }
+}
@@ -0,0 +1,473 @@
+use std::fmt::Write;
+
+use super::parser::{HeuristicNode, Rule};
+use super::Heuristic;
+
+// Exporters of heuristics for papers and reviews: Graphviz DOT graphs of the tree,
+// and LaTeX and MathML formulas in conventional notation. Formulas leave out the
+// magnitudes `sqrt`, `log` and `pow` act on, see `executors`.
+impl HeuristicNode {
+    // A standalone graph of the tree, children left to right in operand order
+    pub fn to_dot(&self) -> String {
+        let mut dot = String::from("digraph heuristic {\n    ordering=out;\n");
+        write_dot_node(self, &mut dot, &mut 0, "    ");
+        dot.push_str("}\n");
+        dot
+    }
+
+    pub fn to_latex(&self) -> String {
+        match self {
+            HeuristicNode::Number(num) => num.to_string(),
+            HeuristicNode::Terminal(rule) => latex_terminal(*rule).to_string(),
+            HeuristicNode::Unary(rule, h) => match rule {
+                // Bracketed like the right operand of a subtraction
+                Rule::neg => format!("-{}", h.latex_operand(h.right_operand_precedence(SUM))),
+                Rule::abs => format!("\\left|{}\\right|", h.to_latex()),
+                Rule::sqrt => format!("\\sqrt{{{}}}", h.to_latex()),
+                Rule::sqr => format!("{}^{{2}}", h.latex_base()),
+                Rule::log => format!("\\ln\\left({}\\right)", h.to_latex()),
+                Rule::exp => format!("e^{{{}}}", h.to_latex()),
+                Rule::floor => format!("\\left\\lfloor {} \\right\\rfloor", h.to_latex()),
+                Rule::ceil => format!("\\left\\lceil {} \\right\\rceil", h.to_latex()),
+                Rule::recip => format!("\\frac{{1}}{{{}}}", h.to_latex()),
+                _ => unreachable!("{:?}", rule),
+            },
+            HeuristicNode::Binary(rule, h1, h2) => match rule {
+                Rule::plus | Rule::minus | Rule::mul => {
+                    let (precedence, symbol) = match rule {
+                        Rule::plus => (SUM, "+"),
+                        Rule::minus => (SUM, "-"),
+                        _ => (PRODUCT, "\\cdot"),
+                    };
+                    format!(
+                        "{} {} {}",
+                        h1.latex_operand(precedence),
+                        symbol,
+                        h2.latex_operand(h2.right_operand_precedence(precedence))
+                    )
+                }
+                Rule::div => format!("\\frac{{{}}}{{{}}}", h1.to_latex(), h2.to_latex()),
+                Rule::max | Rule::min => format!(
+                    "\\{:?}\\left({}, {}\\right)",
+                    rule,
+                    h1.to_latex(),
+                    h2.to_latex()
+                ),
+                Rule::pow => format!("{}^{{{}}}", h1.latex_base(), h2.to_latex()),
+                _ => unreachable!("{:?}", rule),
+            },
+            HeuristicNode::Conditional(rule, h1, h2, h3, h4) => match rule {
+                Rule::ifle => format!(
+                    "\\begin{{cases}} {} & \\text{{if }} {} \\le {} \\\\ {} & \\text{{otherwise}} \\end{{cases}}",
+                    h3.to_latex(),
+                    h1.to_latex(),
+                    h2.to_latex(),
+                    h4.to_latex()
+                ),
+                _ => unreachable!("{:?}", rule),
+            },
+            HeuristicNode::Call(index, args) => format!(
+                "\\mathrm{{adf}}_{{{}}}\\left({}\\right)",
+                index,
+                args.iter()
+                    .map(|arg| arg.to_latex())
+                    .collect::<Vec<_>>()
+                    .join(", ")
+            ),
+            HeuristicNode::Argument(index) => format!("a_{{{}}}", index),
+        }
+    }
+
+    // Presentation MathML, without the enclosing `math` element
+    pub fn to_mathml(&self) -> String {
+        match self {
+            HeuristicNode::Number(num) if *num < 0.0 => {
+                format!("<mrow><mo>-</mo><mn>{}</mn></mrow>", -num)
+            }
+            HeuristicNode::Number(num) => format!("<mn>{}</mn>", num),
+            HeuristicNode::Terminal(rule) => mathml_terminal(*rule),
+            HeuristicNode::Unary(rule, h) => match rule {
+                Rule::neg => format!(
+                    "<mrow><mo>-</mo>{}</mrow>",
+                    h.mathml_operand(h.right_operand_precedence(SUM))
+                ),
+                Rule::abs => fenced("|", &h.to_mathml(), "|"),
+                Rule::sqrt => format!("<msqrt>{}</msqrt>", h.to_mathml()),
+                Rule::sqr => format!("<msup>{}<mn>2</mn></msup>", h.mathml_base()),
+                Rule::log => format!(
+                    "<mrow><mi>ln</mi><mo>&#x2061;</mo>{}</mrow>",
+                    fenced("(", &h.to_mathml(), ")")
+                ),
+                Rule::exp => format!("<msup><mi>e</mi>{}</msup>", h.to_mathml()),
+                Rule::floor => fenced("&#x230A;", &h.to_mathml(), "&#x230B;"),
+                Rule::ceil => fenced("&#x2308;", &h.to_mathml(), "&#x2309;"),
+                Rule::recip => format!("<mfrac><mn>1</mn>{}</mfrac>", h.to_mathml()),
+                _ => unreachable!("{:?}", rule),
+            },
+            HeuristicNode::Binary(rule, h1, h2) => match rule {
+                Rule::plus | Rule::minus | Rule::mul => {
+                    let (precedence, symbol) = match rule {
+                        Rule::plus => (SUM, "+"),
+                        Rule::minus => (SUM, "-"),
+                        _ => (PRODUCT, "&#x22C5;"),
+                    };
+                    format!(
+                        "<mrow>{}<mo>{}</mo>{}</mrow>",
+                        h1.mathml_operand(precedence),
+                        symbol,
+                        h2.mathml_operand(h2.right_operand_precedence(precedence))
+                    )
+                }
+                Rule::div => format!("<mfrac>{}{}</mfrac>", h1.to_mathml(), h2.to_mathml()),
+                Rule::max | Rule::min => format!(
+                    "<mrow><mi>{:?}</mi>{}</mrow>",
+                    rule,
+                    fenced(
+                        "(",
+                        &format!("{}<mo>,</mo>{}", h1.to_mathml(), h2.to_mathml()),
+                        ")"
+                    )
+                ),
+                Rule::pow => format!("<msup>{}{}</msup>", h1.mathml_base(), h2.to_mathml()),
+                _ => unreachable!("{:?}", rule),
+            },
+            HeuristicNode::Conditional(rule, h1, h2, h3, h4) => match rule {
+                Rule::ifle => format!(
+                    "<mrow><mo>{{</mo><mtable>\
+                     <mtr><mtd>{}</mtd><mtd><mtext>if&#xA0;</mtext>{}<mo>&#x2264;</mo>{}</mtd></mtr>\
+                     <mtr><mtd>{}</mtd><mtd><mtext>otherwise</mtext></mtd></mtr>\
+                     </mtable></mrow>",
+                    h3.to_mathml(),
+                    h1.to_mathml(),
+                    h2.to_mathml(),
+                    h4.to_mathml()
+                ),
+                _ => unreachable!("{:?}", rule),
+            },
+            HeuristicNode::Call(index, args) => format!(
+                "<mrow><msub><mi>adf</mi><mn>{}</mn></msub>{}</mrow>",
+                index,
+                fenced(
+                    "(",
+                    &args
+                        .iter()
+                        .map(|arg| arg.to_mathml())
+                        .collect::<Vec<_>>()
+                        .join("<mo>,</mo>"),
+                    ")"
+                )
+            ),
+            HeuristicNode::Argument(index) => {
+                format!("<msub><mi>a</mi><mn>{}</mn></msub>", index)
+            }
+        }
+    }
+
+    fn latex_operand(&self, min_precedence: u8) -> String {
+        match self.precedence() < min_precedence {
+            true => format!("\\left({}\\right)", self.to_latex()),
+            false => self.to_latex(),
+        }
+    }
+
+    fn mathml_operand(&self, min_precedence: u8) -> String {
+        match self.precedence() < min_precedence {
+            true => fenced("(", &self.to_mathml(), ")"),
+            false => self.to_mathml(),
+        }
+    }
+
+    // Operators are left-associative, so right operands are bracketed at equal
+    // precedence, and negated right operands always, so that no two signs meet
+    fn right_operand_precedence(&self, precedence: u8) -> u8 {
+        match self.precedence() {
+            NEGATION => ATOM,
+            _ => precedence + 1,
+        }
+    }
+
+    // The base of a power, bracketed unless it is atomic, as `x^2^3` is ambiguous
+    fn latex_base(&self) -> String {
+        match self.is_power() {
+            true => format!("\\left({}\\right)", self.to_latex()),
+            false => self.latex_operand(ATOM),
+        }
+    }
+
+    fn mathml_base(&self) -> String {
+        match self.is_power() {
+            true => fenced("(", &self.to_mathml(), ")"),
+            false => self.mathml_operand(ATOM),
+        }
+    }
+
+    fn is_power(&self) -> bool {
+        matches!(
+            self,
+            HeuristicNode::Unary(Rule::sqr | Rule::exp, _) | HeuristicNode::Binary(Rule::pow, _, _)
+        )
+    }
+
+    fn precedence(&self) -> u8 {
+        match self {
+            HeuristicNode::Binary(Rule::plus | Rule::minus, _, _) => SUM,
+            HeuristicNode::Binary(Rule::mul, _, _) => PRODUCT,
+            HeuristicNode::Unary(Rule::neg, _) => NEGATION,
+            HeuristicNode::Number(num) if *num < 0.0 => NEGATION,
+            _ => ATOM,
+        }
+    }
+}
+
+// How tightly the notations bind, fractions and functions being atomic
+const SUM: u8 = 1;
+const PRODUCT: u8 = 2;
+const NEGATION: u8 = 3;
+const ATOM: u8 = 4;
+
+fn fenced(open: &str, content: &str, close: &str) -> String {
+    format!(
+        "<mrow><mo>{}</mo>{}<mo>{}</mo></mrow>",
+        open, content, close
+    )
+}
+
+fn latex_terminal(rule: Rule) -> &'static str {
+    match rule {
+        Rule::x1 => "x_1",
+        Rule::y1 => "y_1",
+        Rule::x2 => "x_2",
+        Rule::y2 => "y_2",
+        Rule::deltaX => "\\Delta x",
+        Rule::deltaY => "\\Delta y",
+        Rule::manhattan => "\\mathrm{manhattan}",
+        Rule::euclid => "\\mathrm{euclid}",
+        Rule::octile => "\\mathrm{octile}",
+        Rule::cheby => "\\mathrm{cheby}",
+        Rule::lineObstacles => "\\mathrm{lineObstacles}",
+        Rule::wallDist => "\\mathrm{wallDist}",
+        Rule::sameRoom => "\\mathrm{sameRoom}",
+        Rule::rowDensity => "\\mathrm{rowDensity}",
+        Rule::colDensity => "\\mathrm{colDensity}",
+        _ => unreachable!("{:?}", rule),
+    }
+}
+
+fn mathml_terminal(rule: Rule) -> String {
+    match rule {
+        Rule::x1 | Rule::y1 | Rule::x2 | Rule::y2 => {
+            let name = format!("{:?}", rule);
+            format!(
+                "<msub><mi>{}</mi><mn>{}</mn></msub>",
+                &name[..1],
+                &name[1..]
+            )
+        }
+        Rule::deltaX => {
+            "<mrow><mi mathvariant=\"normal\">&#x394;</mi><mi>x</mi></mrow>".to_string()
+        }
+        Rule::deltaY => {
+            "<mrow><mi mathvariant=\"normal\">&#x394;</mi><mi>y</mi></mrow>".to_string()
+        }
+        // Multi-letter identifiers are set upright
+        _ => format!("<mi>{:?}</mi>", rule),
+    }
+}
+
+// Writes the subtree as nodes numbered from `next_id`, returning the id of its root
+fn write_dot_node(
+    node: &HeuristicNode,
+    dot: &mut String,
+    next_id: &mut usize,
+    indent: &str,
+) -> usize {
+    let id = *next_id;
+    *next_id += 1;
+
+    let (label, children) = match node {
+        HeuristicNode::Number(num) => (num.to_string(), Vec::new()),
+        HeuristicNode::Terminal(rule) => (format!("{:?}", rule), Vec::new()),
+        HeuristicNode::Argument(index) => (format!("arg{}", index), Vec::new()),
+        HeuristicNode::Unary(rule, h) => (format!("{:?}", rule), vec![&**h]),
+        HeuristicNode::Binary(rule, h1, h2) => (format!("{:?}", rule), vec![&**h1, &**h2]),
+        HeuristicNode::Conditional(rule, h1, h2, h3, h4) => {
+            (format!("{:?}", rule), vec![&**h1, &**h2, &**h3, &**h4])
+        }
+        HeuristicNode::Call(index, args) => (format!("adf{}", index), args.iter().collect()),
+    };
+    let shape = match children.is_empty() {
+        true => "box",
+        false => "ellipse",
+    };
+    writeln!(
+        dot,
+        "{}n{} [label=\"{}\", shape={}];",
+        indent, id, label, shape
+    )
+    .unwrap();
+
+    for child in children {
+        let child_id = write_dot_node(child, dot, next_id, indent);
+        writeln!(dot, "{}n{} -> n{};", indent, id, child_id).unwrap();
+    }
+    id
+}
+
+// A graph of the main tree, with the body of every ADF in a cluster of its own
+pub fn heuristic_to_dot(heuristic: &Heuristic) -> String {
+    let mut dot = String::from("digraph heuristic {\n    ordering=out;\n");
+    let mut next_id = 0;
+    for (index, adf) in heuristic.adfs.iter().enumerate() {
+        writeln!(dot, "    subgraph cluster_adf{} {{", index).unwrap();
+        writeln!(
+            dot,
+            "        label=\"adf{} ({} arguments)\";",
+            index, adf.arity
+        )
+        .unwrap();
+        write_dot_node(&adf.body, &mut dot, &mut next_id, "        ");
+        dot.push_str("    }\n");
+    }
+    write_dot_node(&heuristic.root, &mut dot, &mut next_id, "    ");
+    dot.push_str("}\n");
+    dot
+}
+
+// The formula of the main tree, preceded by the definitions of the ADFs if there
+// are any
+pub fn heuristic_to_latex(heuristic: &Heuristic) -> String {
+    if heuristic.adfs.is_empty() {
+        return heuristic.root.to_latex();
+    }
+
+    let mut lines = heuristic
+        .adfs
+        .iter()
+        .enumerate()
+        .map(|(index, adf)| {
+            let call =
+                HeuristicNode::Call(index, (0..adf.arity).map(HeuristicNode::Argument).collect());
+            format!("{} &= {}", call.to_latex(), adf.body.to_latex())
+        })
+        .collect::<Vec<_>>();
+    lines.push(format!("h &= {}", heuristic.root.to_latex()));
+    format!(
+        "\\begin{{aligned}} {} \\end{{aligned}}",
+        lines.join(" \\\\ ")
+    )
+}
+
+// A complete `math` element, laid out like `heuristic_to_latex`
+pub fn heuristic_to_mathml(heuristic: &Heuristic) -> String {
+    let content = match heuristic.adfs.is_empty() {
+        true => heuristic.root.to_mathml(),
+        false => {
+            let mut rows = heuristic
+                .adfs
+                .iter()
+                .enumerate()
+                .map(|(index, adf)| {
+                    let call = HeuristicNode::Call(
+                        index,
+                        (0..adf.arity).map(HeuristicNode::Argument).collect(),
+                    );
+                    (call.to_mathml(), adf.body.to_mathml())
+                })
+                .collect::<Vec<_>>();
+            rows.push(("<mi>h</mi>".to_string(), heuristic.root.to_mathml()));
+            let rows = rows
+                .into_iter()
+                .map(|(lhs, rhs)| {
+                    format!("<mtr><mtd>{}</mtd><mtd><mo>=</mo>{}</mtd></mtr>", lhs, rhs)
+                })
+                .collect::<String>();
+            format!("<mtable columnalign=\"right left\">{}</mtable>", rows)
+        }
+    };
+    format!(
+        "<math xmlns=\"http://www.w3.org/1998/Math/MathML\" display=\"block\">{}</math>",
+        content
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::heuristic::parser::parse_heuristic;
+
+    use test_case::test_case;
+
+    #[test_case("(+ deltaX deltaY)", "\\Delta x + \\Delta y")]
+    #[test_case("(max deltaX deltaY)", "\\max\\left(\\Delta x, \\Delta y\\right)")]
+    #[test_case("(/ (+ x1 1) y2)", "\\frac{x_1 + 1}{y_2}")]
+    #[test_case(
+        "(sqrt (+ (sqr deltaX) (sqr deltaY)))",
+        "\\sqrt{\\Delta x^{2} + \\Delta y^{2}}"
+    )]
+    #[test_case(
+        "(* (+ x1 x2) (- y1 y2))",
+        "\\left(x_1 + x_2\\right) \\cdot \\left(y_1 - y_2\\right)"
+    )]
+    #[test_case("(- x1 (- x2 y1))", "x_1 - \\left(x_2 - y_1\\right)")]
+    #[test_case("(- (- x1 x2) y1)", "x_1 - x_2 - y_1")]
+    #[test_case("(* -2 (neg x1))", "-2 \\cdot \\left(-x_1\\right)")]
+    #[test_case("(neg (+ x1 (neg x2)))", "-\\left(x_1 + \\left(-x_2\\right)\\right)")]
+    #[test_case("(neg (neg (* x1 2)))", "-\\left(-x_1 \\cdot 2\\right)")]
+    #[test_case("(pow (sqr x1) (+ y1 1))", "\\left(x_1^{2}\\right)^{y_1 + 1}")]
+    #[test_case("(pow (+ x1 1) 2)", "\\left(x_1 + 1\\right)^{2}")]
+    #[test_case("(ifle x1 y1 x2 cheby)", "\\begin{cases} x_2 & \\text{if } x_1 \\le y_1 \\\\ \\mathrm{cheby} & \\text{otherwise} \\end{cases}")]
+    fn test_to_latex(input: &str, expected: &str) {
+        assert_eq!(parse_heuristic(input).root.to_latex(), expected);
+    }
+
+    #[test_case("(+ deltaX 3)", "<mrow><mrow><mi mathvariant=\"normal\">&#x394;</mi><mi>x</mi></mrow><mo>+</mo><mn>3</mn></mrow>")]
+    #[test_case("(sqr x1)", "<msup><msub><mi>x</mi><mn>1</mn></msub><mn>2</mn></msup>")]
+    #[test_case(
+        "(/ 1 (neg wallDist))",
+        "<mfrac><mn>1</mn><mrow><mo>-</mo><mi>wallDist</mi></mrow></mfrac>"
+    )]
+    #[test_case("(- cheby -2)", "<mrow><mi>cheby</mi><mo>-</mo><mrow><mo>(</mo><mrow><mo>-</mo><mn>2</mn></mrow><mo>)</mo></mrow></mrow>")]
+    fn test_to_mathml(input: &str, expected: &str) {
+        assert_eq!(parse_heuristic(input).root.to_mathml(), expected);
+    }
+
+    #[test]
+    fn test_to_dot() {
+        let dot = parse_heuristic("(- x1 (abs 2))").root.to_dot();
+        assert_eq!(
+            dot,
+            "digraph heuristic {
+    ordering=out;
+    n0 [label=\"minus\", shape=ellipse];
+    n1 [label=\"x1\", shape=box];
+    n0 -> n1;
+    n2 [label=\"abs\", shape=ellipse];
+    n3 [label=\"2\", shape=box];
+    n2 -> n3;
+    n0 -> n2;
+}
+"
+        );
+    }
+
+    #[test]
+    fn test_export_adfs() {
+        let h = parse_heuristic("(defun adf0 2 (max arg0 arg1)) (adf0 deltaX deltaY)");
+        assert_eq!(
+            heuristic_to_latex(&h),
+            "\\begin{aligned} \\mathrm{adf}_{0}\\left(a_{0}, a_{1}\\right) &= \\max\\left(a_{0}, a_{1}\\right) \\\\ \
+             h &= \\mathrm{adf}_{0}\\left(\\Delta x, \\Delta y\\right) \\end{aligned}"
+        );
+
+        let dot = heuristic_to_dot(&h);
+        assert!(dot.contains("subgraph cluster_adf0 {\n        label=\"adf0 (2 arguments)\";"));
+        assert!(dot.contains("    n3 [label=\"adf0\", shape=ellipse];\n"));
+        assert_eq!(dot.matches(" -> ").count(), 4);
+
+        let mathml = heuristic_to_mathml(&h);
+        assert!(mathml.starts_with(
+            "<math xmlns=\"http://www.w3.org/1998/Math/MathML\" display=\"block\"><mtable"
+        ));
+        assert_eq!(mathml.matches("<mtr>").count(), 2);
+    }
+}
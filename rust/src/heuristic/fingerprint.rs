@@ -0,0 +1,227 @@
+#[cfg(feature = "python")]
+use pyo3::{exceptions::PyValueError, PyErr};
+use std::collections::HashMap;
+use std::fmt::Display;
+use std::sync::Arc;
+
+use super::executors::interpreter::Interpreter;
+use super::executors::HeuristicExecuter;
+use super::Heuristic;
+use crate::map::features::MapFeatures;
+use crate::map::util::{Map, Tile};
+
+// Fixed so that fingerprints are comparable across runs
+const FINGERPRINT_SEED: u64 = 651;
+pub const FINGERPRINT_SAMPLES: usize = 64;
+
+// The outputs of a heuristic on a fixed set of (x1, y1, x2, y2) samples.
+// Heuristics with equal fingerprints are treated as semantically identical.
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub struct Fingerprint(Vec<u32>);
+
+// Samples can only be drawn from maps with at least one passable tile
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct NoPassableTiles;
+
+impl Display for NoPassableTiles {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "the map has no passable tiles to sample from")
+    }
+}
+
+impl std::error::Error for NoPassableTiles {}
+
+#[cfg(feature = "python")]
+impl From<NoPassableTiles> for PyErr {
+    fn from(error: NoPassableTiles) -> Self {
+        PyValueError::new_err(error.to_string())
+    }
+}
+
+// Groups heuristics by fingerprint, remembering one value (e.g. the result of
+// solving a problem cycle) per equivalence class
+#[derive(Clone)]
+pub struct EquivalenceClasses<T> {
+    samples: Vec<(f32, f32, f32, f32)>,
+    // If set, outputs are rounded to a multiple of `quantum` before comparison
+    quantum: Option<f32>,
+    // Needed to evaluate map feature terminals
+    features: Option<Arc<MapFeatures>>,
+    classes: HashMap<Fingerprint, T>,
+}
+
+impl<T: Clone> EquivalenceClasses<T> {
+    pub fn new(
+        map: &Map,
+        num_samples: usize,
+        quantum: Option<f32>,
+    ) -> Result<EquivalenceClasses<T>, NoPassableTiles> {
+        if !map.map.contains(&Tile::Passable) {
+            return Err(NoPassableTiles);
+        }
+
+        // Use a private generator, so that sampling does not disturb a seeded run
+        let mut rng = fastrand::Rng::with_seed(FINGERPRINT_SEED);
+        let mut random_free_position = || {
+            let mut pos = rng.usize(0..map.map.len());
+            while map.map[pos] == Tile::Unpassable {
+                pos = rng.usize(0..map.map.len());
+            }
+            map.ind2sub(pos)
+        };
+
+        let samples = (0..num_samples)
+            .map(|_| {
+                let (x1, y1) = random_free_position();
+                let (x2, y2) = random_free_position();
+                (x1 as f32, y1 as f32, x2 as f32, y2 as f32)
+            })
+            .collect();
+
+        Ok(EquivalenceClasses {
+            features: Some(map.features.clone()),
+            ..EquivalenceClasses::from_samples(samples, quantum)
+        })
+    }
+
+    pub fn from_samples(
+        samples: Vec<(f32, f32, f32, f32)>,
+        quantum: Option<f32>,
+    ) -> EquivalenceClasses<T> {
+        EquivalenceClasses {
+            samples,
+            quantum,
+            features: None,
+            classes: HashMap::new(),
+        }
+    }
+
+    pub fn fingerprint(&self, heuristic: &Heuristic) -> Fingerprint {
+        let executor = match &self.features {
+            Some(features) => Interpreter::create_with_features(heuristic, features.clone()),
+            None => Interpreter::create(heuristic),
+        };
+
+        Fingerprint(
+            self.samples
+                .iter()
+                .map(|&(x1, y1, x2, y2)| {
+                    let value = executor.execute(x1, y1, x2, y2);
+                    let value = match self.quantum {
+                        Some(quantum) => (value / quantum).round(),
+                        None => value,
+                    };
+                    // Adding zero maps -0 to 0, so both land in the same class
+                    (value + 0.0).to_bits()
+                })
+                .collect(),
+        )
+    }
+
+    pub fn equivalent(&self, heuristic1: &Heuristic, heuristic2: &Heuristic) -> bool {
+        self.fingerprint(heuristic1) == self.fingerprint(heuristic2)
+    }
+
+    // The value remembered for the class of `fingerprint`, if any
+    pub fn get(&self, fingerprint: &Fingerprint) -> Option<&T> {
+        self.classes.get(fingerprint)
+    }
+
+    pub fn insert(&mut self, fingerprint: Fingerprint, value: T) {
+        self.classes.insert(fingerprint, value);
+    }
+
+    // Returns the value remembered for the class of `heuristic`, computing and
+    // remembering it with `compute` if the class has not been seen before
+    pub fn get_or_insert_with(&mut self, heuristic: &Heuristic, compute: impl FnOnce() -> T) -> T {
+        let fingerprint = self.fingerprint(heuristic);
+        self.classes
+            .entry(fingerprint)
+            .or_insert_with(compute)
+            .clone()
+    }
+
+    // The number of distinct classes seen so far
+    pub fn len(&self) -> usize {
+        self.classes.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.classes.is_empty()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::heuristic::parser::parse_heuristic;
+
+    fn open_map() -> Map {
+        Map::from(16, 16, vec![Tile::Passable; 256])
+    }
+
+    #[test]
+    fn test_fingerprint_commutative() {
+        let classes: EquivalenceClasses<()> =
+            EquivalenceClasses::new(&open_map(), FINGERPRINT_SAMPLES, None).unwrap();
+        assert!(classes.equivalent(
+            &parse_heuristic("(+ deltaX deltaY)"),
+            &parse_heuristic("(+ deltaY deltaX)")
+        ));
+        assert!(classes.equivalent(
+            &parse_heuristic("(max (abs (- x1 x2)) deltaY)"),
+            &parse_heuristic("(max deltaY deltaX)")
+        ));
+    }
+
+    #[test]
+    fn test_fingerprint_different() {
+        let classes: EquivalenceClasses<()> =
+            EquivalenceClasses::new(&open_map(), FINGERPRINT_SAMPLES, None).unwrap();
+        assert!(!classes.equivalent(
+            &parse_heuristic("(+ deltaX deltaY)"),
+            &parse_heuristic("(max deltaX deltaY)")
+        ));
+        assert!(!classes.equivalent(
+            &parse_heuristic("(+ deltaX deltaY)"),
+            &parse_heuristic("(+ deltaX (+ deltaY 0.01))")
+        ));
+    }
+
+    #[test]
+    fn test_fingerprint_quantised() {
+        let classes: EquivalenceClasses<()> =
+            EquivalenceClasses::new(&open_map(), FINGERPRINT_SAMPLES, Some(1.0)).unwrap();
+        assert!(classes.equivalent(
+            &parse_heuristic("(+ deltaX deltaY)"),
+            &parse_heuristic("(+ deltaX (+ deltaY 0.01))")
+        ));
+    }
+
+    #[test]
+    fn test_no_passable_tiles() {
+        let map = Map::from(4, 4, vec![Tile::Unpassable; 16]);
+        assert_eq!(
+            EquivalenceClasses::<()>::new(&map, FINGERPRINT_SAMPLES, None).err(),
+            Some(NoPassableTiles)
+        );
+    }
+
+    #[test]
+    fn test_get_or_insert_with() {
+        let mut classes = EquivalenceClasses::new(&open_map(), FINGERPRINT_SAMPLES, None).unwrap();
+        assert_eq!(
+            classes.get_or_insert_with(&parse_heuristic("(+ deltaX deltaY)"), || 1),
+            1
+        );
+        assert_eq!(
+            classes.get_or_insert_with(&parse_heuristic("(+ deltaY deltaX)"), || 2),
+            1
+        );
+        assert_eq!(
+            classes.get_or_insert_with(&parse_heuristic("(* deltaY deltaX)"), || 3),
+            3
+        );
+        assert_eq!(classes.len(), 2);
+    }
+}
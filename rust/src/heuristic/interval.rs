@@ -0,0 +1,677 @@
+use std::f32::consts::SQRT_2;
+use std::fmt::Display;
+
+use super::parser::{HeuristicNode, Rule};
+use super::Heuristic;
+use crate::map::features::MapFeatures;
+use crate::map::util::Map;
+
+// The number of regions `analyze` bisects the domain into before giving up on
+// proving the heuristic safe or finding a counterexample
+const MAX_REGIONS: usize = 256;
+
+// A set of values a heuristic may evaluate to: every non-NaN value in `[lo, hi]`,
+// and NaN if `nan` is set. The bounds may be infinite.
+//
+// The bounds are computed with the f32 operations the executors use. Those that
+// are correctly rounded (`+`, `-`, `*`, `/`, `sqrt`) are monotone, so applying them
+// to the bounds gives bounds on the results. The others (`exp`, `log`, `pow`) need
+// not be, so their results are widened by one ulp unless their operands are points.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Interval {
+    pub lo: f32,
+    pub hi: f32,
+    pub nan: bool,
+}
+
+impl Interval {
+    pub fn new(lo: f32, hi: f32) -> Interval {
+        Interval { lo, hi, nan: false }
+    }
+
+    pub fn point(value: f32) -> Interval {
+        match value.is_nan() {
+            true => Interval::everything(),
+            false => Interval::new(value, value),
+        }
+    }
+
+    // No value at all, which is what a union starts from
+    pub fn empty() -> Interval {
+        Interval::new(f32::INFINITY, f32::NEG_INFINITY)
+    }
+
+    pub fn everything() -> Interval {
+        Interval {
+            lo: f32::NEG_INFINITY,
+            hi: f32::INFINITY,
+            nan: true,
+        }
+    }
+
+    pub fn contains(&self, value: f32) -> bool {
+        match value.is_nan() {
+            true => self.nan,
+            false => self.lo <= value && value <= self.hi,
+        }
+    }
+
+    // Whether every value is a finite number >= 0, as search expects of a heuristic
+    pub fn is_finite_non_negative(&self) -> bool {
+        !self.nan && self.lo >= 0.0 && self.hi.is_finite()
+    }
+
+    pub fn union(&self, other: &Interval) -> Interval {
+        Interval {
+            lo: self.lo.min(other.lo),
+            hi: self.hi.max(other.hi),
+            nan: self.nan || other.nan,
+        }
+    }
+
+    fn with_nan(self, nan: bool) -> Interval {
+        Interval {
+            nan: self.nan || nan,
+            ..self
+        }
+    }
+
+    fn contains_zero(&self) -> bool {
+        self.lo <= 0.0 && 0.0 <= self.hi
+    }
+
+    fn is_unbounded(&self) -> bool {
+        self.lo.is_infinite() || self.hi.is_infinite()
+    }
+
+    // The bounds of `f` over the corners of both intervals, where a NaN corner
+    // means the bounds can not be told from the corners
+    fn corners(&self, other: &Interval, f: impl Fn(f32, f32) -> f32) -> Interval {
+        let values = [
+            f(self.lo, other.lo),
+            f(self.lo, other.hi),
+            f(self.hi, other.lo),
+            f(self.hi, other.hi),
+        ];
+        match values.iter().any(|value| value.is_nan()) {
+            true => Interval::everything(),
+            false => Interval::new(
+                values.iter().copied().fold(f32::INFINITY, f32::min),
+                values.iter().copied().fold(f32::NEG_INFINITY, f32::max),
+            ),
+        }
+    }
+
+    // Accounts for functions that are not correctly rounded, applied to `operands`
+    fn widen(self, operands: &[&Interval]) -> Interval {
+        if operands.iter().all(|operand| operand.lo == operand.hi) {
+            return self;
+        }
+
+        let widen = |value: f32, f: fn(f32) -> f32| match value.is_finite() {
+            true => f(value),
+            false => value,
+        };
+        Interval {
+            lo: widen(self.lo, f32::next_down),
+            hi: widen(self.hi, f32::next_up),
+            nan: self.nan,
+        }
+    }
+
+    fn non_negative(self) -> Interval {
+        Interval {
+            lo: self.lo.max(0.0),
+            hi: self.hi.max(0.0),
+            nan: self.nan,
+        }
+    }
+
+    pub fn neg(&self) -> Interval {
+        Interval {
+            lo: -self.hi,
+            hi: -self.lo,
+            nan: self.nan,
+        }
+    }
+
+    pub fn abs(&self) -> Interval {
+        match (self.lo >= 0.0, self.hi <= 0.0) {
+            (true, _) => *self,
+            (_, true) => self.neg(),
+            _ => Interval {
+                lo: 0.0,
+                hi: self.hi.max(-self.lo),
+                nan: self.nan,
+            },
+        }
+    }
+
+    pub fn sqrt(&self) -> Interval {
+        let sqrt = |value: f32| value.signum() * value.abs().sqrt();
+        Interval {
+            lo: sqrt(self.lo),
+            hi: sqrt(self.hi),
+            nan: self.nan,
+        }
+    }
+
+    pub fn sqr(&self) -> Interval {
+        let abs = self.abs();
+        Interval {
+            lo: abs.lo * abs.lo,
+            hi: abs.hi * abs.hi,
+            nan: self.nan,
+        }
+    }
+
+    pub fn log(&self) -> Interval {
+        let abs = self.abs();
+        Interval::new(abs.lo.ln(), abs.hi.ln())
+            .widen(&[self])
+            .with_nan(self.nan)
+    }
+
+    pub fn exp(&self) -> Interval {
+        Interval::new(self.lo.exp(), self.hi.exp())
+            .widen(&[self])
+            .non_negative()
+            .with_nan(self.nan)
+    }
+
+    pub fn floor(&self) -> Interval {
+        Interval {
+            lo: self.lo.floor(),
+            hi: self.hi.floor(),
+            nan: self.nan,
+        }
+    }
+
+    pub fn ceil(&self) -> Interval {
+        Interval {
+            lo: self.lo.ceil(),
+            hi: self.hi.ceil(),
+            nan: self.nan,
+        }
+    }
+
+    pub fn recip(&self) -> Interval {
+        Interval::point(1.0).div(self)
+    }
+
+    pub fn add(&self, other: &Interval) -> Interval {
+        // `inf + -inf`
+        let nan = (self.lo == f32::NEG_INFINITY && other.hi == f32::INFINITY)
+            || (self.hi == f32::INFINITY && other.lo == f32::NEG_INFINITY);
+        let bound = |value: f32, default: f32| match value.is_nan() {
+            true => default,
+            false => value,
+        };
+        Interval {
+            lo: bound(self.lo + other.lo, f32::NEG_INFINITY),
+            hi: bound(self.hi + other.hi, f32::INFINITY),
+            nan: self.nan || other.nan || nan,
+        }
+    }
+
+    pub fn sub(&self, other: &Interval) -> Interval {
+        self.add(&other.neg())
+    }
+
+    pub fn mul(&self, other: &Interval) -> Interval {
+        // `0 * inf`
+        let nan = (self.contains_zero() && other.is_unbounded())
+            || (self.is_unbounded() && other.contains_zero());
+        self.corners(other, |a, b| a * b)
+            .with_nan(self.nan || other.nan || nan)
+    }
+
+    pub fn div(&self, other: &Interval) -> Interval {
+        // `0 / 0` and `inf / inf`
+        let nan = (self.contains_zero() && other.contains_zero())
+            || (self.is_unbounded() && other.is_unbounded());
+        let result = match other.contains_zero() {
+            true => Interval::new(f32::NEG_INFINITY, f32::INFINITY),
+            false => self.corners(other, |a, b| a / b),
+        };
+        result.with_nan(self.nan || other.nan || nan)
+    }
+
+    // NaN is ignored in favor of the other operand
+    pub fn max(&self, other: &Interval) -> Interval {
+        self.select(other, f32::max)
+    }
+
+    pub fn min(&self, other: &Interval) -> Interval {
+        self.select(other, f32::min)
+    }
+
+    fn select(&self, other: &Interval, f: fn(f32, f32) -> f32) -> Interval {
+        let mut result = Interval {
+            lo: f(self.lo, other.lo),
+            hi: f(self.hi, other.hi),
+            nan: self.nan && other.nan,
+        };
+        if self.nan {
+            result = result.union(&Interval {
+                nan: false,
+                ..*other
+            });
+        }
+        if other.nan {
+            result = result.union(&Interval {
+                nan: false,
+                ..*self
+            });
+        }
+        result
+    }
+
+    // `pow` raises the magnitude of its base, which is monotone in the base and in
+    // the exponent, so the bounds are at the corners
+    pub fn pow(&self, other: &Interval) -> Interval {
+        self.abs()
+            .corners(other, f32::powf)
+            .widen(&[self, other])
+            .non_negative()
+            .with_nan(self.nan || other.nan)
+    }
+
+    // The comparison fails whenever either side is NaN
+    pub fn ifle(&self, other: &Interval, then: Interval, otherwise: Interval) -> Interval {
+        let may_hold = self.lo <= other.hi;
+        let may_fail = self.nan || other.nan || self.hi > other.lo;
+        match (may_hold, may_fail) {
+            (true, true) => then.union(&otherwise),
+            (true, false) => then,
+            (false, _) => otherwise,
+        }
+    }
+}
+
+impl Display for Interval {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "[{}, {}]", self.lo, self.hi)?;
+        if self.nan {
+            write!(f, " or NaN")?;
+        }
+        Ok(())
+    }
+}
+
+// A box of cells, given by the inclusive ranges of `x1`, `y1`, `x2` and `y2`
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Region {
+    pub bounds: [(usize, usize); 4],
+}
+
+impl Region {
+    // Every pair of cells of the map, unless it has none
+    pub fn of_map(map: &Map) -> Option<Region> {
+        if map.n == 0 || map.m == 0 {
+            return None;
+        }
+        let (x, y) = ((0, map.n - 1), (0, map.m - 1));
+        Some(Region {
+            bounds: [x, y, x, y],
+        })
+    }
+
+    pub fn is_point(&self) -> bool {
+        self.bounds.iter().all(|(lo, hi)| lo == hi)
+    }
+
+    pub fn num_points(&self) -> usize {
+        self.bounds.iter().map(|(lo, hi)| hi - lo + 1).product()
+    }
+
+    // Halves the widest range, unless the region is a single point
+    pub fn split(&self) -> Option<(Region, Region)> {
+        let (index, &(lo, hi)) = self
+            .bounds
+            .iter()
+            .enumerate()
+            .max_by_key(|(_, (lo, hi))| hi - lo)?;
+        if lo == hi {
+            return None;
+        }
+
+        let mid = lo + (hi - lo) / 2;
+        let (mut lower, mut upper) = (*self, *self);
+        lower.bounds[index] = (lo, mid);
+        upper.bounds[index] = (mid + 1, hi);
+        Some((lower, upper))
+    }
+
+    fn coordinate(&self, index: usize) -> Interval {
+        let (lo, hi) = self.bounds[index];
+        Interval::new(lo as f32, hi as f32)
+    }
+}
+
+impl Display for Region {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let names = ["x1", "y1", "x2", "y2"];
+        let ranges = names
+            .iter()
+            .zip(self.bounds.iter())
+            .map(|(name, (lo, hi))| match lo == hi {
+                true => format!("{} = {}", name, lo),
+                false => format!("{} in [{}, {}]", name, lo, hi),
+            })
+            .collect::<Vec<_>>();
+        write!(f, "{}", ranges.join(", "))
+    }
+}
+
+// A pair of cells where a heuristic evaluates to a value in `range`, which is not
+// finite and non-negative
+#[derive(Debug, Clone, PartialEq)]
+pub struct Counterexample {
+    pub region: Region,
+    pub range: Interval,
+}
+
+impl Display for Counterexample {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{} for {}", self.range, self.region)
+    }
+}
+
+// Why `analyze` could not prove a heuristic finite and non-negative
+#[derive(Debug, Clone, PartialEq)]
+pub enum AnalysisError {
+    Counterexample(Counterexample),
+    // The analysis gave up before finding either a proof or a counterexample, with
+    // the smallest region it failed on and the values it may take there
+    Inconclusive { region: Region, range: Interval },
+}
+
+impl Display for AnalysisError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            AnalysisError::Counterexample(counterexample) => write!(f, "{}", counterexample),
+            AnalysisError::Inconclusive { region, range } => {
+                write!(f, "inconclusive, {} for {}", range, region)
+            }
+        }
+    }
+}
+
+// Proves the heuristic finite and non-negative on every pair of cells of the map,
+// returning the values it may take, or finds a pair where it is not
+pub fn analyze(heuristic: &Heuristic, map: &Map) -> Result<Interval, AnalysisError> {
+    let full = match Region::of_map(map) {
+        Some(region) => region,
+        None => return Ok(Interval::empty()),
+    };
+    let node = heuristic.inlined();
+    let range = evaluate_interval(&node, &full, &map.features);
+    if range.is_finite_non_negative() {
+        return Ok(range);
+    }
+
+    // Bisect the regions the analysis fails on, depth first, to narrow the failure
+    // down to a point. Regions where it succeeds need not be looked at again.
+    let (mut smallest, mut smallest_range) = (full, range);
+    let mut proven = Interval::empty();
+    let mut stack = vec![full];
+    for _ in 0..MAX_REGIONS {
+        let region = match stack.pop() {
+            Some(region) => region,
+            // The regions the heuristic was proven on cover the map
+            None => return Ok(proven),
+        };
+        let range = evaluate_interval(&node, &region, &map.features);
+        if range.is_finite_non_negative() {
+            proven = proven.union(&range);
+            continue;
+        }
+
+        match region.split() {
+            Some((lower, upper)) => stack.extend([upper, lower]),
+            // Intervals of points are exact
+            None => {
+                return Err(AnalysisError::Counterexample(Counterexample {
+                    region,
+                    range,
+                }))
+            }
+        }
+        if region.num_points() < smallest.num_points() {
+            (smallest, smallest_range) = (region, range);
+        }
+    }
+
+    match stack.is_empty() {
+        true => Ok(proven),
+        false => Err(AnalysisError::Inconclusive {
+            region: smallest,
+            range: smallest_range,
+        }),
+    }
+}
+
+// The values a (call free) heuristic may take for any pair of cells in the region
+pub fn evaluate_interval(
+    node: &HeuristicNode,
+    region: &Region,
+    features: &MapFeatures,
+) -> Interval {
+    let evaluate = |h: &HeuristicNode| evaluate_interval(h, region, features);
+
+    match node {
+        HeuristicNode::Number(num) => Interval::point(*num),
+        HeuristicNode::Terminal(rule) => evaluate_terminal(*rule, region, features),
+        HeuristicNode::Unary(rule, h) => {
+            let h = evaluate(h);
+            match rule {
+                Rule::neg => h.neg(),
+                Rule::abs => h.abs(),
+                Rule::sqrt => h.sqrt(),
+                Rule::sqr => h.sqr(),
+                Rule::log => h.log(),
+                Rule::exp => h.exp(),
+                Rule::floor => h.floor(),
+                Rule::ceil => h.ceil(),
+                Rule::recip => h.recip(),
+                _ => unreachable!("{:?}", rule),
+            }
+        }
+        HeuristicNode::Binary(rule, h1, h2) => {
+            let (h1, h2) = (evaluate(h1), evaluate(h2));
+            match rule {
+                Rule::plus => h1.add(&h2),
+                Rule::minus => h1.sub(&h2),
+                Rule::mul => h1.mul(&h2),
+                Rule::div => h1.div(&h2),
+                Rule::max => h1.max(&h2),
+                Rule::min => h1.min(&h2),
+                Rule::pow => h1.pow(&h2),
+                _ => unreachable!("{:?}", rule),
+            }
+        }
+        HeuristicNode::Conditional(rule, h1, h2, h3, h4) => match rule {
+            Rule::ifle => evaluate(h1).ifle(&evaluate(h2), evaluate(h3), evaluate(h4)),
+            _ => unreachable!("{:?}", rule),
+        },
+        HeuristicNode::Call(..) | HeuristicNode::Argument(_) => {
+            unreachable!("ADF calls are inlined before analysis")
+        }
+    }
+}
+
+fn evaluate_terminal(rule: Rule, region: &Region, features: &MapFeatures) -> Interval {
+    let [x1, y1, x2, y2] = [0, 1, 2, 3].map(|index| region.coordinate(index));
+    let delta_x = x2.sub(&x1).abs();
+    let delta_y = y2.sub(&y1).abs();
+
+    match rule {
+        Rule::x1 => x1,
+        Rule::y1 => y1,
+        Rule::x2 => x2,
+        Rule::y2 => y2,
+        Rule::deltaX => delta_x,
+        Rule::deltaY => delta_y,
+        Rule::manhattan => delta_x.add(&delta_y),
+        Rule::euclid => delta_x.sqr().add(&delta_y.sqr()).sqrt(),
+        Rule::octile => {
+            let diagonal = Interval::point(SQRT_2 - 1.0).mul(&delta_x.min(&delta_y));
+            delta_x.max(&delta_y).add(&diagonal)
+        }
+        Rule::cheby => delta_x.max(&delta_y),
+        Rule::lineObstacles => match region.is_point() {
+            true => Interval::point(features.line_obstacles(x1.lo, y1.lo, x2.lo, y2.lo)),
+            // A line visits at most one cell per row or column
+            false => Interval::new(0.0, features.n.max(features.m) as f32),
+        },
+        Rule::wallDist => match x1.lo == x1.hi && y1.lo == y1.hi {
+            true => Interval::point(features.wall_distance(x1.lo, y1.lo)),
+            false => table_range(&features.wall_distances),
+        },
+        Rule::sameRoom => match region.is_point() {
+            true => Interval::point(features.same_room(x1.lo, y1.lo, x2.lo, y2.lo)),
+            false => Interval::new(0.0, 1.0),
+        },
+        Rule::rowDensity => {
+            let (lo, hi) = region.bounds[0];
+            table_range(&features.row_densities[lo..=hi])
+        }
+        Rule::colDensity => {
+            let (lo, hi) = region.bounds[1];
+            table_range(&features.column_densities[lo..=hi])
+        }
+        _ => unreachable!("{:?}", rule),
+    }
+}
+
+fn table_range(table: &[f32]) -> Interval {
+    Interval::new(
+        table.iter().copied().fold(f32::INFINITY, f32::min),
+        table.iter().copied().fold(f32::NEG_INFINITY, f32::max),
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::heuristic::executors::interpreter::Interpreter;
+    use crate::heuristic::executors::{clamp_heuristic_value, HeuristicExecuter};
+    use crate::heuristic::mutate_probs::TermProbabilities;
+    use crate::heuristic::parser::parse_heuristic;
+    use crate::heuristic::util::random_heuristic;
+    use crate::map::util::Tile;
+
+    use test_case::test_case;
+
+    #[test_case("manhattan", 0.0, 7.0)]
+    #[test_case("(+ (sqr x1) y2)", 0.0, 13.0)]
+    #[test_case("(ifle deltaX 0 1 deltaX)", 1.0, 3.0)]
+    #[test_case("(max (- x1 x2) 0)", 0.0, 3.0)]
+    #[test_case("(/ manhattan (+ 1 euclid))", 0.0, 7.0)]
+    #[test_case("(pow 2 (neg deltaY))", 0.0625, 1.0)]
+    #[test_case("(* rowDensity sameRoom)", 0.0, 0.2)]
+    fn test_analyze_safe(input: &str, lo: f32, hi: f32) {
+        let range = analyze(&parse_heuristic(input), &Map::walled()).unwrap();
+        assert!(range.lo <= lo && lo <= range.hi, "{}", range);
+        assert!(range.lo <= hi && hi <= range.hi, "{}", range);
+        assert!(!range.nan);
+    }
+
+    #[test_case("(/ x1 (- x2 x2))")]
+    #[test_case("(- x1 x2)")]
+    #[test_case("(log deltaX)")]
+    #[test_case("(recip (- manhattan 3))")]
+    #[test_case("(- (exp (* 100 x1)) (exp (* 100 y1)))")]
+    #[test_case("(ifle x1 2 x1 (neg wallDist))")]
+    fn test_analyze_counterexample(input: &str) {
+        let map = Map::walled();
+        let heuristic = parse_heuristic(input);
+        let counterexample = match analyze(&heuristic, &map) {
+            Err(AnalysisError::Counterexample(counterexample)) => counterexample,
+            other => panic!("{:?}", other),
+        };
+        assert!(counterexample.region.is_point(), "{}", counterexample);
+
+        let [x1, y1, x2, y2] = counterexample.region.bounds.map(|(lo, _)| lo as f32);
+        let value = Interpreter::create_with_features(&heuristic, map.features.clone())
+            .execute(x1, y1, x2, y2);
+        // Negative values are reported as 0, NaN and infinities as `f32::MAX`
+        assert!(value == 0.0 || value == f32::MAX);
+    }
+
+    // Safe, but only once the difference is bisected down to single values of `x1`
+    // and `x2`, which the map is too large for
+    #[test_case(Map::from(3, 3, vec![Tile::Passable; 3 * 3]), true)]
+    #[test_case(Map::from(64, 64, vec![Tile::Passable; 64 * 64]), false)]
+    fn test_analyze_bisected(map: Map, proven: bool) {
+        match analyze(&parse_heuristic("(- manhattan deltaX)"), &map) {
+            Ok(range) => assert!(proven && range.is_finite_non_negative(), "{}", range),
+            Err(AnalysisError::Inconclusive { region, .. }) => {
+                assert!(!proven && !region.is_point(), "{}", region)
+            }
+            Err(error) => panic!("{}", error),
+        }
+    }
+
+    #[test]
+    fn test_analyze_empty_map() {
+        let map = Map::from(0, 0, Vec::new());
+        assert_eq!(
+            analyze(&parse_heuristic("(- x1 x2)"), &map),
+            Ok(Interval::empty())
+        );
+    }
+
+    #[test]
+    fn test_region_split() {
+        let region = Region {
+            bounds: [(0, 3), (2, 2), (0, 9), (5, 6)],
+        };
+        let (lower, upper) = region.split().unwrap();
+        assert_eq!(lower.bounds[2], (0, 4));
+        assert_eq!(upper.bounds[2], (5, 9));
+        assert_eq!(lower.num_points() + upper.num_points(), region.num_points());
+        assert_eq!(
+            Region {
+                bounds: [(1, 1); 4]
+            }
+            .split(),
+            None
+        );
+    }
+
+    // Every value the interpreter computes lies in the interval of the region, once
+    // clamped like the value is
+    #[test]
+    fn test_interval_sound() {
+        let map = Map::walled();
+        let region = Region {
+            bounds: [(0, 2), (1, 4), (1, 3), (0, 2)],
+        };
+        let term_probs = Some(TermProbabilities::new(true).with_feature_terminals());
+        for _ in 0..500 {
+            let root = random_heuristic(fastrand::i32(1..=20), &term_probs);
+            let range = evaluate_interval(&root, &region, &map.features);
+            let (lo, hi) = (
+                clamp_heuristic_value(range.lo),
+                clamp_heuristic_value(range.hi),
+            );
+            let executor =
+                Interpreter::create_with_features(&Heuristic::new(root), map.features.clone());
+
+            for _ in 0..20 {
+                let [x1, y1, x2, y2] = region
+                    .bounds
+                    .map(|(lo, hi)| fastrand::usize(lo..=hi) as f32);
+                let value = executor.execute(x1, y1, x2, y2);
+                assert!(
+                    (lo <= value && value <= hi) || (value == f32::MAX && range.nan),
+                    "{} not in {}",
+                    value,
+                    range
+                );
+            }
+        }
+    }
+}
@@ -1,35 +1,80 @@
+pub mod adf;
+pub mod archive;
+pub mod codegen;
 pub mod executors;
+pub mod export;
+pub mod fingerprint;
+pub mod interval;
 pub mod mutator;
+pub mod optimize;
 pub mod parser;
+pub mod simplify;
 pub mod util;
 pub mod mutate_probs;
 
+use std::borrow::Borrow;
+use std::collections::HashMap;
+use std::fmt::Display;
 use std::time::{Duration, SystemTime, UNIX_EPOCH};
 
+#[cfg(feature = "python")]
 use pyo3::prelude::*;
 
-use parser::HeuristicNode;
+use adf::{inline_calls, Adf};
+use codegen::MissingFeatureTables;
+use parser::{HeuristicNode, Rule};
+use util::heuristic_size;
+
+use crate::map::util::Map;
 
 #[derive(Debug, Clone, Eq, PartialEq)]
-#[pyclass]
+#[cfg_attr(feature = "python", pyclass)]
 pub struct Heuristic {
     pub root: HeuristicNode,
+    // The ADFs the main tree may call, see `adf::Adf`
+    pub adfs: Vec<Adf>,
     pub creation: Duration,
+    // The textual forms of the heuristics this one was derived from, and the
+    // generation of the `GeneticAlgorithm` it was created in
+    pub parents: Vec<String>,
+    pub generation: usize,
     size: usize,
 }
 
 impl Heuristic {
     pub fn new(root: HeuristicNode) -> Heuristic {
-        let size = heuristic_node_size(root.clone());
+        Self::with_adfs(root, Vec::new())
+    }
+
+    pub fn with_adfs(root: HeuristicNode, adfs: Vec<Adf>) -> Heuristic {
+        let size = heuristic_node_size(&root, &adfs);
         Heuristic {
             root,
+            adfs,
             creation: SystemTime::now()
                 .duration_since(UNIX_EPOCH)
                 .expect("Backwards time??? :O"),
+            parents: Vec::new(),
+            generation: 0,
             size,
         }
     }
 
+    // Records `parent` as the only parent of this heuristic
+    pub fn derived_from(mut self, parent: &Heuristic, generation: usize) -> Heuristic {
+        self.parents = vec![parent.to_string()];
+        self.generation = generation;
+        self
+    }
+
+    // The main tree with every ADF call inlined
+    pub fn inlined(&self) -> HeuristicNode {
+        match self.adfs.is_empty() {
+            true => self.root.clone(),
+            false => inline_calls(&self.root, &self.adfs),
+        }
+    }
+
     pub fn root(&self) -> &HeuristicNode {
         &self.root
     }
@@ -37,13 +82,118 @@ impl Heuristic {
     pub fn size(&self) -> usize {
         self.size
     }
+
+    // Like `size`, but structurally identical subtrees are counted once, as they
+    // can be bound to a single name and are computed only once by the `Jit`
+    pub fn shared_size(&self) -> usize {
+        let mut subtrees = HashMap::new();
+        collect_subtrees(&self.root, &mut subtrees);
+
+        // Arguments are only distinct within the ADF they belong to
+        let mut size = subtrees.len();
+        for adf in &self.adfs {
+            let mut adf_subtrees = HashMap::new();
+            collect_subtrees(&adf.body, &mut adf_subtrees);
+            size += adf_subtrees.len();
+        }
+        size
+    }
+}
+
+#[cfg_attr(feature = "python", pymethods)]
+impl Heuristic {
+    #[cfg(feature = "python")]
+    fn __str__(&self) -> String {
+        self.to_string()
+    }
+
+    // The infix syntax has no definitions, so ADF calls are inlined
+    pub fn to_infix(&self) -> String {
+        self.inlined().to_infix()
+    }
+
+    // See `export` for the notations
+    pub fn to_dot(&self) -> String {
+        export::heuristic_to_dot(self)
+    }
+
+    pub fn to_latex(&self) -> String {
+        export::heuristic_to_latex(self)
+    }
+
+    pub fn to_mathml(&self) -> String {
+        export::heuristic_to_mathml(self)
+    }
+
+    // See `codegen`. Heuristics using map feature terminals need the map they are
+    // generated for, which their tables are copied from.
+    pub fn to_c(&self, name: &str, map: Option<&Map>) -> Result<String, MissingFeatureTables> {
+        codegen::heuristic_to_c(self, name, map.map(|map| map.features.as_ref()))
+    }
+
+    pub fn to_rust(&self, name: &str, map: Option<&Map>) -> Result<String, MissingFeatureTables> {
+        codegen::heuristic_to_rust(self, name, map.map(|map| map.features.as_ref()))
+    }
 }
 
-fn heuristic_node_size(node: HeuristicNode) -> usize {
-    match node {
-        HeuristicNode::Number(_) => 1,
-        HeuristicNode::Terminal(_) => 1,
-        HeuristicNode::Unary(_, h) => 1 + heuristic_node_size(*h),
-        HeuristicNode::Binary(_, h1, h2) => 1 + heuristic_node_size(*h1) + heuristic_node_size(*h2),
+// The ADF definitions, one per line, followed by the main tree
+impl Display for Heuristic {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        for (index, adf) in self.adfs.iter().enumerate() {
+            writeln!(f, "(defun adf{} {} {})", index, adf.arity, adf.body)?;
+        }
+        write!(f, "{}", self.root)
     }
 }
+
+// Every node of the main tree and of each ADF body, which is counted once however
+// often it is called
+fn heuristic_node_size(node: &HeuristicNode, adfs: &[Adf]) -> usize {
+    let size = heuristic_size(node)
+        + adfs
+            .iter()
+            .map(|adf| heuristic_size(&adf.body))
+            .sum::<i32>();
+    size as usize
+}
+
+// A subtree whose children are replaced by the numbers of their subtrees, so that
+// it is hashed in constant time
+#[derive(PartialEq, Eq, Hash)]
+enum Subtree {
+    Number(u32),
+    Terminal(Rule),
+    Argument(usize),
+    Operator(Rule, Vec<usize>),
+    Call(usize, Vec<usize>),
+}
+
+// Numbers the distinct subtrees of `node` bottom up, returning the number of `node`
+fn collect_subtrees(node: &HeuristicNode, subtrees: &mut HashMap<Subtree, usize>) -> usize {
+    let subtree = match node {
+        HeuristicNode::Number(num) => Subtree::Number(num.to_bits()),
+        HeuristicNode::Terminal(rule) => Subtree::Terminal(*rule),
+        HeuristicNode::Argument(index) => Subtree::Argument(*index),
+        HeuristicNode::Unary(rule, h) => Subtree::Operator(*rule, collect_all([h], subtrees)),
+        HeuristicNode::Binary(rule, h1, h2) => {
+            Subtree::Operator(*rule, collect_all([h1, h2], subtrees))
+        }
+        HeuristicNode::Conditional(rule, h1, h2, h3, h4) => {
+            Subtree::Operator(*rule, collect_all([h1, h2, h3, h4], subtrees))
+        }
+        HeuristicNode::Call(index, args) => Subtree::Call(*index, collect_all(args, subtrees)),
+    };
+
+    let next = subtrees.len();
+    *subtrees.entry(subtree).or_insert(next)
+}
+
+fn collect_all<'a, T: Borrow<HeuristicNode> + 'a>(
+    nodes: impl IntoIterator<Item = &'a T>,
+    subtrees: &mut HashMap<Subtree, usize>,
+) -> Vec<usize> {
+    nodes
+        .into_iter()
+        .map(|node| collect_subtrees(node.borrow(), subtrees))
+        .collect()
+}
@@ -1,10 +1,21 @@
+#[cfg(feature = "python")]
 use pyo3::prelude::*;
 
-use crate::heuristic::util::normalize_vector;
+use crate::heuristic::parser::Rule;
+use crate::heuristic::util::{
+    normalize_vector, BINARIES, CONDITIONALS, FEATURE_TERMINALS, TERMINALS, UNARIES,
+};
 use std::collections::HashMap;
 
+// The number of operators and terminals before the grammar was extended, which
+// the probabilities saved by older runs still have
+const LEGACY_NUM_BINARIES: usize = 6;
+const LEGACY_NUM_UNARIES: usize = 4;
+const LEGACY_NUM_TERMINALS: usize = 6;
+
 #[derive(Clone, Copy, Eq, PartialEq, Hash)]
 pub enum Term {
+    Conditional,
     Binary,
     Unary,
     Terminal,
@@ -14,6 +25,7 @@ pub enum Term {
 impl Term {
     pub fn from_str(s: &str) -> Term {
         match s {
+            "conditionals" => Term::Conditional,
             "binaries" => Term::Binary,
             "unaries" => Term::Unary,
             "terminals" => Term::Terminal,
@@ -26,6 +38,7 @@ impl Term {
 
     pub fn to_str(self: &Self) -> &str {
         match self {
+            Term::Conditional => "conditionals",
             Term::Binary => "binaries",
             Term::Unary => "unaries",
             Term::Terminal => "terminals",
@@ -35,79 +48,154 @@ impl Term {
 }
 
 #[derive(Clone)]
-#[pyclass]
+#[cfg_attr(feature = "python", pyclass(get_all))]
 pub struct TermProbabilities {
-    #[pyo3(get)]
+    pub conditionals: Vec<f64>,
     pub binaries: Vec<f64>,
-    #[pyo3(get)]
     pub unaries: Vec<f64>,
-    #[pyo3(get)]
     pub terminals: Vec<f64>,
-    #[pyo3(get)]
     pub numbers: Vec<f64>,
+    // The constants sampled by `numbers`, one per probability
+    pub number_values: Vec<f32>,
 }
 
 impl TermProbabilities {
     pub fn new(uniform: bool) -> TermProbabilities {
+        TermProbabilities::with_number_values(uniform, TermProbabilities::default_number_values())
+    }
+
+    pub fn with_number_values(uniform: bool, number_values: Vec<f32>) -> TermProbabilities {
+        if let Err(message) = TermProbabilities::check_number_values(&number_values) {
+            panic!("{}", message);
+        }
+
         let mut num_terms = HashMap::new();
-        num_terms.insert(Term::Binary, 6);
-        num_terms.insert(Term::Unary, 4);
-        num_terms.insert(Term::Terminal, 6);
-        num_terms.insert(Term::Number, 9); // 1 to 9
+        num_terms.insert(Term::Conditional, CONDITIONALS.len() as i32);
+        num_terms.insert(Term::Binary, BINARIES.len() as i32);
+        num_terms.insert(Term::Unary, UNARIES.len() as i32);
+        num_terms.insert(Term::Terminal, TERMINALS.len() as i32);
+        num_terms.insert(Term::Number, number_values.len() as i32);
 
         match uniform {
             true => TermProbabilities {
+                conditionals: TermProbabilities::uniform_vector(num_terms[&Term::Conditional]),
                 binaries: TermProbabilities::uniform_vector(num_terms[&Term::Binary]),
                 unaries: TermProbabilities::uniform_vector(num_terms[&Term::Unary]),
                 terminals: TermProbabilities::uniform_vector(num_terms[&Term::Terminal]),
                 numbers: TermProbabilities::uniform_vector(num_terms[&Term::Number]),
+                number_values,
             },
             false => TermProbabilities {
+                conditionals: TermProbabilities::random_vector(num_terms[&Term::Conditional]),
                 binaries: TermProbabilities::random_vector(num_terms[&Term::Binary]),
                 unaries: TermProbabilities::random_vector(num_terms[&Term::Unary]),
                 terminals: TermProbabilities::random_vector(num_terms[&Term::Terminal]),
                 numbers: TermProbabilities::random_vector(num_terms[&Term::Number]),
+                number_values,
             },
         }
     }
 
-    pub fn from_hashmap(hashmap: HashMap<String, Vec<f64>>) -> TermProbabilities {
-        let mut result = TermProbabilities {
-            binaries: Vec::new(),
-            unaries: Vec::new(),
-            terminals: Vec::new(),
-            numbers: Vec::new(),
-        };
+    // Also samples the map feature terminals, each as likely as the average of
+    // the other terminals
+    pub fn with_feature_terminals(mut self) -> TermProbabilities {
+        if self.terminals.len() < TERMINALS.len() + FEATURE_TERMINALS.len() {
+            let average = self.terminals.iter().sum::<f64>() / self.terminals.len() as f64;
+            self.terminals
+                .resize(TERMINALS.len() + FEATURE_TERMINALS.len(), average);
+            normalize_vector(&mut self.terminals);
+        }
+        self
+    }
 
-        assert!(hashmap.len() == 4, "Invalid hashmap length");
-
-        for (key, value) in hashmap {
-            match key.as_str() {
-                "binaries" => assert!(value.len() == 6, "Invalid binaries vector length"),
-                "unaries" => assert!(value.len() == 4, "Invalid unaries vector length"),
-                "terminals" => assert!(value.len() == 6, "Invalid terminals vector length"),
-                "numbers" => assert!(value.len() == 9, "Invalid numbers vector length"),
-                _ => {
-                    unreachable!("Invalid key '{}' in hashmap", key);
-                }
-            }
+    // The terminals sampled by `terminals`, one per probability
+    pub fn terminal_rules(&self) -> Vec<Rule> {
+        TERMINALS
+            .iter()
+            .chain(FEATURE_TERMINALS.iter())
+            .take(self.terminals.len())
+            .copied()
+            .collect()
+    }
 
-            match key.as_str() {
-                "binaries" => result.binaries = value,
-                "unaries" => result.unaries = value,
-                "terminals" => result.terminals = value,
-                "numbers" => result.numbers = value,
-                _ => {
-                    unreachable!("Invalid key '{}' in hashmap", key);
-                }
-            }
+    // Why `number_values` can not be sampled from, if they can not
+    pub fn check_number_values(number_values: &[f32]) -> Result<(), String> {
+        if number_values.is_empty() {
+            return Err("number_values must not be empty".to_string());
+        }
+        if !number_values.iter().all(|n| n.is_finite()) {
+            return Err("number_values must be finite".to_string());
         }
+        Ok(())
+    }
 
-        result
+    // The integers 1 to 9, which were the only constants before the grammar
+    // supported arbitrary numbers
+    pub fn default_number_values() -> Vec<f32> {
+        (1..=9).map(|n| n as f32).collect()
+    }
+
+    // Reads probabilities as written by `probabilities2dict`. Those written before
+    // some operators were added lack them, which are then never sampled
+    pub fn from_hashmap(
+        mut hashmap: HashMap<String, Vec<f64>>,
+    ) -> Result<TermProbabilities, String> {
+        let number_values: Vec<f32> = match hashmap.remove("number_values") {
+            Some(values) => values.into_iter().map(|v| v as f32).collect(),
+            None => TermProbabilities::default_number_values(),
+        };
+        TermProbabilities::check_number_values(&number_values)?;
+
+        let conditionals = match hashmap.remove("conditionals") {
+            Some(value) => value,
+            None => TermProbabilities::uniform_vector(CONDITIONALS.len() as i32),
+        };
+        if conditionals.len() != CONDITIONALS.len() {
+            return Err("Invalid conditionals vector length".to_string());
+        }
+
+        let result = TermProbabilities {
+            conditionals,
+            binaries: take_vector(
+                &mut hashmap,
+                "binaries",
+                &[LEGACY_NUM_BINARIES, BINARIES.len()],
+                BINARIES.len(),
+            )?,
+            unaries: take_vector(
+                &mut hashmap,
+                "unaries",
+                &[LEGACY_NUM_UNARIES, UNARIES.len()],
+                UNARIES.len(),
+            )?,
+            terminals: take_vector(
+                &mut hashmap,
+                "terminals",
+                &[
+                    LEGACY_NUM_TERMINALS,
+                    TERMINALS.len(),
+                    TERMINALS.len() + FEATURE_TERMINALS.len(),
+                ],
+                TERMINALS.len(),
+            )?,
+            numbers: take_vector(
+                &mut hashmap,
+                "numbers",
+                &[number_values.len()],
+                number_values.len(),
+            )?,
+            number_values,
+        };
+
+        match hashmap.keys().next() {
+            Some(key) => Err(format!("Invalid key '{}' in hashmap", key)),
+            None => Ok(result),
+        }
     }
 
     pub fn get(self: &Self, t: Term) -> &Vec<f64> {
         match t {
+            Term::Conditional => &self.conditionals,
             Term::Binary => &self.binaries,
             Term::Unary => &self.unaries,
             Term::Terminal => &self.terminals,
@@ -133,11 +221,22 @@ impl TermProbabilities {
     }
 
     pub fn crossover(&self, other: &Self) -> Self {
+        assert!(
+            self.number_values == other.number_values,
+            "Cannot crossover probabilities over different number values"
+        );
+        assert!(
+            self.terminals.len() == other.terminals.len(),
+            "Cannot crossover probabilities over different terminals"
+        );
+
         let mut result = TermProbabilities {
+            conditionals: Vec::new(),
             binaries: Vec::new(),
             unaries: Vec::new(),
             terminals: Vec::new(),
             numbers: Vec::new(),
+            number_values: self.number_values.clone(),
         };
 
         // Create lambda function to sum two vectors then normalize
@@ -154,6 +253,7 @@ impl TermProbabilities {
 
         // Sum the probabilities of each mutation type, then re-normalize so
         // that sum(vec) = 1
+        result.conditionals = sum_normalize(&self.conditionals, &other.conditionals);
         result.binaries = sum_normalize(&self.binaries, &other.binaries);
         result.unaries = sum_normalize(&self.unaries, &other.unaries);
         result.terminals = sum_normalize(&self.terminals, &other.terminals);
@@ -167,6 +267,11 @@ impl TermProbabilities {
 
         // With probability 'mut_prob', change the value of each element in
         // every vector to a random number between 0 and 1
+        for i in 0..copy.conditionals.len() {
+            if fastrand::f64() < mut_prob {
+                copy.conditionals[i] = fastrand::f64();
+            }
+        }
         for i in 0..copy.binaries.len() {
             if fastrand::f64() < mut_prob {
                 copy.binaries[i] = fastrand::f64();
@@ -189,6 +294,7 @@ impl TermProbabilities {
         }
 
         // Re-normalize so that sum(vec) = 1
+        normalize_vector(&mut copy.conditionals);
         normalize_vector(&mut copy.binaries);
         normalize_vector(&mut copy.unaries);
         normalize_vector(&mut copy.terminals);
@@ -199,16 +305,102 @@ impl TermProbabilities {
 
     pub fn get_operator_order(&self, operators: &str) -> Vec<String> {
         let result = match operators {
-            "binaries" => vec!["plus", "div", "mul", "minus", "max", "min"],
-            "unaries" => vec!["neg", "abs", "sqrt", "sqr"],
-            "terminals" => vec!["x1", "x2", "y1", "y2", "deltaX", "deltaY"],
-            "numbers" => vec!["1", "2", "3", "4", "5", "6", "7", "8", "9"],
+            "conditionals" => CONDITIONALS.to_vec(),
+            "binaries" => BINARIES.to_vec(),
+            "unaries" => UNARIES.to_vec(),
+            "terminals" => self.terminal_rules(),
+            "numbers" => {
+                return self.number_values.iter().map(|n| n.to_string()).collect();
+            }
             _ => unreachable!(
                 "Invalid operator type '{}' in get_operator_order",
                 operators
             ),
         };
 
-        result.iter().map(|s| s.to_string()).collect()
+        result.iter().map(|rule| format!("{:?}", rule)).collect()
+    }
+}
+
+// Removes the probabilities under `key`, padded with zeros up to `min_len`
+fn take_vector(
+    hashmap: &mut HashMap<String, Vec<f64>>,
+    key: &str,
+    valid_lengths: &[usize],
+    min_len: usize,
+) -> Result<Vec<f64>, String> {
+    match hashmap.remove(key) {
+        Some(mut value) if valid_lengths.contains(&value.len()) => {
+            if value.len() < min_len {
+                value.resize(min_len, 0.0);
+            }
+            Ok(value)
+        }
+        Some(_) => Err(format!("Invalid {} vector length", key)),
+        None => Err(format!("Missing '{}' in hashmap", key)),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn legacy_hashmap() -> HashMap<String, Vec<f64>> {
+        let mut hashmap = HashMap::new();
+        hashmap.insert("binaries".to_string(), vec![1.0 / 6.0; 6]);
+        hashmap.insert("unaries".to_string(), vec![0.25; 4]);
+        hashmap.insert("terminals".to_string(), vec![1.0 / 6.0; 6]);
+        hashmap.insert("numbers".to_string(), vec![1.0 / 9.0; 9]);
+        hashmap
+    }
+
+    // Probabilities saved before conditionals and the other operators and terminals
+    // were added
+    #[test]
+    fn test_from_hashmap_legacy() {
+        let probs = TermProbabilities::from_hashmap(legacy_hashmap()).unwrap();
+        assert_eq!(probs.conditionals, vec![1.0]);
+        assert_eq!(probs.binaries.len(), BINARIES.len());
+        assert_eq!(probs.binaries[LEGACY_NUM_BINARIES..], [0.0]);
+        assert_eq!(probs.unaries.len(), UNARIES.len());
+        assert_eq!(probs.terminals.len(), TERMINALS.len());
+        assert!(probs.terminals[LEGACY_NUM_TERMINALS..]
+            .iter()
+            .all(|&p| p == 0.0));
+    }
+
+    #[test]
+    fn test_from_hashmap_failure() {
+        let mut hashmap = legacy_hashmap();
+        hashmap.insert("terminals".to_string(), vec![0.5; 2]);
+        assert_eq!(
+            TermProbabilities::from_hashmap(hashmap).err(),
+            Some("Invalid terminals vector length".to_string())
+        );
+
+        let mut hashmap = legacy_hashmap();
+        hashmap.insert("binary".to_string(), vec![1.0]);
+        assert_eq!(
+            TermProbabilities::from_hashmap(hashmap).err(),
+            Some("Invalid key 'binary' in hashmap".to_string())
+        );
+
+        let mut hashmap = legacy_hashmap();
+        hashmap.remove("numbers");
+        assert!(TermProbabilities::from_hashmap(hashmap).is_err());
+    }
+
+    #[test]
+    fn test_with_feature_terminals() {
+        let probs = TermProbabilities::new(true).with_feature_terminals();
+        assert_eq!(
+            probs.terminals.len(),
+            TERMINALS.len() + FEATURE_TERMINALS.len()
+        );
+        assert_eq!(probs.terminal_rules()[TERMINALS.len()..], FEATURE_TERMINALS);
+        assert!(probs
+            .terminals
+            .iter()
+            .all(|&p| (p - probs.terminals[0]).abs() < 1e-12));
     }
 }
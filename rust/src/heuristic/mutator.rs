@@ -1,16 +1,63 @@
 use super::{
+    adf::{Adf, Scope},
     parser::HeuristicNode,
-    util::{heuristic_size, random_heuristic}, mutate_probs::TermProbabilities,
+    util::{heuristic_size, random_heuristic_in}, mutate_probs::TermProbabilities,
+    Heuristic,
 };
 use crate::constants::*;
 
 pub fn mutate_heuristic(heuristic: &HeuristicNode, term_probs: &Option<TermProbabilities>) -> HeuristicNode {
+    mutate_heuristic_in(heuristic, term_probs, &Scope::default(), MAX_TREE_SIZE)
+}
+
+// Mutates either the main tree or one of the ADF bodies, chosen in proportion to
+// their sizes. ADFs keep their arity, so every call stays valid.
+pub fn mutate(heuristic: &Heuristic, term_probs: &Option<TermProbabilities>) -> Heuristic {
+    let mut sizes = vec![heuristic_size(heuristic.root())];
+    sizes.extend(heuristic.adfs.iter().map(|adf| heuristic_size(&adf.body)));
+
+    let mut choice = fastrand::i32(0..sizes.iter().sum::<i32>());
+    let target = sizes
+        .iter()
+        .position(|&size| {
+            choice -= size;
+            choice < 0
+        })
+        .unwrap();
+
+    let mut adfs = heuristic.adfs.clone();
+    match target {
+        0 => {
+            let scope = Scope { adfs: &adfs, arity: 0 };
+            let root = mutate_heuristic_in(heuristic.root(), term_probs, &scope, MAX_TREE_SIZE);
+            Heuristic::with_adfs(root, adfs)
+        }
+        _ => {
+            let index = target - 1;
+            let scope = Scope::of_adf(&heuristic.adfs, index);
+            adfs[index] = Adf {
+                arity: scope.arity,
+                body: mutate_heuristic_in(&adfs[index].body, term_probs, &scope, MAX_ADF_SIZE),
+            };
+            Heuristic::with_adfs(heuristic.root().clone(), adfs)
+        }
+    }
+}
+
+// Like `mutate_heuristic`, but new subtrees may call the ADFs in `scope` and read
+// its parameters, and the result has at most `max_size` nodes
+pub fn mutate_heuristic_in(
+    heuristic: &HeuristicNode,
+    term_probs: &Option<TermProbabilities>,
+    scope: &Scope,
+    max_size: i32,
+) -> HeuristicNode {
     // Since there is no guarantee that mutation will occur on the first call,
     // we loop until the heuristic is actually mutated
     let mut_prob = 1.0 / (heuristic_size(&heuristic) as f32);
 
     loop {
-        let (new_heuristic, mutated) = mutate_heuristic_helper(heuristic, mut_prob, term_probs, MAX_TREE_SIZE);
+        let (new_heuristic, mutated) = mutate_heuristic_helper(heuristic, mut_prob, term_probs, scope, max_size);
         if mutated {
             break new_heuristic;
         }
@@ -21,6 +68,7 @@ fn mutate_heuristic_helper(
     heuristic: &HeuristicNode,
     mut_prob: f32,
     term_probs: &Option<TermProbabilities>,
+    scope: &Scope,
     max_possible_tree_size: i32,
 ) -> (HeuristicNode, bool) {
     // Sample the new tree size to result in a maximum tree size of MAX_TREE_SIZE
@@ -30,19 +78,20 @@ fn mutate_heuristic_helper(
     // => Mutate iff X ~ Unif[0, 1] <= 1 / hsize
 
     match mut_prob >= fastrand::f32() {
-        true => (random_heuristic(new_tree_size, term_probs), true),
+        true => (random_heuristic_in(new_tree_size, term_probs, scope), true),
         false => match heuristic {
-            HeuristicNode::Number(_) => (random_heuristic(new_tree_size, term_probs), false),
-            HeuristicNode::Terminal(_) => (random_heuristic(new_tree_size, term_probs), false),
+            HeuristicNode::Number(_) => (random_heuristic_in(new_tree_size, term_probs, scope), false),
+            HeuristicNode::Terminal(_) => (random_heuristic_in(new_tree_size, term_probs, scope), false),
+            HeuristicNode::Argument(_) => (random_heuristic_in(new_tree_size, term_probs, scope), false),
             HeuristicNode::Unary(rule, h) => {
                 let (new_h, mutated) =
-                    mutate_heuristic_helper(h, mut_prob, term_probs, max_possible_tree_size - 1);
+                    mutate_heuristic_helper(h, mut_prob, term_probs, scope, max_possible_tree_size - 1);
                 (HeuristicNode::Unary(*rule, Box::new(new_h)), mutated)
             }
             HeuristicNode::Binary(rule, h1, h2) => {
                 let right_size = heuristic_size(h2);
                 let (new_h, mutated) =
-                    mutate_heuristic_helper(h1, mut_prob, term_probs, max_possible_tree_size - right_size - 1);
+                    mutate_heuristic_helper(h1, mut_prob, term_probs, scope, max_possible_tree_size - right_size - 1);
 
                 if mutated {
                     return (
@@ -53,12 +102,71 @@ fn mutate_heuristic_helper(
 
                 let left_size = heuristic_size(h1);
                 let (new_h, mutated) =
-                    mutate_heuristic_helper(h2, mut_prob, term_probs, max_possible_tree_size - left_size - 1);
+                    mutate_heuristic_helper(h2, mut_prob, term_probs, scope, max_possible_tree_size - left_size - 1);
                 (
                     HeuristicNode::Binary(*rule, h1.clone(), Box::new(new_h)),
                     mutated,
                 )
             }
+            HeuristicNode::Conditional(rule, h1, h2, h3, h4) => {
+                let mut operands = [h1, h2, h3, h4].map(|h| (**h).clone());
+                let mutated = mutate_operands(&mut operands, mut_prob, term_probs, scope, max_possible_tree_size);
+
+                let [h1, h2, h3, h4] = operands.map(Box::new);
+                (HeuristicNode::Conditional(*rule, h1, h2, h3, h4), mutated)
+            }
+            HeuristicNode::Call(index, args) => {
+                let mut args = args.clone();
+                let mutated = mutate_operands(&mut args, mut_prob, term_probs, scope, max_possible_tree_size);
+                (HeuristicNode::Call(*index, args), mutated)
+            }
         },
     }
 }
+
+// Mutates the first operand that accepts a mutation, leaving room for the others
+fn mutate_operands(
+    operands: &mut [HeuristicNode],
+    mut_prob: f32,
+    term_probs: &Option<TermProbabilities>,
+    scope: &Scope,
+    max_possible_tree_size: i32,
+) -> bool {
+    let total_size: i32 = operands.iter().map(heuristic_size).sum();
+
+    for operand in operands.iter_mut() {
+        let other_size = total_size - heuristic_size(operand);
+        let (new_h, mutated) =
+            mutate_heuristic_helper(operand, mut_prob, term_probs, scope, max_possible_tree_size - other_size - 1);
+        if mutated {
+            *operand = new_h;
+            return true;
+        }
+    }
+    false
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::heuristic::adf::random_adfs;
+    use crate::heuristic::parser::parse_heuristic;
+
+    #[test]
+    fn test_mutate_keeps_calls_valid() {
+        let mut h = parse_heuristic(
+            "(defun adf0 2 (max arg0 arg1))
+             (defun adf1 1 (adf0 arg0 1))
+             (adf1 deltaX)",
+        );
+        h.adfs.extend(random_adfs(1, &None));
+
+        for _ in 0..200 {
+            h = mutate(&h, &None);
+            assert_eq!(h.adfs.len(), 3);
+            assert!(heuristic_size(h.root()) <= MAX_TREE_SIZE);
+            // Parsing checks every call and parameter against its scope
+            assert_eq!(parse_heuristic(&h.to_string()).to_string(), h.to_string());
+        }
+    }
+}
@@ -0,0 +1,244 @@
+use super::adf::Adf;
+use super::parser::HeuristicNode;
+use super::Heuristic;
+
+// Tunes the numeric leaves of a heuristic (including those in its ADF bodies) while
+// keeping its tree structure fixed.
+// This is a pattern search: every constant in turn is moved one step up or down
+// while that improves the objective, and the step is halved whenever a full sweep
+// over all constants finds no improvement.
+#[derive(Debug, Clone)]
+pub struct ConstantOptimizer {
+    pub initial_step: f32,
+    // The search stops once the step falls below this
+    pub min_step: f32,
+    // The maximum number of objective evaluations, including the initial one
+    pub max_evaluations: usize,
+}
+
+impl Default for ConstantOptimizer {
+    fn default() -> Self {
+        ConstantOptimizer {
+            initial_step: 1.0,
+            min_step: 0.125,
+            max_evaluations: 50,
+        }
+    }
+}
+
+impl ConstantOptimizer {
+    pub fn new(initial_step: f32, min_step: f32, max_evaluations: usize) -> ConstantOptimizer {
+        assert!(initial_step > 0.0 && min_step > 0.0);
+        ConstantOptimizer {
+            initial_step,
+            min_step,
+            max_evaluations,
+        }
+    }
+
+    // Minimizes `objective`, returning the best heuristic found and its objective
+    pub fn optimize(
+        &self,
+        heuristic: &Heuristic,
+        mut objective: impl FnMut(&Heuristic) -> f64,
+    ) -> (Heuristic, f64) {
+        let mut best = heuristic_constants(heuristic);
+        let mut best_value = objective(heuristic);
+        let mut best_heuristic = heuristic.clone();
+        let mut evaluations = 1;
+        let mut step = self.initial_step;
+
+        while !best.is_empty() && step >= self.min_step {
+            let mut improved = false;
+
+            for i in 0..best.len() {
+                for candidate in [best[i] + step, best[i] - step] {
+                    if evaluations >= self.max_evaluations {
+                        return (best_heuristic, best_value);
+                    }
+                    // Constants must stay representable in the grammar
+                    if !candidate.is_finite() {
+                        continue;
+                    }
+
+                    let mut values = best.clone();
+                    values[i] = candidate;
+                    let h = heuristic_with_constants(heuristic, &values);
+                    let value = objective(&h);
+                    evaluations += 1;
+
+                    if value < best_value {
+                        (best, best_value, best_heuristic) = (values, value, h);
+                        improved = true;
+                        break;
+                    }
+                }
+            }
+
+            if !improved {
+                step /= 2.0;
+            }
+        }
+
+        (best_heuristic, best_value)
+    }
+}
+
+// The constants of the main tree followed by those of each ADF body
+fn heuristic_constants(heuristic: &Heuristic) -> Vec<f32> {
+    let mut result = constants(heuristic.root());
+    for adf in &heuristic.adfs {
+        result.extend(constants(&adf.body));
+    }
+    result
+}
+
+fn heuristic_with_constants(heuristic: &Heuristic, values: &[f32]) -> Heuristic {
+    let (root_values, mut values) = values.split_at(constants(heuristic.root()).len());
+    let adfs = heuristic
+        .adfs
+        .iter()
+        .map(|adf| {
+            let (body_values, rest) = values.split_at(constants(&adf.body).len());
+            values = rest;
+            Adf {
+                arity: adf.arity,
+                body: with_constants(&adf.body, body_values),
+            }
+        })
+        .collect();
+    Heuristic::with_adfs(with_constants(heuristic.root(), root_values), adfs)
+}
+
+// The numeric leaves of a heuristic, in pre-order
+pub fn constants(heuristic: &HeuristicNode) -> Vec<f32> {
+    let mut result = Vec::new();
+    collect_constants(heuristic, &mut result);
+    result
+}
+
+fn collect_constants(heuristic: &HeuristicNode, result: &mut Vec<f32>) {
+    match heuristic {
+        HeuristicNode::Number(num) => result.push(*num),
+        HeuristicNode::Terminal(_) | HeuristicNode::Argument(_) => {}
+        HeuristicNode::Unary(_, h) => collect_constants(h, result),
+        HeuristicNode::Binary(_, h1, h2) => {
+            collect_constants(h1, result);
+            collect_constants(h2, result);
+        }
+        HeuristicNode::Conditional(_, h1, h2, h3, h4) => {
+            for h in [h1, h2, h3, h4] {
+                collect_constants(h, result);
+            }
+        }
+        HeuristicNode::Call(_, args) => {
+            for arg in args {
+                collect_constants(arg, result);
+            }
+        }
+    }
+}
+
+// Replaces the numeric leaves of a heuristic, in the order returned by `constants`
+pub fn with_constants(heuristic: &HeuristicNode, values: &[f32]) -> HeuristicNode {
+    let mut values = values.iter();
+    let result = replace_constants(heuristic, &mut values);
+    assert!(values.next().is_none(), "Too many constants");
+    result
+}
+
+fn replace_constants<'a>(
+    heuristic: &HeuristicNode,
+    values: &mut impl Iterator<Item = &'a f32>,
+) -> HeuristicNode {
+    let mut replace = |h: &HeuristicNode| Box::new(replace_constants(h, values));
+
+    match heuristic {
+        HeuristicNode::Number(_) => {
+            HeuristicNode::Number(*values.next().expect("Too few constants"))
+        }
+        HeuristicNode::Terminal(_) | HeuristicNode::Argument(_) => heuristic.clone(),
+        HeuristicNode::Unary(rule, h) => HeuristicNode::Unary(*rule, replace(h)),
+        HeuristicNode::Binary(rule, h1, h2) => {
+            HeuristicNode::Binary(*rule, replace(h1), replace(h2))
+        }
+        HeuristicNode::Conditional(rule, h1, h2, h3, h4) => {
+            HeuristicNode::Conditional(*rule, replace(h1), replace(h2), replace(h3), replace(h4))
+        }
+        HeuristicNode::Call(index, args) => HeuristicNode::Call(
+            *index,
+            args.iter()
+                .map(|arg| replace_constants(arg, values))
+                .collect(),
+        ),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::heuristic::executors::interpreter::Interpreter;
+    use crate::heuristic::executors::HeuristicExecuter;
+    use crate::heuristic::parser::parse_heuristic;
+
+    #[test]
+    fn test_constants_round_trip() {
+        let h = parse_heuristic("(ifle x1 2 (+ 3 (neg -4)) (max 0.5 deltaY))");
+        assert_eq!(constants(h.root()), vec![2.0, 3.0, -4.0, 0.5]);
+
+        let replaced = with_constants(h.root(), &[1.0, 2.0, 3.0, 4.0]);
+        assert_eq!(
+            replaced,
+            parse_heuristic("(ifle x1 1 (+ 2 (neg 3)) (max 4 deltaY))").root
+        );
+    }
+
+    #[test]
+    fn test_optimize_finds_target() {
+        // The best constant makes the heuristic evaluate to 7.25 at the origin
+        let objective = |h: &Heuristic| {
+            let value = Interpreter::create(h).execute(0.0, 0.0, 0.0, 0.0);
+            ((value - 7.25) as f64).abs()
+        };
+        let optimizer = ConstantOptimizer::new(1.0, 0.25, 100);
+        let (best, value) = optimizer.optimize(&parse_heuristic("(+ deltaX 3)"), objective);
+
+        assert_eq!(best.root, parse_heuristic("(+ deltaX 7.25)").root);
+        assert_eq!(value, 0.0);
+    }
+
+    #[test]
+    fn test_optimize_respects_budget() {
+        let mut evaluations = 0;
+        let optimizer = ConstantOptimizer::new(1.0, 0.001, 10);
+        let (best, _) = optimizer.optimize(&parse_heuristic("(* 2 (+ x1 9))"), |h| {
+            evaluations += 1;
+            constants(h.root()).iter().sum::<f32>() as f64
+        });
+
+        assert_eq!(evaluations, 10);
+        assert_eq!(constants(best.root()), vec![0.0, 7.0]);
+    }
+
+    #[test]
+    fn test_optimize_adf_constants() {
+        // The only constant is in the ADF body
+        let objective = |h: &Heuristic| {
+            let value = Interpreter::create(h).execute(0.0, 0.0, 0.0, 0.0);
+            ((value - 6.0) as f64).abs()
+        };
+        let h = parse_heuristic("(defun adf0 1 (+ arg0 2)) (adf0 deltaX)");
+        let (best, value) = ConstantOptimizer::new(1.0, 0.25, 100).optimize(&h, objective);
+
+        assert_eq!(value, 0.0);
+        assert_eq!(best.to_string(), "(defun adf0 1 (plus arg0 6))\n(adf0 deltaX)");
+    }
+
+    #[test]
+    fn test_optimize_without_constants() {
+        let h = parse_heuristic("(+ deltaX deltaY)");
+        let (best, value) = ConstantOptimizer::default().optimize(&h, |_| 1.0);
+        assert_eq!(best.root, h.root);
+        assert_eq!(value, 1.0);
+    }
+}
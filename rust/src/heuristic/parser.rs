@@ -1,71 +1,550 @@
-use pest::iterators::Pairs;
-use pest::Parser;
+use pest::error::{Error, ErrorVariant, InputLocation, LineColLocation};
+use pest::iterators::{Pair, Pairs};
+use pest::pratt_parser::{Assoc, Op, PrattParser};
+use pest::{Parser, Position, Span};
 use pest_derive::Parser;
+#[cfg(feature = "python")]
+use pyo3::exceptions::PyValueError;
+#[cfg(feature = "python")]
+use pyo3::PyErr;
 use std::fmt::Display;
+use std::fs;
 use std::hash::Hash;
 
+use super::adf::{Adf, Scope};
 use super::Heuristic;
 
 #[derive(Parser)]
 #[grammar = "heuristic/grammar/heuristic.pest"]
 struct HeuristicParser;
 
-#[derive(Debug, Clone, PartialEq, Eq)]
+#[derive(Debug, Clone)]
 pub enum HeuristicNode {
-    Number(i32),
+    Number(f32),
     Terminal(Rule),
     Unary(Rule, Box<HeuristicNode>),
     Binary(Rule, Box<HeuristicNode>, Box<HeuristicNode>),
+    // The compared operands followed by the two branches
+    Conditional(
+        Rule,
+        Box<HeuristicNode>,
+        Box<HeuristicNode>,
+        Box<HeuristicNode>,
+        Box<HeuristicNode>,
+    ),
+    // A call to the ADF at the given index of the heuristic, with its arguments
+    Call(usize, Vec<HeuristicNode>),
+    // The parameter at the given index of the ADF this is the body of
+    Argument(usize),
+}
+
+// Numbers are compared by their bits, like they are hashed, so that equality is
+// total and -0 and 0 are distinct heuristics
+impl PartialEq for HeuristicNode {
+    fn eq(&self, other: &Self) -> bool {
+        match (self, other) {
+            (HeuristicNode::Number(n1), HeuristicNode::Number(n2)) => n1.to_bits() == n2.to_bits(),
+            (HeuristicNode::Terminal(r1), HeuristicNode::Terminal(r2)) => r1 == r2,
+            (HeuristicNode::Unary(r1, h1), HeuristicNode::Unary(r2, h2)) => r1 == r2 && h1 == h2,
+            (HeuristicNode::Binary(r1, h1, h2), HeuristicNode::Binary(r2, h3, h4)) => {
+                r1 == r2 && h1 == h3 && h2 == h4
+            }
+            (
+                HeuristicNode::Conditional(r1, h1, h2, h3, h4),
+                HeuristicNode::Conditional(r2, h5, h6, h7, h8),
+            ) => r1 == r2 && h1 == h5 && h2 == h6 && h3 == h7 && h4 == h8,
+            (HeuristicNode::Call(i1, args1), HeuristicNode::Call(i2, args2)) => {
+                i1 == i2 && args1 == args2
+            }
+            (HeuristicNode::Argument(i1), HeuristicNode::Argument(i2)) => i1 == i2,
+            _ => false,
+        }
+    }
 }
 
+impl Eq for HeuristicNode {}
+
 // Pretty printing for heuristics
 impl Display for HeuristicNode {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
         match self {
-            HeuristicNode::Number(num) => write!(f, "{:?}", num),
+            HeuristicNode::Number(num) => write!(f, "{}", num),
             HeuristicNode::Terminal(rule) => write!(f, "{:?}", rule),
             HeuristicNode::Unary(rule, h) => write!(f, "({:?} {})", rule, h),
             HeuristicNode::Binary(rule, h1, h2) => write!(f, "({:?} {} {})", rule, h1, h2),
+            HeuristicNode::Conditional(rule, h1, h2, h3, h4) => {
+                write!(f, "({:?} {} {} {} {})", rule, h1, h2, h3, h4)
+            }
+            HeuristicNode::Call(index, args) => {
+                write!(f, "(adf{}", index)?;
+                for arg in args {
+                    write!(f, " {}", arg)?;
+                }
+                write!(f, ")")
+            }
+            HeuristicNode::Argument(index) => write!(f, "arg{}", index),
+        }
+    }
+}
+
+// Pretty printing for heuristics in infix notation, e.g. `max(deltaX, deltaY) * 9 + sqrt(x1)`
+impl HeuristicNode {
+    pub fn to_infix(&self) -> String {
+        match self {
+            HeuristicNode::Number(num) => num.to_string(),
+            HeuristicNode::Terminal(rule) => format!("{:?}", rule),
+            HeuristicNode::Unary(Rule::neg, h) => match **h {
+                // `-3` would parse back as a negative literal, not a negation
+                HeuristicNode::Number(_) => format!("-({})", h.to_infix()),
+                _ => format!("-{}", h.infix_operand(self.infix_precedence())),
+            },
+            HeuristicNode::Unary(Rule::abs, h) => format!("|{}|", h.to_infix()),
+            HeuristicNode::Unary(rule, h) => format!("{:?}({})", rule, h.to_infix()),
+            HeuristicNode::Binary(rule @ (Rule::max | Rule::min | Rule::pow), h1, h2) => {
+                format!("{:?}({}, {})", rule, h1.to_infix(), h2.to_infix())
+            }
+            HeuristicNode::Binary(rule, h1, h2) => {
+                // Operators are left-associative, so only the right operand needs
+                // brackets at equal precedence
+                let precedence = self.infix_precedence();
+                format!(
+                    "{} {} {}",
+                    h1.infix_operand(precedence),
+                    infix_symbol(*rule),
+                    h2.infix_operand(precedence + 1)
+                )
+            }
+            HeuristicNode::Conditional(rule, h1, h2, h3, h4) => format!(
+                "{:?}({}, {}, {}, {})",
+                rule,
+                h1.to_infix(),
+                h2.to_infix(),
+                h3.to_infix(),
+                h4.to_infix()
+            ),
+            HeuristicNode::Call(index, args) => format!(
+                "adf{}({})",
+                index,
+                args.iter()
+                    .map(|arg| arg.to_infix())
+                    .collect::<Vec<_>>()
+                    .join(", ")
+            ),
+            HeuristicNode::Argument(index) => format!("arg{}", index),
+        }
+    }
+
+    fn infix_operand(&self, min_precedence: u8) -> String {
+        match self.infix_precedence() < min_precedence {
+            true => format!("({})", self.to_infix()),
+            false => self.to_infix(),
+        }
+    }
+
+    fn infix_precedence(&self) -> u8 {
+        match self {
+            HeuristicNode::Binary(Rule::plus | Rule::minus, _, _) => 1,
+            HeuristicNode::Binary(Rule::mul | Rule::div, _, _) => 2,
+            HeuristicNode::Unary(Rule::neg, _) => 3,
+            _ => 4,
+        }
+    }
+}
+
+fn infix_symbol(rule: Rule) -> &'static str {
+    match rule {
+        Rule::plus => "+",
+        Rule::minus => "-",
+        Rule::mul => "*",
+        Rule::div => "/",
+        _ => {
+            unreachable!("{:?}", rule);
         }
     }
 }
 
 impl Hash for HeuristicNode {
     fn hash<H: std::hash::Hasher>(&self, state: &mut H) {
-        self.to_string().hash(state);
+        std::mem::discriminant(self).hash(state);
+        match self {
+            HeuristicNode::Number(num) => num.to_bits().hash(state),
+            HeuristicNode::Terminal(rule) => rule.hash(state),
+            HeuristicNode::Unary(rule, h) => {
+                rule.hash(state);
+                h.hash(state);
+            }
+            HeuristicNode::Binary(rule, h1, h2) => {
+                rule.hash(state);
+                h1.hash(state);
+                h2.hash(state);
+            }
+            HeuristicNode::Conditional(rule, h1, h2, h3, h4) => {
+                rule.hash(state);
+                h1.hash(state);
+                h2.hash(state);
+                h3.hash(state);
+                h4.hash(state);
+            }
+            HeuristicNode::Call(index, args) => {
+                index.hash(state);
+                args.hash(state);
+            }
+            HeuristicNode::Argument(index) => index.hash(state),
+        }
+    }
+}
+
+// A heuristic that could not be parsed, along with where and why
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct HeuristicParseError {
+    // Byte offsets (start, end) of the offending input
+    pub span: (usize, usize),
+    // 1-based (line, column) of the start of the offending input
+    pub line_col: (usize, usize),
+    // The tokens the parser would have accepted at `span`
+    pub expected: Vec<String>,
+    // Human-readable diagnostic, with the offending input underlined by a caret
+    pub message: String,
+}
+
+impl From<Error<Rule>> for HeuristicParseError {
+    fn from(error: Error<Rule>) -> Self {
+        let span = match error.location {
+            InputLocation::Pos(pos) => (pos, pos),
+            InputLocation::Span(span) => span,
+        };
+        let line_col = match error.line_col {
+            LineColLocation::Pos(pos) => pos,
+            LineColLocation::Span(start, _) => start,
+        };
+        let expected = match &error.variant {
+            ErrorVariant::ParsingError { positives, .. } => {
+                positives.iter().map(|rule| format!("{:?}", rule)).collect()
+            }
+            ErrorVariant::CustomError { .. } => Vec::new(),
+        };
+
+        HeuristicParseError {
+            span,
+            line_col,
+            expected,
+            message: error.to_string(),
+        }
+    }
+}
+
+impl HeuristicParseError {
+    fn at_span(span: Span, message: String) -> Self {
+        Error::new_from_span(ErrorVariant::CustomError { message }, span).into()
+    }
+
+    // Prefixes the diagnostic with the file the heuristic was read from
+    pub fn with_path(mut self, path: &str) -> Self {
+        self.message = format!(
+            "{}:{}:{}\n{}",
+            path, self.line_col.0, self.line_col.1, self.message
+        );
+        self
+    }
+}
+
+impl Display for HeuristicParseError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}", self.message)
+    }
+}
+
+impl std::error::Error for HeuristicParseError {}
+
+#[cfg(feature = "python")]
+impl From<HeuristicParseError> for PyErr {
+    fn from(error: HeuristicParseError) -> Self {
+        PyValueError::new_err(error.message)
     }
 }
 
 pub fn parse_heuristic(input: &str) -> Heuristic {
-    let result = HeuristicParser::parse(Rule::heuristic, input).unwrap_or_else(|e| panic!("{}", e));
-    Heuristic::new(pairs2struct(result))
+    try_parse_heuristic(input).unwrap_or_else(|e| panic!("{}", e))
+}
+
+pub fn parse_heuristic_file(path: &str) -> Heuristic {
+    // Read the contents of the heuristic file
+    let contents =
+        fs::read_to_string(path).unwrap_or_else(|_| panic!("Heuristic file {path} doesn't exist!"));
+
+    try_parse_heuristic(&contents).unwrap_or_else(|e| panic!("{}", e.with_path(path)))
+}
+
+pub fn try_parse_heuristic(input: &str) -> Result<Heuristic, HeuristicParseError> {
+    let mut adfs = Vec::new();
+
+    for pair in HeuristicParser::parse(Rule::input, input)? {
+        match pair.as_rule() {
+            Rule::definition => adfs.push(definition2struct(pair, &adfs)?),
+            Rule::heuristic => {
+                let scope = Scope {
+                    adfs: &adfs,
+                    arity: 0,
+                };
+                let root = pairs2struct(pair, &scope, &[])?;
+                return Ok(Heuristic::with_adfs(root, adfs));
+            }
+            _ => {}
+        }
+    }
+
+    Err(HeuristicParseError::at_span(
+        Position::from_start(input).span(&Position::from_start(input)),
+        "empty heuristic".to_string(),
+    ))
 }
 
-fn pairs2struct(result: Pairs<Rule>) -> HeuristicNode {
-    let mut pairs = result.peek().unwrap().into_inner();
-    let operator = pairs.next().unwrap();
+pub fn parse_infix_heuristic(input: &str) -> Heuristic {
+    try_parse_infix_heuristic(input).unwrap_or_else(|e| panic!("{}", e))
+}
+
+pub fn try_parse_infix_heuristic(input: &str) -> Result<Heuristic, HeuristicParseError> {
+    let pair =
+        HeuristicParser::parse(Rule::infix_input, input)?.find(|pair| pair.as_rule() == Rule::expr);
+
+    // Standard arithmetic precedence, with negation binding tightest
+    let pratt = PrattParser::new()
+        .op(Op::infix(Rule::add, Assoc::Left) | Op::infix(Rule::sub, Assoc::Left))
+        .op(Op::infix(Rule::times, Assoc::Left) | Op::infix(Rule::over, Assoc::Left))
+        .op(Op::prefix(Rule::negate));
+
+    match pair {
+        Some(pair) => Ok(Heuristic::new(infix2struct(pair.into_inner(), &pratt)?)),
+        None => Err(HeuristicParseError::at_span(
+            Position::from_start(input).span(&Position::from_start(input)),
+            "empty heuristic".to_string(),
+        )),
+    }
+}
+
+fn infix2struct(
+    pairs: Pairs<Rule>,
+    pratt: &PrattParser<Rule>,
+) -> Result<HeuristicNode, HeuristicParseError> {
+    pratt
+        .map_primary(|primary| infix_primary2struct(primary, pratt))
+        .map_prefix(|operator, h| match operator.as_rule() {
+            Rule::negate => Ok(HeuristicNode::Unary(Rule::neg, Box::new(h?))),
+            other => Err(unexpected(operator, other)),
+        })
+        .map_infix(|h1, operator, h2| {
+            let rule = match operator.as_rule() {
+                Rule::add => Rule::plus,
+                Rule::sub => Rule::minus,
+                Rule::times => Rule::mul,
+                Rule::over => Rule::div,
+                other => return Err(unexpected(operator, other)),
+            };
+            Ok(HeuristicNode::Binary(rule, Box::new(h1?), Box::new(h2?)))
+        })
+        .parse(pairs)
+}
+
+fn infix_primary2struct(
+    primary: Pair<Rule>,
+    pratt: &PrattParser<Rule>,
+) -> Result<HeuristicNode, HeuristicParseError> {
+    let span = primary.as_span();
+    let rule = primary.as_rule();
+    let malformed = || HeuristicParseError::at_span(span, "malformed heuristic".to_string());
+
+    match rule {
+        Rule::number => number2struct(primary),
+        Rule::terminal => match primary.into_inner().next() {
+            Some(inner) => Ok(HeuristicNode::Terminal(inner.as_rule())),
+            None => Err(malformed()),
+        },
+        Rule::dx => Ok(HeuristicNode::Terminal(Rule::deltaX)),
+        Rule::dy => Ok(HeuristicNode::Terminal(Rule::deltaY)),
+        Rule::expr => infix2struct(primary.into_inner(), pratt),
+        Rule::abs_bars | Rule::unary_call | Rule::binary_call | Rule::conditional_call => {
+            let mut pairs = primary.into_inner();
+            let operator = match rule {
+                Rule::abs_bars => Rule::abs,
+                Rule::unary_call | Rule::conditional_call => pairs
+                    .next()
+                    .and_then(|operator| operator.into_inner().next())
+                    .ok_or_else(malformed)?
+                    .as_rule(),
+                _ => pairs.next().ok_or_else(malformed)?.as_rule(),
+            };
+            let mut operand = || match pairs.next() {
+                Some(inner) => infix2struct(inner.into_inner(), pratt).map(Box::new),
+                None => Err(malformed()),
+            };
+
+            match rule {
+                Rule::conditional_call => Ok(HeuristicNode::Conditional(
+                    operator,
+                    operand()?,
+                    operand()?,
+                    operand()?,
+                    operand()?,
+                )),
+                Rule::binary_call => Ok(HeuristicNode::Binary(operator, operand()?, operand()?)),
+                _ => Ok(HeuristicNode::Unary(operator, operand()?)),
+            }
+        }
+        other => Err(unexpected(primary, other)),
+    }
+}
+
+fn unexpected(pair: Pair<Rule>, rule: Rule) -> HeuristicParseError {
+    HeuristicParseError::at_span(pair.as_span(), format!("unexpected {:?}", rule))
+}
+
+fn number2struct(pair: Pair<Rule>) -> Result<HeuristicNode, HeuristicParseError> {
+    match pair.as_str().parse::<f32>() {
+        Ok(num) if num.is_finite() => Ok(HeuristicNode::Number(num)),
+        Ok(_) => Err(HeuristicParseError::at_span(
+            pair.as_span(),
+            "number out of range".to_string(),
+        )),
+        Err(e) => Err(HeuristicParseError::at_span(
+            pair.as_span(),
+            format!("invalid number: {}", e),
+        )),
+    }
+}
+
+// ADFs must be defined in order, as `adf0`, `adf1`, ..., and may only call the
+// ADFs defined before them
+fn definition2struct(pair: Pair<Rule>, adfs: &[Adf]) -> Result<Adf, HeuristicParseError> {
+    let span = pair.as_span();
+    let mut pairs = pair.into_inner();
+    let malformed = || HeuristicParseError::at_span(span, "malformed definition".to_string());
+
+    let name = pairs.next().ok_or_else(malformed)?;
+    if index2struct(&name, "adf")? != adfs.len() {
+        return Err(HeuristicParseError::at_span(
+            name.as_span(),
+            format!("expected `adf{}`", adfs.len()),
+        ));
+    }
+    let arity = index2struct(&pairs.next().ok_or_else(malformed)?, "")?;
+    let scope = Scope { adfs, arity };
+    let body = pairs2struct(pairs.next().ok_or_else(malformed)?, &scope, &[])?;
+
+    Ok(Adf { arity, body })
+}
+
+// The number following `prefix`, e.g. 3 for `adf3`
+fn index2struct(pair: &Pair<Rule>, prefix: &str) -> Result<usize, HeuristicParseError> {
+    pair.as_str()[prefix.len()..]
+        .parse::<usize>()
+        .map_err(|e| HeuristicParseError::at_span(pair.as_span(), format!("invalid index: {}", e)))
+}
+
+// Let-bindings are substituted into their body, so a bound value becomes a subtree
+// repeated at every use. The `Jit` computes such repeated subtrees once, and
+// `Heuristic::shared_size`, which the fitness is penalized by, counts them once.
+fn pairs2struct(
+    pair: Pair<Rule>,
+    scope: &Scope,
+    bindings: &[(&str, HeuristicNode)],
+) -> Result<HeuristicNode, HeuristicParseError> {
+    let span = pair.as_span();
+    let mut pairs = pair.into_inner();
+    let malformed = || HeuristicParseError::at_span(span, "malformed heuristic".to_string());
+
+    let operator = pairs.next().ok_or_else(malformed)?;
+    let operator_rule = |operator: Pair<Rule>| match operator.into_inner().next() {
+        Some(inner) => Ok(inner.as_rule()),
+        None => Err(malformed()),
+    };
+    let mut operand = || match pairs.next() {
+        Some(inner) => pairs2struct(inner, scope, bindings).map(Box::new),
+        None => Err(malformed()),
+    };
 
     match operator.as_rule() {
-        Rule::binary => HeuristicNode::Binary(
-            operator.into_inner().next().unwrap().as_rule(),
-            Box::new(pairs2struct(Pairs::single(pairs.next().unwrap()))),
-            Box::new(pairs2struct(Pairs::single(pairs.next().unwrap()))),
-        ),
-        Rule::unary => HeuristicNode::Unary(
-            operator.into_inner().next().unwrap().as_rule(),
-            Box::new(pairs2struct(Pairs::single(pairs.next().unwrap()))),
-        ),
-        Rule::terminal => HeuristicNode::Terminal(operator.into_inner().next().unwrap().as_rule()),
-        Rule::number => HeuristicNode::Number(operator.as_str().parse::<i32>().unwrap()),
-        other => {
-            unreachable!("{:?}", other)
+        Rule::binding => {
+            let name = operator.into_inner().next().ok_or_else(malformed)?.as_str();
+            let value = *operand()?;
+
+            let mut inner_bindings = bindings.to_vec();
+            inner_bindings.push((name, value));
+            match pairs.next() {
+                Some(body) => pairs2struct(body, scope, &inner_bindings),
+                None => Err(malformed()),
+            }
+        }
+        Rule::identifier => match bindings
+            .iter()
+            .rev()
+            .find(|(name, _)| *name == operator.as_str())
+        {
+            Some((_, value)) => Ok(value.clone()),
+            None => Err(HeuristicParseError::at_span(
+                operator.as_span(),
+                format!("unbound variable `{}`", operator.as_str()),
+            )),
+        },
+        Rule::adf => {
+            let index = index2struct(&operator, "adf")?;
+            let arity = match scope.adfs.get(index) {
+                Some(adf) => adf.arity,
+                None => {
+                    return Err(HeuristicParseError::at_span(
+                        operator.as_span(),
+                        format!("undefined function `{}`", operator.as_str()),
+                    ))
+                }
+            };
+            let args = pairs
+                .map(|arg| pairs2struct(arg, scope, bindings))
+                .collect::<Result<Vec<_>, _>>()?;
+
+            match args.len() == arity {
+                true => Ok(HeuristicNode::Call(index, args)),
+                false => Err(HeuristicParseError::at_span(
+                    span,
+                    format!(
+                        "`{}` takes {} arguments but {} were given",
+                        operator.as_str(),
+                        arity,
+                        args.len()
+                    ),
+                )),
+            }
         }
+        Rule::argument => match index2struct(&operator, "arg")? {
+            index if index < scope.arity => Ok(HeuristicNode::Argument(index)),
+            _ => Err(HeuristicParseError::at_span(
+                operator.as_span(),
+                format!("unbound argument `{}`", operator.as_str()),
+            )),
+        },
+        Rule::conditional => Ok(HeuristicNode::Conditional(
+            operator_rule(operator)?,
+            operand()?,
+            operand()?,
+            operand()?,
+            operand()?,
+        )),
+        Rule::binary => Ok(HeuristicNode::Binary(
+            operator_rule(operator)?,
+            operand()?,
+            operand()?,
+        )),
+        Rule::unary => Ok(HeuristicNode::Unary(operator_rule(operator)?, operand()?)),
+        Rule::terminal => Ok(HeuristicNode::Terminal(operator_rule(operator)?)),
+        Rule::number => number2struct(operator),
+        other => Err(unexpected(operator, other)),
     }
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::heuristic::mutate_probs::TermProbabilities;
+    use crate::heuristic::util::{heuristic_size, random_heuristic};
+    use test_case::test_case;
 
     #[test]
     fn test_parse_success_1() {
@@ -113,12 +592,102 @@ mod tests {
             h4,
             HeuristicNode::Binary(
                 Rule::plus,
-                Box::new(HeuristicNode::Number(1)),
-                Box::new(HeuristicNode::Number(3))
+                Box::new(HeuristicNode::Number(1.0)),
+                Box::new(HeuristicNode::Number(3.0))
             )
         );
     }
 
+    #[test]
+    fn test_parse_distance_terminals() {
+        let h = parse_heuristic("(max (+ manhattan euclid) (- octile cheby))").root;
+        assert_eq!(
+            h,
+            HeuristicNode::Binary(
+                Rule::max,
+                Box::new(HeuristicNode::Binary(
+                    Rule::plus,
+                    Box::new(HeuristicNode::Terminal(Rule::manhattan)),
+                    Box::new(HeuristicNode::Terminal(Rule::euclid))
+                )),
+                Box::new(HeuristicNode::Binary(
+                    Rule::minus,
+                    Box::new(HeuristicNode::Terminal(Rule::octile)),
+                    Box::new(HeuristicNode::Terminal(Rule::cheby))
+                ))
+            )
+        );
+    }
+
+    #[test]
+    fn test_parse_conditional() {
+        let h = parse_heuristic("(ifle deltaX deltaY (pow deltaY 2) (log (recip x1)))").root;
+        assert_eq!(
+            h,
+            HeuristicNode::Conditional(
+                Rule::ifle,
+                Box::new(HeuristicNode::Terminal(Rule::deltaX)),
+                Box::new(HeuristicNode::Terminal(Rule::deltaY)),
+                Box::new(HeuristicNode::Binary(
+                    Rule::pow,
+                    Box::new(HeuristicNode::Terminal(Rule::deltaY)),
+                    Box::new(HeuristicNode::Number(2.0))
+                )),
+                Box::new(HeuristicNode::Unary(
+                    Rule::log,
+                    Box::new(HeuristicNode::Unary(
+                        Rule::recip,
+                        Box::new(HeuristicNode::Terminal(Rule::x1))
+                    ))
+                ))
+            )
+        );
+    }
+
+    #[test]
+    fn test_parse_success_5() {
+        let h5 = parse_heuristic("(* (+ 12 -3) (max 0.5 1.625))").root;
+        assert_eq!(
+            h5,
+            HeuristicNode::Binary(
+                Rule::mul,
+                Box::new(HeuristicNode::Binary(
+                    Rule::plus,
+                    Box::new(HeuristicNode::Number(12.0)),
+                    Box::new(HeuristicNode::Number(-3.0))
+                )),
+                Box::new(HeuristicNode::Binary(
+                    Rule::max,
+                    Box::new(HeuristicNode::Number(0.5)),
+                    Box::new(HeuristicNode::Number(1.625))
+                ))
+            )
+        );
+    }
+
+    #[test]
+    fn test_display_round_trip() {
+        let input = "(max 12 (min -3 (abs (sqrt 1.625))))";
+        assert_eq!(parse_heuristic(input).root.to_string(), input);
+    }
+
+    #[test_case("(+ x1 0)", "(+ x1 -0)", false)]
+    #[test_case("(+ x1 0.5)", "(+ x1 0.50)", true)]
+    #[test_case("(ifle x1 y1 2 3)", "(ifle x1 y1 3 2)", false)]
+    fn test_eq_agrees_with_hash(input1: &str, input2: &str, equal: bool) {
+        use std::collections::hash_map::DefaultHasher;
+        use std::hash::Hasher;
+
+        let hash = |node: &HeuristicNode| {
+            let mut hasher = DefaultHasher::new();
+            node.hash(&mut hasher);
+            hasher.finish()
+        };
+        let (h1, h2) = (parse_heuristic(input1).root, parse_heuristic(input2).root);
+        assert_eq!(h1 == h2, equal);
+        assert_eq!(hash(&h1) == hash(&h2), equal);
+    }
+
     #[test]
     #[should_panic]
     fn test_parse_failure_1() {
@@ -136,4 +705,227 @@ mod tests {
     fn test_parse_failure_3() {
         parse_heuristic("(/ (max deltaX deltaY) ())");
     }
+
+    #[test]
+    fn test_parse_whitespace() {
+        let expected = parse_heuristic("(+ (abs x1) (max deltaX deltaY))").root;
+        assert_eq!(
+            parse_heuristic("(+  (abs x1)\t(max deltaX deltaY))").root,
+            expected
+        );
+        assert_eq!(
+            parse_heuristic("\n( + (abs x1 )\n   (max deltaX\r\n deltaY) )\n").root,
+            expected
+        );
+    }
+
+    #[test]
+    fn test_parse_comments() {
+        let h =
+            parse_heuristic("; manhattan distance\n(+ deltaX ; horizontal\n   deltaY) ; vertical");
+        assert_eq!(h.root, parse_heuristic("(+ deltaX deltaY)").root);
+    }
+
+    #[test]
+    #[should_panic]
+    fn test_parse_failure_4() {
+        parse_heuristic("(+ deltaXdeltaY)");
+    }
+
+    #[test]
+    fn test_parse_heuristic_file() {
+        let h = parse_heuristic_file("./src/map/data/heuristics/manhattan.heuristic");
+        assert_eq!(h.root, parse_heuristic("(+ deltaX deltaY)").root);
+
+        let h = parse_heuristic_file("./src/map/data/heuristics/weighted-octile.heuristic");
+        assert_eq!(
+            h.root,
+            parse_heuristic("(+ (max deltaX deltaY) (* 0.5 (min deltaX deltaY)))").root
+        );
+    }
+
+    #[test]
+    fn test_try_parse_success() {
+        let h = try_parse_heuristic("(+ deltaX deltaY)").unwrap();
+        assert_eq!(h.root, parse_heuristic("(+ deltaX deltaY)").root);
+    }
+
+    #[test]
+    fn test_try_parse_failure_1() {
+        let err = try_parse_heuristic("(+ deltaX #)").unwrap_err();
+        assert_eq!(err.span, (10, 10));
+        assert_eq!(err.line_col, (1, 11));
+        assert!(err.expected.contains(&"heuristic".to_string()));
+        assert!(err.message.contains('^'));
+    }
+
+    #[test]
+    fn test_try_parse_failure_2() {
+        let err = try_parse_heuristic("(abs x1) x2").unwrap_err();
+        assert_eq!(err.span, (9, 9));
+        assert_eq!(err.expected, vec!["EOI".to_string()]);
+    }
+
+    #[test]
+    fn test_try_parse_failure_3() {
+        let err = try_parse_heuristic("").unwrap_err();
+        assert_eq!(err.span, (0, 0));
+    }
+
+    #[test]
+    fn test_try_parse_failure_4() {
+        let err = try_parse_heuristic(&"9".repeat(40)).unwrap_err();
+        assert_eq!(err.span, (0, 40));
+    }
+
+    #[test]
+    fn test_parse_infix_1() {
+        let h = parse_infix_heuristic("max(|dx|, |dy|) * 9 + sqrt(x1)").root;
+        assert_eq!(
+            h,
+            parse_heuristic("(+ (* (max (abs deltaX) (abs deltaY)) 9) (sqrt x1))").root
+        );
+    }
+
+    #[test]
+    fn test_parse_infix_2() {
+        let h = parse_infix_heuristic("x1 - x2 - 3 * -y1 / sqr(deltaY)").root;
+        assert_eq!(
+            h,
+            parse_heuristic("(- (- x1 x2) (/ (* 3 (neg y1)) (sqr deltaY)))").root
+        );
+    }
+
+    #[test]
+    fn test_parse_infix_3() {
+        let h = parse_infix_heuristic("x1 - -3 + -(2) - (x2 - 0.5)").root;
+        assert_eq!(
+            h,
+            parse_heuristic("(- (+ (- x1 -3) (neg 2)) (- x2 0.5))").root
+        );
+    }
+
+    #[test]
+    fn test_parse_infix_conditional() {
+        let h = parse_infix_heuristic("ifle(dx, dy, floor(exp(x1)), pow(ceil(y1), 2)) + 1").root;
+        assert_eq!(
+            h,
+            parse_heuristic("(+ (ifle deltaX deltaY (floor (exp x1)) (pow (ceil y1) 2)) 1)").root
+        );
+    }
+
+    #[test]
+    #[should_panic]
+    fn test_parse_conditional_failure() {
+        parse_heuristic("(ifle deltaX deltaY deltaX)");
+    }
+
+    #[test]
+    fn test_parse_let() {
+        let h = parse_heuristic("(let m (max deltaX deltaY) (+ m (sqr m)))").root;
+        assert_eq!(
+            h,
+            parse_heuristic("(+ (max deltaX deltaY) (sqr (max deltaX deltaY)))").root
+        );
+    }
+
+    #[test]
+    fn test_let_size() {
+        // The bound value counts once towards the shared size, which the fitness is
+        // penalized by, but towards the plain size at every use
+        let h = parse_heuristic("(let m (max deltaX deltaY) (+ m (sqr m)))");
+        assert_eq!(h.shared_size(), 5);
+        assert_eq!(h.size(), 8);
+        assert_eq!(heuristic_size(h.root()), 8);
+    }
+
+    #[test]
+    fn test_parse_let_shadowing() {
+        let h = parse_heuristic("(let a x1 (let a (+ a 1) (* a a)))").root;
+        assert_eq!(h, parse_heuristic("(* (+ x1 1) (+ x1 1))").root);
+    }
+
+    #[test]
+    fn test_parse_let_unbound() {
+        let err = try_parse_heuristic("(let a x1 (+ a b))").unwrap_err();
+        assert_eq!(err.span, (15, 16));
+        assert!(err.message.contains("unbound variable `b`"));
+    }
+
+    #[test]
+    #[should_panic]
+    fn test_parse_let_terminal_name() {
+        parse_heuristic("(let x1 deltaX (+ x1 x1))");
+    }
+
+    #[test]
+    fn test_parse_adfs() {
+        let input = "(defun adf0 2 (max arg0 arg1))\n(defun adf1 0 9)\n(plus (adf0 x1 (adf1)) y1)";
+        let h = parse_heuristic(input);
+        assert_eq!(h.adfs.len(), 2);
+        assert_eq!(h.adfs[0].arity, 2);
+        assert_eq!(
+            h.root,
+            HeuristicNode::Binary(
+                Rule::plus,
+                Box::new(HeuristicNode::Call(
+                    0,
+                    vec![
+                        HeuristicNode::Terminal(Rule::x1),
+                        HeuristicNode::Call(1, vec![])
+                    ]
+                )),
+                Box::new(HeuristicNode::Terminal(Rule::y1))
+            )
+        );
+        assert_eq!(h.to_string(), input);
+    }
+
+    #[test_case("(defun adf1 1 arg0) x1", "expected `adf0`")]
+    #[test_case("(defun adf0 1 (adf0 arg0)) x1", "undefined function `adf0`")]
+    #[test_case(
+        "(defun adf0 1 arg0) (adf0 x1 y1)",
+        "`adf0` takes 1 arguments but 2 were given"
+    )]
+    #[test_case("(defun adf0 1 arg1) x1", "unbound argument `arg1`")]
+    #[test_case("(+ arg0 x1)", "unbound argument `arg0`")]
+    fn test_parse_adf_failure(input: &str, message: &str) {
+        let err = try_parse_heuristic(input).unwrap_err();
+        assert!(err.message.contains(message), "{}", err.message);
+    }
+
+    #[test]
+    #[should_panic]
+    fn test_parse_infix_failure_1() {
+        parse_infix_heuristic("max(deltaX)");
+    }
+
+    #[test]
+    #[should_panic]
+    fn test_parse_infix_failure_2() {
+        parse_infix_heuristic("(+ deltaX deltaY)");
+    }
+
+    #[test]
+    fn test_to_infix() {
+        let h = parse_heuristic("(+ (* (max (abs deltaX) (abs deltaY)) 9) (sqrt x1))");
+        assert_eq!(h.root.to_infix(), "max(|deltaX|, |deltaY|) * 9 + sqrt(x1)");
+
+        let h = parse_heuristic("(- x1 (- (neg (+ x2 y1)) (neg -3)))");
+        assert_eq!(h.root.to_infix(), "x1 - (-(x2 + y1) - -(-3))");
+    }
+
+    #[test]
+    fn test_round_trip_random() {
+        let term_probs = Some(TermProbabilities::with_number_values(
+            true,
+            vec![-3.0, -0.25, 0.0, 0.5, 1.625, 9.0, 1000.0],
+        ));
+
+        for size in 1..=200 {
+            let h = random_heuristic(size % 40 + 1, &term_probs);
+            assert_eq!(parse_heuristic(&h.to_string()).root, h);
+            assert_eq!(parse_infix_heuristic(&h.to_infix()).root, h);
+        }
+    }
 }
@@ -0,0 +1,274 @@
+use super::adf::Adf;
+use super::executors::interpreter::{evaluate_binary, evaluate_condition, evaluate_unary};
+use super::parser::{HeuristicNode, Rule};
+use super::Heuristic;
+
+// Terminals are grid coordinates (or differences of them), so their magnitude is
+// bounded by the map dimensions. This is far beyond any map we search on.
+const MAX_COORDINATE: f64 = 1048576.0;
+
+// Removes dead structure from a heuristic without changing what it evaluates to.
+// Only identities that hold bit-for-bit under f32 arithmetic (including the signed
+// `sqrt` used by the executors, NaN and infinities) are applied. Constants are
+// folded with the operators of the `Interpreter`.
+pub fn simplify(heuristic: &HeuristicNode) -> HeuristicNode {
+    match heuristic {
+        HeuristicNode::Number(_) | HeuristicNode::Terminal(_) | HeuristicNode::Argument(_) => {
+            heuristic.clone()
+        }
+        HeuristicNode::Unary(rule, h) => simplify_unary(*rule, simplify(h)),
+        HeuristicNode::Binary(rule, h1, h2) => simplify_binary(*rule, simplify(h1), simplify(h2)),
+        HeuristicNode::Conditional(rule, h1, h2, h3, h4) => simplify_conditional(
+            *rule,
+            simplify(h1),
+            simplify(h2),
+            simplify(h3),
+            simplify(h4),
+        ),
+        HeuristicNode::Call(index, args) => {
+            HeuristicNode::Call(*index, args.iter().map(simplify).collect())
+        }
+    }
+}
+
+// Simplifies the main tree and every ADF body, keeping the ADFs themselves so
+// that calls stay valid. The lineage of the heuristic is kept as well.
+pub fn simplify_heuristic(heuristic: &Heuristic) -> Heuristic {
+    let adfs = heuristic
+        .adfs
+        .iter()
+        .map(|adf| Adf {
+            arity: adf.arity,
+            body: simplify(&adf.body),
+        })
+        .collect();
+    let mut simplified = Heuristic::with_adfs(simplify(heuristic.root()), adfs);
+    simplified.parents = heuristic.parents.clone();
+    simplified.generation = heuristic.generation;
+    simplified
+}
+
+fn simplify_unary(rule: Rule, h: HeuristicNode) -> HeuristicNode {
+    if let HeuristicNode::Number(num) = h {
+        if let Some(folded) = fold(evaluate_unary(rule, num)) {
+            return folded;
+        }
+    }
+
+    match (rule, h) {
+        // (neg (neg x)) => x
+        (Rule::neg, HeuristicNode::Unary(Rule::neg, x)) => *x,
+        // (abs (neg x)) => (abs x)
+        (Rule::abs, HeuristicNode::Unary(Rule::neg, x)) => simplify_unary(Rule::abs, *x),
+        // (abs x) => x, if x is never negative
+        (Rule::abs, x) if is_non_negative(&x) => x,
+        // (sqr (neg x)) => (sqr x), (sqr (abs x)) => (sqr x)
+        (Rule::sqr, HeuristicNode::Unary(Rule::neg | Rule::abs, x)) => {
+            simplify_unary(Rule::sqr, *x)
+        }
+        (rule, h) => HeuristicNode::Unary(rule, Box::new(h)),
+    }
+}
+
+fn simplify_binary(rule: Rule, h1: HeuristicNode, h2: HeuristicNode) -> HeuristicNode {
+    if let (HeuristicNode::Number(num1), HeuristicNode::Number(num2)) = (&h1, &h2) {
+        if let Some(folded) = fold(evaluate_binary(rule, *num1, *num2)) {
+            return folded;
+        }
+    }
+
+    match (rule, h1, h2) {
+        // (max x x) => x, (min x x) => x
+        (Rule::max | Rule::min, h1, h2) if h1 == h2 => h1,
+        // (- x x) => 0, unless x can be infinite or NaN
+        (Rule::minus, h1, h2) if h1 == h2 && is_finite(&h1) => HeuristicNode::Number(0.0),
+        // (* 1 x) => x, (* x 1) => x, (/ x 1) => x, (- x 0) => x
+        (Rule::mul, HeuristicNode::Number(1.0), h) => h,
+        (Rule::mul | Rule::div, h, HeuristicNode::Number(1.0)) => h,
+        (Rule::minus, h, HeuristicNode::Number(num)) if num.to_bits() == 0.0f32.to_bits() => h,
+        // (- x (neg y)) => (+ x y), (+ x (neg y)) => (- x y)
+        (Rule::minus, h1, HeuristicNode::Unary(Rule::neg, h2)) => {
+            simplify_binary(Rule::plus, h1, *h2)
+        }
+        (Rule::plus, h1, HeuristicNode::Unary(Rule::neg, h2)) => {
+            simplify_binary(Rule::minus, h1, *h2)
+        }
+        // (* (neg x) (neg y)) => (* x y), (/ (neg x) (neg y)) => (/ x y)
+        (
+            Rule::mul | Rule::div,
+            HeuristicNode::Unary(Rule::neg, h1),
+            HeuristicNode::Unary(Rule::neg, h2),
+        ) => simplify_binary(rule, *h1, *h2),
+        (rule, h1, h2) => HeuristicNode::Binary(rule, Box::new(h1), Box::new(h2)),
+    }
+}
+
+fn simplify_conditional(
+    rule: Rule,
+    h1: HeuristicNode,
+    h2: HeuristicNode,
+    h3: HeuristicNode,
+    h4: HeuristicNode,
+) -> HeuristicNode {
+    match (rule, h1, h2, h3, h4) {
+        // (ifle a b c d) => c or d, if a and b are constant
+        (Rule::ifle, HeuristicNode::Number(num1), HeuristicNode::Number(num2), h3, h4) => {
+            match evaluate_condition(rule, num1, num2) {
+                true => h3,
+                false => h4,
+            }
+        }
+        // (ifle a b c c) => c
+        (Rule::ifle, _, _, h3, h4) if h3 == h4 => h3,
+        (rule, h1, h2, h3, h4) => HeuristicNode::Conditional(
+            rule,
+            Box::new(h1),
+            Box::new(h2),
+            Box::new(h3),
+            Box::new(h4),
+        ),
+    }
+}
+
+// Constants must stay finite to be representable in the grammar
+fn fold(value: f32) -> Option<HeuristicNode> {
+    match value.is_finite() {
+        true => Some(HeuristicNode::Number(value)),
+        false => None,
+    }
+}
+
+fn is_non_negative(heuristic: &HeuristicNode) -> bool {
+    match heuristic {
+        HeuristicNode::Number(num) => num.is_sign_positive(),
+        HeuristicNode::Terminal(rule) => !matches!(rule, Rule::x1 | Rule::x2 | Rule::y1 | Rule::y2),
+        HeuristicNode::Unary(rule, _) => matches!(rule, Rule::abs | Rule::sqr | Rule::exp),
+        HeuristicNode::Binary(rule, _, _) => *rule == Rule::pow,
+        HeuristicNode::Conditional(_, _, _, h3, h4) => is_non_negative(h3) && is_non_negative(h4),
+        // Calls and parameters could be anything
+        HeuristicNode::Call(..) | HeuristicNode::Argument(_) => false,
+    }
+}
+
+fn is_finite(heuristic: &HeuristicNode) -> bool {
+    // Leave plenty of headroom for rounding in f32
+    magnitude_bound(heuristic) < f32::MAX as f64 / 4.0
+}
+
+// An upper bound on the absolute value a heuristic can evaluate to
+fn magnitude_bound(heuristic: &HeuristicNode) -> f64 {
+    match heuristic {
+        HeuristicNode::Number(num) => num.abs() as f64,
+        // Distance terminals are at most the sum of the coordinate differences
+        HeuristicNode::Terminal(_) => 2.0 * MAX_COORDINATE,
+        HeuristicNode::Unary(rule, h) => {
+            let bound = magnitude_bound(h);
+            match rule {
+                Rule::sqrt => bound.sqrt(),
+                Rule::sqr => bound * bound,
+                Rule::exp => bound.exp(),
+                Rule::floor | Rule::ceil => bound + 1.0,
+                // Unbounded near 0
+                Rule::log | Rule::recip => f64::INFINITY,
+                _ => bound,
+            }
+        }
+        HeuristicNode::Binary(rule, h1, h2) => {
+            let (bound1, bound2) = (magnitude_bound(h1), magnitude_bound(h2));
+            match (rule, &**h2) {
+                (Rule::plus | Rule::minus, _) => bound1 + bound2,
+                (Rule::mul, _) => bound1 * bound2,
+                (Rule::div, HeuristicNode::Number(num)) if *num != 0.0 => bound1 / num.abs() as f64,
+                (Rule::div | Rule::pow, _) => f64::INFINITY,
+                _ => bound1.max(bound2),
+            }
+        }
+        HeuristicNode::Conditional(_, _, _, h3, h4) => magnitude_bound(h3).max(magnitude_bound(h4)),
+        HeuristicNode::Call(..) | HeuristicNode::Argument(_) => f64::INFINITY,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::heuristic::executors::interpreter::Interpreter;
+    use crate::heuristic::executors::HeuristicExecuter;
+    use crate::heuristic::mutate_probs::TermProbabilities;
+    use crate::heuristic::parser::parse_heuristic;
+    use crate::heuristic::util::random_heuristic;
+    use crate::heuristic::Heuristic;
+    use crate::map::util::{Map, Tile};
+
+    use test_case::test_case;
+
+    #[test_case("(neg (neg x1))", "x1")]
+    #[test_case("(abs (abs x1))", "(abs x1)")]
+    #[test_case("(abs (neg (neg (neg x1))))", "(abs x1)")]
+    #[test_case("(abs deltaX)", "deltaX")]
+    #[test_case("(abs (neg octile))", "octile")]
+    #[test_case("(sqr (abs (neg y1)))", "(sqr y1)")]
+    #[test_case("(max (+ x1 y1) (+ x1 y1))", "(+ x1 y1)")]
+    #[test_case("(min deltaX deltaX)", "deltaX")]
+    #[test_case("(- (* x1 y2) (* x1 y2))", "0")]
+    #[test_case("(- (/ x1 y2) (/ x1 y2))", "(- (/ x1 y2) (/ x1 y2))")]
+    #[test_case("(* 1 (/ deltaX 1))", "deltaX")]
+    #[test_case("(+ x1 (neg y1))", "(- x1 y1)")]
+    #[test_case("(- x1 (neg y1))", "(+ x1 y1)")]
+    #[test_case("(* (neg x1) (neg y1))", "(* x1 y1)")]
+    #[test_case("(* (+ 2 3) (sqrt (neg 4)))", "-10")]
+    #[test_case("(+ deltaX (* 9 (* 9 9)))", "(+ deltaX 729)")]
+    #[test_case("(/ x1 (- 2 2))", "(/ x1 0)")]
+    #[test_case("(+ x1 (/ 1 0))", "(+ x1 (/ 1 0))")]
+    #[test_case("(sqrt (sqr x1))", "(sqrt (sqr x1))")]
+    #[test_case("(ifle 1 2 x1 y1)", "x1")]
+    #[test_case("(ifle (+ 1 2) 2 x1 y1)", "y1")]
+    #[test_case("(ifle x1 y1 (abs deltaX) deltaX)", "deltaX")]
+    #[test_case("(abs (ifle x1 y1 (exp x2) (pow x1 y1)))", "(ifle x1 y1 (exp x2) (pow x1 y1))")]
+    #[test_case("(+ x1 (recip (log 1)))", "(+ x1 (recip 0))")]
+    #[test_case("(* (floor 2.5) (ceil -0.5))", "-0")]
+    fn test_simplify(input: &str, expected: &str) {
+        let simplified = simplify(parse_heuristic(input).root());
+        assert_eq!(simplified, parse_heuristic(expected).root);
+    }
+
+    #[test]
+    fn test_simplify_heuristic() {
+        let h = parse_heuristic("(defun adf0 1 (* 1 (abs arg0))) (adf0 (- x1 (neg y1)))");
+        assert_eq!(
+            simplify_heuristic(&h).to_string(),
+            "(defun adf0 1 (abs arg0))\n(adf0 (plus x1 y1))"
+        );
+    }
+
+    #[test]
+    fn test_simplify_preserves_evaluation() {
+        let term_probs = Some(TermProbabilities::with_number_values(
+            true,
+            vec![0.0, 1.0, 2.0, 0.5, -3.0],
+        ));
+        let mut tiles = vec![Tile::Passable; 64 * 64];
+        for i in (0..tiles.len()).step_by(7) {
+            tiles[i] = Tile::Unpassable;
+        }
+        let features = Map::from(64, 64, tiles).features;
+
+        for size in 1..=500 {
+            let h = Heuristic::new(random_heuristic(size % 40 + 1, &term_probs));
+            let simplified = Heuristic::new(simplify(h.root()));
+            assert!(simplified.size() <= h.size());
+
+            let original = Interpreter::create_with_features(&h, features.clone());
+            let simplified = Interpreter::create_with_features(&simplified, features.clone());
+            for _ in 0..20 {
+                let (x1, y1) = (fastrand::u32(0..64) as f32, fastrand::u32(0..64) as f32);
+                let (x2, y2) = (fastrand::u32(0..64) as f32, fastrand::u32(0..64) as f32);
+                assert_eq!(
+                    original.execute(x1, y1, x2, y2).to_bits(),
+                    simplified.execute(x1, y1, x2, y2).to_bits(),
+                    "{}",
+                    h.root()
+                );
+            }
+        }
+    }
+}
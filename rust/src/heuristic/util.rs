@@ -1,14 +1,65 @@
-use super::{mutate_probs::TermProbabilities, parser::HeuristicNode};
+use super::{adf::Scope, mutate_probs::TermProbabilities, parser::HeuristicNode};
+use crate::constants::{ARGUMENT_PROBABILITY, CALL_PROBABILITY, CONDITIONAL_PROBABILITY};
 use crate::heuristic::mutate_probs::Term;
 use crate::heuristic::parser::Rule;
 use std::cmp::*;
 
+// The operators and terminals available to random heuristics, in the order used
+// by `TermProbabilities`
+pub const BINARIES: [Rule; 7] = [
+    Rule::plus,
+    Rule::div,
+    Rule::mul,
+    Rule::minus,
+    Rule::max,
+    Rule::min,
+    Rule::pow,
+];
+pub const UNARIES: [Rule; 9] = [
+    Rule::neg,
+    Rule::abs,
+    Rule::sqrt,
+    Rule::sqr,
+    Rule::log,
+    Rule::exp,
+    Rule::floor,
+    Rule::ceil,
+    Rule::recip,
+];
+pub const CONDITIONALS: [Rule; 1] = [Rule::ifle];
+pub const TERMINALS: [Rule; 10] = [
+    Rule::x1,
+    Rule::x2,
+    Rule::y1,
+    Rule::y2,
+    Rule::deltaX,
+    Rule::deltaY,
+    Rule::manhattan,
+    Rule::euclid,
+    Rule::octile,
+    Rule::cheby,
+];
+// Only sampled by `TermProbabilities::with_feature_terminals`, as they can only be
+// evaluated by executers created with feature tables
+pub const FEATURE_TERMINALS: [Rule; 5] = [
+    Rule::lineObstacles,
+    Rule::wallDist,
+    Rule::sameRoom,
+    Rule::rowDensity,
+    Rule::colDensity,
+];
+
 pub fn heuristic_size(heuristic: &HeuristicNode) -> i32 {
     match heuristic {
         HeuristicNode::Number(_) => 1,
         HeuristicNode::Terminal(_) => 1,
+        HeuristicNode::Argument(_) => 1,
         HeuristicNode::Unary(_, heuristic) => 1 + heuristic_size(heuristic),
         HeuristicNode::Binary(_, left, right) => 1 + heuristic_size(left) + heuristic_size(right),
+        HeuristicNode::Conditional(_, h1, h2, h3, h4) => {
+            1 + heuristic_size(h1) + heuristic_size(h2) + heuristic_size(h3) + heuristic_size(h4)
+        }
+        HeuristicNode::Call(_, args) => 1 + args.iter().map(heuristic_size).sum::<i32>(),
     }
 }
 
@@ -16,14 +67,33 @@ pub fn heuristic_depth(heuristic: &HeuristicNode) -> i32 {
     match heuristic {
         HeuristicNode::Number(_) => 1,
         HeuristicNode::Terminal(_) => 1,
+        HeuristicNode::Argument(_) => 1,
         HeuristicNode::Unary(_, heuristic) => 1 + heuristic_depth(heuristic),
         HeuristicNode::Binary(_, left, right) => {
             1 + max(heuristic_depth(left), heuristic_depth(right))
         }
+        HeuristicNode::Conditional(_, h1, h2, h3, h4) => {
+            1 + [h1, h2, h3, h4]
+                .iter()
+                .map(|h| heuristic_depth(h))
+                .max()
+                .unwrap()
+        }
+        HeuristicNode::Call(_, args) => 1 + args.iter().map(heuristic_depth).max().unwrap_or(0),
     }
 }
 
 pub fn random_heuristic(hsize: i32, term_probs: &Option<TermProbabilities>) -> HeuristicNode {
+    random_heuristic_in(hsize, term_probs, &Scope::default())
+}
+
+// Like `random_heuristic`, but the tree may also call the ADFs in `scope` and read
+// its parameters
+pub fn random_heuristic_in(
+    hsize: i32,
+    term_probs: &Option<TermProbabilities>,
+    scope: &Scope,
+) -> HeuristicNode {
     let hsize = match hsize >= 1 {
         true => hsize,
         _ => fastrand::i32(1..=40),
@@ -37,6 +107,9 @@ pub fn random_heuristic(hsize: i32, term_probs: &Option<TermProbabilities>) -> H
 
     // Base cases
     if hsize == 1 {
+        if scope.arity > 0 && fastrand::f64() < ARGUMENT_PROBABILITY {
+            return HeuristicNode::Argument(fastrand::usize(0..scope.arity));
+        }
         return match fastrand::i32(0..=1) {
             0 => random_terminal(term_probs),
             1 => random_number(term_probs),
@@ -44,15 +117,31 @@ pub fn random_heuristic(hsize: i32, term_probs: &Option<TermProbabilities>) -> H
                 unreachable!("{:?}", other)
             }
         };
-    } else if hsize == 2 {
+    }
+
+    // A call needs at least one node per argument, and somewhere to put the rest
+    let callable = (0..scope.adfs.len())
+        .filter(|&index| (1..hsize).contains(&(scope.adfs[index].arity as i32)))
+        .collect::<Vec<_>>();
+    if !callable.is_empty() && fastrand::f64() < CALL_PROBABILITY {
+        let index = callable[fastrand::usize(0..callable.len())];
+        return random_call(hsize, index, term_probs, scope);
+    }
+
+    if hsize == 2 {
         // with a heuristic size of 2, we can only have unary -> terminal
         // we can't have a binary, since that implies at least 3 terms
-        return random_unary(2, term_probs);
+        return random_unary(2, term_probs, scope);
+    }
+
+    // A conditional needs at least four operands
+    if hsize >= 5 && fastrand::f64() < CONDITIONAL_PROBABILITY {
+        return random_conditional(hsize, term_probs, scope);
     }
 
     match fastrand::u32(0..=1) {
-        0 => random_unary(hsize, term_probs),
-        1 => random_binary(hsize, term_probs),
+        0 => random_unary(hsize, term_probs, scope),
+        1 => random_binary(hsize, term_probs, scope),
         _ => {
             unreachable!()
         }
@@ -60,33 +149,31 @@ pub fn random_heuristic(hsize: i32, term_probs: &Option<TermProbabilities>) -> H
 }
 
 fn random_number(term_probs: &Option<TermProbabilities>) -> HeuristicNode {
-    let items = (1..=9).collect::<Vec<i32>>();
+    let term_probs = term_probs.as_ref().unwrap();
 
-    HeuristicNode::Number(random_weighted_sample::<i32>(
-        term_probs.as_ref().unwrap().get(Term::Number),
-        &items,
+    HeuristicNode::Number(random_weighted_sample::<f32>(
+        term_probs.get(Term::Number),
+        &term_probs.number_values,
     ))
 }
 
 fn random_terminal(term_probs: &Option<TermProbabilities>) -> HeuristicNode {
-    let items = vec![
-        Rule::x1,
-        Rule::x2,
-        Rule::y1,
-        Rule::y2,
-        Rule::deltaX,
-        Rule::deltaY,
-    ];
+    let term_probs = term_probs.as_ref().unwrap();
+    let items = term_probs.terminal_rules();
 
     HeuristicNode::Terminal(random_weighted_sample::<Rule>(
-        term_probs.as_ref().unwrap().get(Term::Terminal),
+        term_probs.get(Term::Terminal),
         &items,
     ))
 }
 
-fn random_unary(hsize: i32, term_probs: &Option<TermProbabilities>) -> HeuristicNode {
-    let sub = Box::new(random_heuristic(hsize - 1, term_probs));
-    let items = vec![Rule::neg, Rule::abs, Rule::sqrt, Rule::sqr];
+fn random_unary(
+    hsize: i32,
+    term_probs: &Option<TermProbabilities>,
+    scope: &Scope,
+) -> HeuristicNode {
+    let sub = Box::new(random_heuristic_in(hsize - 1, term_probs, scope));
+    let items = UNARIES.to_vec();
 
     HeuristicNode::Unary(
         random_weighted_sample::<Rule>(term_probs.as_ref().unwrap().get(Term::Unary), &items),
@@ -94,20 +181,17 @@ fn random_unary(hsize: i32, term_probs: &Option<TermProbabilities>) -> Heuristic
     )
 }
 
-fn random_binary(hsize: i32, term_probs: &Option<TermProbabilities>) -> HeuristicNode {
+fn random_binary(
+    hsize: i32,
+    term_probs: &Option<TermProbabilities>,
+    scope: &Scope,
+) -> HeuristicNode {
     let left_subtree_size = fastrand::i32(1..=hsize - 2);
     let right_subtree_size = hsize - left_subtree_size - 1;
-    let left = Box::new(random_heuristic(left_subtree_size, term_probs));
-    let right = Box::new(random_heuristic(right_subtree_size, term_probs));
-
-    let items = vec![
-        Rule::plus,
-        Rule::div,
-        Rule::mul,
-        Rule::minus,
-        Rule::max,
-        Rule::min,
-    ];
+    let left = Box::new(random_heuristic_in(left_subtree_size, term_probs, scope));
+    let right = Box::new(random_heuristic_in(right_subtree_size, term_probs, scope));
+
+    let items = BINARIES.to_vec();
 
     HeuristicNode::Binary(
         random_weighted_sample::<Rule>(term_probs.as_ref().unwrap().get(Term::Binary), &items),
@@ -116,6 +200,51 @@ fn random_binary(hsize: i32, term_probs: &Option<TermProbabilities>) -> Heuristi
     )
 }
 
+fn random_conditional(
+    hsize: i32,
+    term_probs: &Option<TermProbabilities>,
+    scope: &Scope,
+) -> HeuristicNode {
+    // Spread the remaining size over the operands, each getting at least 1
+    let mut sizes = [1; 4];
+    for _ in 0..hsize - 5 {
+        sizes[fastrand::usize(0..4)] += 1;
+    }
+    let [h1, h2, h3, h4] = sizes.map(|size| Box::new(random_heuristic_in(size, term_probs, scope)));
+
+    let items = CONDITIONALS.to_vec();
+
+    HeuristicNode::Conditional(
+        random_weighted_sample::<Rule>(term_probs.as_ref().unwrap().get(Term::Conditional), &items),
+        h1,
+        h2,
+        h3,
+        h4,
+    )
+}
+
+fn random_call(
+    hsize: i32,
+    index: usize,
+    term_probs: &Option<TermProbabilities>,
+    scope: &Scope,
+) -> HeuristicNode {
+    // Spread the remaining size over the arguments, each getting at least 1
+    let arity = scope.adfs[index].arity;
+    let mut sizes = vec![1; arity];
+    for _ in 0..hsize - 1 - arity as i32 {
+        sizes[fastrand::usize(0..arity)] += 1;
+    }
+
+    HeuristicNode::Call(
+        index,
+        sizes
+            .into_iter()
+            .map(|size| random_heuristic_in(size, term_probs, scope))
+            .collect(),
+    )
+}
+
 pub fn random_weighted_sample<T: Clone>(probs: &Vec<f64>, items: &Vec<T>) -> T {
     // throw error if probs has length 0
     if probs.len() == 0 {
@@ -154,6 +283,7 @@ pub fn normalize_vector(vec: &mut Vec<f64>) -> () {
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::heuristic::adf::random_adfs;
 
     // Tests for heuristic size
     #[test]
@@ -207,6 +337,43 @@ mod tests {
         assert_eq!(heuristic_depth(&h3), 3);
     }
 
+    #[test]
+    fn test_random_heuristic_size() {
+        for hsize in 1..=40 {
+            let h = random_heuristic(hsize, &None);
+            assert_eq!(heuristic_size(&h), hsize);
+        }
+    }
+
+    #[test]
+    fn test_random_heuristic_in_size() {
+        let adfs = random_adfs(2, &None);
+        let scope = Scope {
+            adfs: &adfs,
+            arity: 2,
+        };
+        for hsize in 1..=40 {
+            let h = random_heuristic_in(hsize, &None, &scope);
+            assert_eq!(heuristic_size(&h), hsize);
+        }
+    }
+
+    // Map feature terminals can only be evaluated with feature tables, so they are
+    // only sampled when asked for
+    #[test]
+    fn test_random_terminal_features() {
+        let sample_features = |term_probs: Option<TermProbabilities>| {
+            (0..1000)
+                .filter(|_| match random_terminal(&term_probs) {
+                    HeuristicNode::Terminal(rule) => FEATURE_TERMINALS.contains(&rule),
+                    other => unreachable!("{:?}", other),
+                })
+                .count()
+        };
+        assert_eq!(sample_features(Some(TermProbabilities::new(false))), 0);
+        assert!(sample_features(Some(TermProbabilities::new(true).with_feature_terminals())) > 0);
+    }
+
     // Tests for normalize vector
     #[test]
     fn test_normalize_vector_1() {
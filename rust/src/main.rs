@@ -1,24 +1,23 @@
-mod alife;
-mod constants;
-mod heuristic;
-mod map;
-
 use std::time::Duration;
 
-use alife::search::problem::Problem;
-use heuristic::executors::jit::Jit;
-use heuristic::mutator::mutate_heuristic;
-use heuristic::parser::parse_heuristic;
-use heuristic::util::{heuristic_size, random_heuristic};
-use map::parser::parse_map_file;
-use map::util::Maps;
-
-use crate::alife::search::cycle::{CycleSolver, ProblemCycle};
-use crate::alife::sim::genetic_algorithm::GeneticAlgorithm;
-use crate::constants::PROBLEM_CYCLE_LENGTH;
-use crate::heuristic::executors::interpreter::Interpreter;
-use crate::heuristic::executors::HeuristicExecuter;
-use crate::heuristic::Heuristic;
+use cmput651py::alife::search::problem::Problem;
+#[cfg(feature = "cranelift")]
+use cmput651py::heuristic::executors::cranelift::Cranelift;
+#[cfg(feature = "jit")]
+use cmput651py::heuristic::executors::jit::Jit;
+use cmput651py::heuristic::mutator::mutate_heuristic;
+use cmput651py::heuristic::parser::{parse_heuristic, try_parse_heuristic};
+use cmput651py::heuristic::util::{heuristic_size, random_heuristic};
+use cmput651py::map::parser::parse_map_file;
+use cmput651py::map::util::{Map, Maps};
+
+use cmput651py::alife::search::cycle::{CycleSolver, EvaluationMode, ProblemCycle};
+use cmput651py::alife::sim::genetic_algorithm::GeneticAlgorithm;
+use cmput651py::constants::{MAX_TREE_SIZE, PROBLEM_CYCLE_LENGTH};
+use cmput651py::heuristic::executors::interpreter::Interpreter;
+use cmput651py::heuristic::executors::vm::Vm;
+use cmput651py::heuristic::executors::HeuristicExecuter;
+use cmput651py::heuristic::Heuristic;
 
 fn main() {
     let choice = 7;
@@ -32,6 +31,7 @@ fn main() {
         6 => alife_demo(),
         7 => ga_demo(),
         8 => eval_heursitic(),
+        9 => benchmark_tables(),
         _ => {
             unreachable!("Invalid choice in function `main`. Please choose from 0-4");
         }
@@ -49,6 +49,10 @@ fn heuristic_demo() {
     );
     println!("{:?}", random_heuristic(2, &None));
 
+    if let Err(e) = try_parse_heuristic("(+ deltaX (abs))") {
+        println!("{}", e);
+    }
+
     let mut h = parse_heuristic("(+ deltaX deltaY)");
     for _ in 0..100 {
         h = Heuristic::new(mutate_heuristic(h.root(), &None));
@@ -77,7 +81,7 @@ fn search_demo() {
     println!("Goal: {:?}", map.ind2sub(goal));
 
     let problem = Problem::new(start, goal);
-    let executer = Interpreter::create(&h);
+    let executer = Interpreter::create_with_features(&h, map.features.clone());
     let result = problem.solve(&map, |x1, y1, x2, y2| executer.execute(x1, y1, x2, y2));
 
     assert!(result.solved);
@@ -105,26 +109,197 @@ fn benchmark() {
     println!("Time to solve problems on second go: {:.2?}", now.elapsed());
 }
 
+// Compiles each of a set of random heuristics and solves a full problem cycle with
+// it, timing every executer. Compilation only pays off over enough evaluations.
 fn benchmark_executers() {
-    let h =
-        parse_heuristic("(* (+ (* (+ (+ (+ deltaX deltaY) deltaY) deltaX) deltaY) deltaX) deltaY)");
-    let heuristic = h;
-
-    let mut x = 0.0;
-    for _ in 0..10000 {
-        let context = inkwell::context::Context::create();
-        let jit = Jit::create(&heuristic, &context);
-        x += jit.execute(x, x, x, x);
-        drop(jit)
+    use std::time::Instant;
+    let map = parse_map_file(Maps::Den312d.path());
+    let cycle = ProblemCycle::new(map.clone(), PROBLEM_CYCLE_LENGTH);
+
+    fastrand::seed(42);
+    let heuristics = (0..20)
+        .map(|_| Heuristic::new(random_heuristic(fastrand::i32(1..=MAX_TREE_SIZE), &None)))
+        .collect::<Vec<_>>();
+    let solve_cycle = |executer: &dyn Fn(f32, f32, f32, f32) -> f32| {
+        cycle
+            .problems
+            .iter()
+            .map(|problem| problem.solve(&map, executer).expansions.len())
+            .sum::<usize>()
+    };
+
+    // The expansions must agree between executers, as they share their semantics
+    let (mut compile, mut expansions) = (Duration::ZERO, 0);
+    let now = Instant::now();
+    for heuristic in &heuristics {
+        let start = Instant::now();
+        let interpreter = Interpreter::create_with_features(heuristic, map.features.clone());
+        compile += start.elapsed();
+        expansions += solve_cycle(&|x1, y1, x2, y2| interpreter.execute(x1, y1, x2, y2));
+    }
+    println!(
+        "Interpreter: {:.2?} compiling, {:.2?} in total, {} expansions",
+        compile,
+        now.elapsed(),
+        expansions
+    );
+
+    let (mut compile, mut expansions) = (Duration::ZERO, 0);
+    let now = Instant::now();
+    for heuristic in &heuristics {
+        let start = Instant::now();
+        let vm = Vm::create_with_features(heuristic, map.features.clone());
+        compile += start.elapsed();
+        expansions += solve_cycle(&|x1, y1, x2, y2| vm.execute(x1, y1, x2, y2));
+    }
+    println!(
+        "Vm: {:.2?} compiling, {:.2?} in total, {} expansions",
+        compile,
+        now.elapsed(),
+        expansions
+    );
+
+    #[cfg(feature = "jit")]
+    {
+        let (mut compile, mut expansions) = (Duration::ZERO, 0);
+        let now = Instant::now();
+        for heuristic in &heuristics {
+            let start = Instant::now();
+            let context = inkwell::context::Context::create();
+            let jit = Jit::create_with_features(heuristic, &context, map.features.clone());
+            compile += start.elapsed();
+            expansions += solve_cycle(&|x1, y1, x2, y2| jit.execute(x1, y1, x2, y2));
+        }
+        println!(
+            "Jit: {:.2?} compiling, {:.2?} in total, {} expansions",
+            compile,
+            now.elapsed(),
+            expansions
+        );
+    }
+
+    #[cfg(feature = "cranelift")]
+    {
+        let (mut compile, mut expansions) = (Duration::ZERO, 0);
+        let now = Instant::now();
+        for heuristic in &heuristics {
+            let start = Instant::now();
+            let cranelift = Cranelift::create_with_features(heuristic, map.features.clone());
+            compile += start.elapsed();
+            expansions += solve_cycle(&|x1, y1, x2, y2| cranelift.execute(x1, y1, x2, y2));
+        }
+        println!(
+            "Cranelift: {:.2?} compiling, {:.2?} in total, {} expansions",
+            compile,
+            now.elapsed(),
+            expansions
+        );
+    }
+
+    // The latency of compiling a single heuristic and the cost of a single call,
+    // which decide which of the native code backends pays off sooner
+    #[cfg(any(feature = "jit", feature = "cranelift"))]
+    {
+        let points = (0..100_000)
+            .map(|_| {
+                let (x1, y1) = map.ind2sub(map.random_free_position());
+                let (x2, y2) = map.ind2sub(map.random_free_position());
+                (x1 as f32, y1 as f32, x2 as f32, y2 as f32)
+            })
+            .collect::<Vec<_>>();
+        let time_calls = |executer: &dyn Fn(f32, f32, f32, f32) -> f32| {
+            let now = Instant::now();
+            let sum = points
+                .iter()
+                .map(|&(x1, y1, x2, y2)| executer(x1, y1, x2, y2))
+                .sum::<f32>();
+            std::hint::black_box(sum);
+            now.elapsed() / points.len() as u32
+        };
+        let num_heuristics = heuristics.len() as u32;
+
+        #[cfg(feature = "jit")]
+        {
+            let (mut compile, mut calls) = (Duration::ZERO, Duration::ZERO);
+            for heuristic in &heuristics {
+                let start = Instant::now();
+                let context = inkwell::context::Context::create();
+                let jit = Jit::create_with_features(heuristic, &context, map.features.clone());
+                compile += start.elapsed();
+                calls += time_calls(&|x1, y1, x2, y2| jit.execute(x1, y1, x2, y2));
+            }
+            println!(
+                "Jit: {:.2?} per compilation, {:.2?} per call",
+                compile / num_heuristics,
+                calls / num_heuristics
+            );
+        }
+
+        #[cfg(feature = "cranelift")]
+        {
+            let (mut compile, mut calls) = (Duration::ZERO, Duration::ZERO);
+            for heuristic in &heuristics {
+                let start = Instant::now();
+                let cranelift = Cranelift::create_with_features(heuristic, map.features.clone());
+                compile += start.elapsed();
+                calls += time_calls(&|x1, y1, x2, y2| cranelift.execute(x1, y1, x2, y2));
+            }
+            println!(
+                "Cranelift: {:.2?} per compilation, {:.2?} per call",
+                compile / num_heuristics,
+                calls / num_heuristics
+            );
+        }
     }
+}
 
-    println!("{}", x);
+// Solves the same cycle with direct and table driven evaluation on every bundled map,
+// once with an informed heuristic and once with one that expands most of the map
+fn benchmark_tables() {
+    use std::time::Instant;
+    let maps = [
+        Maps::Den009d,
+        Maps::Den312d,
+        Maps::Orz103d,
+        Maps::Hrt201d,
+        Maps::Maze1,
+        Maps::Maze2,
+    ];
+    let heuristics = [
+        parse_heuristic("(+ deltaX deltaY)"),
+        parse_heuristic("(sqrt (min deltaX deltaY))"),
+    ];
+
+    let time_cycle = |map: &Map, cycle: &ProblemCycle, heuristic: &Heuristic, mode| {
+        let now = Instant::now();
+        CycleSolver::from_cycle(cycle.clone(), map.clone(), heuristic.clone())
+            .with_mode(mode)
+            .solve_cycle();
+        now.elapsed()
+    };
+
+    for map in maps {
+        let path = map.path();
+        let map = parse_map_file(path);
+        let cycle = ProblemCycle::new(map.clone(), PROBLEM_CYCLE_LENGTH);
+
+        for heuristic in &heuristics {
+            println!(
+                "{} {}: direct {:.2?}, tables {:.2?}, automatic {:.2?}",
+                path,
+                heuristic,
+                time_cycle(&map, &cycle, heuristic, EvaluationMode::Direct),
+                time_cycle(&map, &cycle, heuristic, EvaluationMode::Table),
+                time_cycle(&map, &cycle, heuristic, EvaluationMode::Automatic)
+            );
+        }
+    }
 }
 
 fn alife_demo() {
     let map = parse_map_file(Maps::Den312d.path());
 
-    alife::alife(map, Duration::from_secs(10));
+    cmput651py::alife::alife(map, Duration::from_secs(10));
 }
 
 fn ga_demo() {
@@ -0,0 +1,252 @@
+use std::collections::VecDeque;
+
+use super::util::Tile;
+
+// Tables backing the map-aware feature terminals, precomputed once per map.
+// Cell tables are indexed like `Map::map`, i.e. by `x * m + y`.
+#[derive(Debug)]
+pub struct MapFeatures {
+    pub n: usize,
+    pub m: usize,
+    pub obstacles: Vec<bool>,
+    // Steps to the nearest unpassable tile, where the outside of the map counts as wall
+    pub wall_distances: Vec<f32>,
+    // Cells with equal room ids share a room, see `find_rooms`
+    pub rooms: Vec<f32>,
+    // Fraction of unpassable tiles in every row and column
+    pub row_densities: Vec<f32>,
+    pub column_densities: Vec<f32>,
+}
+
+impl MapFeatures {
+    pub fn new(n: usize, m: usize, map: &[Tile]) -> MapFeatures {
+        assert_eq!(n * m, map.len());
+        let obstacles: Vec<bool> = map.iter().map(|tile| *tile == Tile::Unpassable).collect();
+
+        let row_densities = (0..n)
+            .map(|x| (0..m).filter(|&y| obstacles[x * m + y]).count() as f32 / m as f32)
+            .collect();
+        let column_densities = (0..m)
+            .map(|y| (0..n).filter(|&x| obstacles[x * m + y]).count() as f32 / n as f32)
+            .collect();
+
+        MapFeatures {
+            n,
+            m,
+            wall_distances: find_wall_distances(n, m, &obstacles),
+            rooms: find_rooms(n, m, &obstacles),
+            obstacles,
+            row_densities,
+            column_densities,
+        }
+    }
+
+    // Coordinates are clamped to the map, so every input maps onto some cell.
+    // NaN clamps to 0, like `llvm.maxnum` does in the `Jit`.
+    pub fn clamp_row(&self, x: f32) -> usize {
+        x.max(0.0).min((self.n - 1) as f32) as usize
+    }
+
+    pub fn clamp_column(&self, y: f32) -> usize {
+        y.max(0.0).min((self.m - 1) as f32) as usize
+    }
+
+    fn cell(&self, x: f32, y: f32) -> usize {
+        self.clamp_row(x) * self.m + self.clamp_column(y)
+    }
+
+    // The number of unpassable tiles on the Bresenham line between both cells
+    pub fn line_obstacles(&self, x1: f32, y1: f32, x2: f32, y2: f32) -> f32 {
+        let (mut x, mut y) = (self.clamp_row(x1) as i64, self.clamp_column(y1) as i64);
+        let (x2, y2) = (self.clamp_row(x2) as i64, self.clamp_column(y2) as i64);
+        let (dx, dy) = ((x2 - x).abs(), -(y2 - y).abs());
+        let (sx, sy) = ((x2 - x).signum(), (y2 - y).signum());
+        let mut error = dx + dy;
+
+        let mut count = 0;
+        loop {
+            if self.obstacles[x as usize * self.m + y as usize] {
+                count += 1;
+            }
+            if x == x2 && y == y2 {
+                break;
+            }
+
+            let error2 = 2 * error;
+            if error2 >= dy {
+                error += dy;
+                x += sx;
+            }
+            if error2 <= dx {
+                error += dx;
+                y += sy;
+            }
+        }
+
+        count as f32
+    }
+
+    pub fn wall_distance(&self, x: f32, y: f32) -> f32 {
+        self.wall_distances[self.cell(x, y)]
+    }
+
+    pub fn same_room(&self, x1: f32, y1: f32, x2: f32, y2: f32) -> f32 {
+        match self.rooms[self.cell(x1, y1)] == self.rooms[self.cell(x2, y2)] {
+            true => 1.0,
+            false => 0.0,
+        }
+    }
+
+    pub fn row_density(&self, x: f32) -> f32 {
+        self.row_densities[self.clamp_row(x)]
+    }
+
+    pub fn column_density(&self, y: f32) -> f32 {
+        self.column_densities[self.clamp_column(y)]
+    }
+}
+
+fn grid_neighbours(n: usize, m: usize, i: usize) -> impl Iterator<Item = usize> {
+    let (x, y) = (i / m, i % m);
+    [
+        (x > 0).then(|| i - m),
+        (x + 1 < n).then(|| i + m),
+        (y > 0).then(|| i - 1),
+        (y + 1 < m).then(|| i + 1),
+    ]
+    .into_iter()
+    .flatten()
+}
+
+fn is_border(n: usize, m: usize, i: usize) -> bool {
+    let (x, y) = (i / m, i % m);
+    x == 0 || y == 0 || x == n - 1 || y == m - 1
+}
+
+// Multi-source BFS from all walls. Walls are at distance 0 and passable border
+// tiles at distance 1, as they touch the outside of the map.
+fn find_wall_distances(n: usize, m: usize, obstacles: &[bool]) -> Vec<f32> {
+    let mut distances = vec![usize::MAX; obstacles.len()];
+    let mut queue = VecDeque::new();
+
+    for i in (0..obstacles.len()).filter(|&i| obstacles[i]) {
+        distances[i] = 0;
+        queue.push_back(i);
+    }
+    for i in (0..obstacles.len()).filter(|&i| !obstacles[i] && is_border(n, m, i)) {
+        distances[i] = 1;
+        queue.push_back(i);
+    }
+
+    while let Some(current) = queue.pop_front() {
+        for neighbour in grid_neighbours(n, m, current) {
+            if distances[neighbour] > distances[current] + 1 {
+                distances[neighbour] = distances[current] + 1;
+                queue.push_back(neighbour);
+            }
+        }
+    }
+
+    distances.into_iter().map(|d| d as f32).collect()
+}
+
+// Rooms are the connected areas left after cutting the map at its doorways, the
+// passable tiles that are blocked on two opposite sides. Doorways and walls each
+// form a room of their own.
+fn find_rooms(n: usize, m: usize, obstacles: &[bool]) -> Vec<f32> {
+    let blocked = |x: usize, y: usize, dx: i64, dy: i64| {
+        let (x, y) = (x as i64 + dx, y as i64 + dy);
+        x < 0 || y < 0 || x >= n as i64 || y >= m as i64 || obstacles[x as usize * m + y as usize]
+    };
+    let is_doorway = |i: usize| {
+        let (x, y) = (i / m, i % m);
+        !obstacles[i]
+            && ((blocked(x, y, -1, 0) && blocked(x, y, 1, 0))
+                || (blocked(x, y, 0, -1) && blocked(x, y, 0, 1)))
+    };
+
+    let mut rooms: Vec<Option<usize>> = vec![None; obstacles.len()];
+    let mut num_rooms = 0;
+
+    for i in 0..obstacles.len() {
+        if rooms[i].is_some() {
+            continue;
+        }
+
+        rooms[i] = Some(num_rooms);
+        if !obstacles[i] && !is_doorway(i) {
+            // DFS over the room
+            let mut stack = vec![i];
+            while let Some(current) = stack.pop() {
+                for neighbour in grid_neighbours(n, m, current) {
+                    if rooms[neighbour].is_none() && !obstacles[neighbour] && !is_doorway(neighbour)
+                    {
+                        rooms[neighbour] = Some(num_rooms);
+                        stack.push(neighbour);
+                    }
+                }
+            }
+        }
+        num_rooms += 1;
+    }
+
+    rooms.into_iter().map(|room| room.unwrap() as f32).collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use test_case::test_case;
+
+    // Two 3x3 rooms joined by a doorway at (1, 3)
+    //   . . . # . . .
+    //   . . . . . . .
+    //   . . . # . . .
+    fn two_rooms() -> MapFeatures {
+        let mut map = vec![Tile::Passable; 3 * 7];
+        map[3] = Tile::Unpassable;
+        map[2 * 7 + 3] = Tile::Unpassable;
+        MapFeatures::new(3, 7, &map)
+    }
+
+    #[test_case((0.0, 0.0, 0.0, 6.0), 1.0)]
+    #[test_case((1.0, 0.0, 1.0, 6.0), 0.0)]
+    #[test_case((0.0, 0.0, 2.0, 6.0), 0.0)]
+    #[test_case((2.0, 2.0, 2.0, 4.0), 1.0)]
+    #[test_case((0.0, 3.0, 0.0, 3.0), 1.0)]
+    fn test_line_obstacles((x1, y1, x2, y2): (f32, f32, f32, f32), expected: f32) {
+        assert_eq!(two_rooms().line_obstacles(x1, y1, x2, y2), expected);
+    }
+
+    #[test]
+    fn test_wall_distance() {
+        let features = two_rooms();
+        assert_eq!(features.wall_distance(0.0, 3.0), 0.0);
+        assert_eq!(features.wall_distance(1.0, 3.0), 1.0);
+        assert_eq!(features.wall_distance(1.0, 1.0), 2.0);
+        assert_eq!(features.wall_distance(1.0, 0.0), 1.0);
+        // Out of range coordinates are clamped onto the map
+        assert_eq!(features.wall_distance(-5.0, 100.0), 1.0);
+        assert_eq!(features.wall_distance(f32::NAN, 1.0), 1.0);
+    }
+
+    #[test]
+    fn test_same_room() {
+        let features = two_rooms();
+        assert_eq!(features.same_room(0.0, 0.0, 2.0, 2.0), 1.0);
+        assert_eq!(features.same_room(0.0, 4.0, 2.0, 6.0), 1.0);
+        assert_eq!(features.same_room(0.0, 0.0, 2.0, 6.0), 0.0);
+        assert_eq!(features.same_room(0.0, 0.0, 1.0, 3.0), 0.0);
+        assert_eq!(features.same_room(1.0, 3.0, 1.0, 3.0), 1.0);
+    }
+
+    #[test]
+    fn test_densities() {
+        let features = two_rooms();
+        assert_eq!(features.row_density(0.0), 1.0 / 7.0);
+        assert_eq!(features.row_density(1.0), 0.0);
+        assert_eq!(features.column_density(3.0), 2.0 / 3.0);
+        assert_eq!(features.column_density(0.0), 0.0);
+    }
+}
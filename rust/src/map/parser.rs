@@ -1,5 +1,6 @@
 use std::fs;
 use std::io::{BufRead, BufReader};
+use std::path::Path;
 
 use super::util::{Map, Tile};
 
@@ -8,7 +9,11 @@ pub fn parse_map_file(mapfile: &str) -> Map {
     let contents = fs::read_to_string(mapfile).expect("Map file {mapfile} doesn't exist!");
 
     // Parse the contents into a Map
-    parse_map_string(&contents)
+    let mut map = parse_map_string(&contents);
+    map.name = Path::new(mapfile)
+        .file_stem()
+        .map(|stem| stem.to_string_lossy().into_owned());
+    map
 }
 
 fn parse_map_string(mapstring: &str) -> Map {
@@ -143,21 +148,21 @@ mod tests {
 
         // Correct neighbours
         assert_eq!(map.neighbours.len(), 16);
-        assert_eq!(clone_sort(&map.neighbours[0]), vec![]);
-        assert_eq!(clone_sort(&map.neighbours[1]), vec![]);
-        assert_eq!(clone_sort(&map.neighbours[2]), vec![]);
-        assert_eq!(clone_sort(&map.neighbours[3]), vec![]);
-        assert_eq!(clone_sort(&map.neighbours[4]), vec![]);
+        assert_eq!(clone_sort(&map.neighbours[0]), Vec::<usize>::new());
+        assert_eq!(clone_sort(&map.neighbours[1]), Vec::<usize>::new());
+        assert_eq!(clone_sort(&map.neighbours[2]), Vec::<usize>::new());
+        assert_eq!(clone_sort(&map.neighbours[3]), Vec::<usize>::new());
+        assert_eq!(clone_sort(&map.neighbours[4]), Vec::<usize>::new());
         assert_eq!(clone_sort(&map.neighbours[5]), vec![6, 9]);
         assert_eq!(clone_sort(&map.neighbours[6]), vec![5]);
-        assert_eq!(clone_sort(&map.neighbours[7]), vec![]);
-        assert_eq!(clone_sort(&map.neighbours[8]), vec![]);
+        assert_eq!(clone_sort(&map.neighbours[7]), Vec::<usize>::new());
+        assert_eq!(clone_sort(&map.neighbours[8]), Vec::<usize>::new());
         assert_eq!(clone_sort(&map.neighbours[9]), vec![5]);
-        assert_eq!(clone_sort(&map.neighbours[10]), vec![]);
-        assert_eq!(clone_sort(&map.neighbours[11]), vec![]);
-        assert_eq!(clone_sort(&map.neighbours[12]), vec![]);
-        assert_eq!(clone_sort(&map.neighbours[13]), vec![]);
-        assert_eq!(clone_sort(&map.neighbours[14]), vec![]);
-        assert_eq!(clone_sort(&map.neighbours[15]), vec![]);
+        assert_eq!(clone_sort(&map.neighbours[10]), Vec::<usize>::new());
+        assert_eq!(clone_sort(&map.neighbours[11]), Vec::<usize>::new());
+        assert_eq!(clone_sort(&map.neighbours[12]), Vec::<usize>::new());
+        assert_eq!(clone_sort(&map.neighbours[13]), Vec::<usize>::new());
+        assert_eq!(clone_sort(&map.neighbours[14]), Vec::<usize>::new());
+        assert_eq!(clone_sort(&map.neighbours[15]), Vec::<usize>::new());
     }
 }
@@ -1,6 +1,9 @@
-use std::{collections::HashSet, fmt::Display};
+use std::{collections::HashSet, fmt::Display, sync::Arc};
+#[cfg(feature = "python")]
 use pyo3::prelude::*;
 
+use super::features::MapFeatures;
+
 // Enumeration for possible maps on which to search.
 pub enum Maps {
     Den009d,
@@ -52,12 +55,16 @@ pub enum Tile {
 }
 
 #[derive(Clone)]
-#[pyclass]
+#[cfg_attr(feature = "python", pyclass)]
 pub struct Map {
     pub n: usize,
     pub m: usize,
     pub map: Vec<Tile>,
     pub neighbours: Vec<Vec<usize>>,
+    // Shared, as the tables are read by every executor created for this map
+    pub features: Arc<MapFeatures>,
+    // The file name of the map, without extension, if it was read from a file
+    pub name: Option<String>,
 }
 
 impl Map {
@@ -95,12 +102,15 @@ impl Map {
         }
 
         (map, neighbours) = trim_map_to_largest_connected_component(map, neighbours);
+        let features = Arc::new(MapFeatures::new(n, m, &map));
 
         Map {
             n,
             m,
             map,
             neighbours,
+            features,
+            name: None,
         }
     }
 
@@ -123,6 +133,23 @@ impl Map {
     }
 }
 
+// A 4x5 map with a wall in the middle column, open at the bottom, which splits
+// it into two rooms
+//   . . # . .
+//   . . # . .
+//   . . # . .
+//   . . . . .
+#[cfg(test)]
+impl Map {
+    pub fn walled() -> Map {
+        let mut tiles = vec![Tile::Passable; 4 * 5];
+        for x in 0..3 {
+            tiles[x * 5 + 2] = Tile::Unpassable;
+        }
+        Map::from(4, 5, tiles)
+    }
+}
+
 impl Display for Map {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
         // Write height & width
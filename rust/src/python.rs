@@ -0,0 +1,263 @@
+use std::collections::HashMap;
+use std::time::Duration;
+
+use crate::alife::search::admissibility::{check_admissibility, AdmissibilityReport};
+use crate::alife::search::cycle::{CycleSolver, ProblemCycle};
+use crate::alife::sim::genetic_algorithm::{GeneticAlgorithm, GeneticAlgorithmResult};
+use crate::alife::sim::simulator::{Simulation, SimulationResult};
+use crate::constants::PROBLEM_CYCLE_LENGTH;
+use crate::heuristic::mutate_probs::{Term, TermProbabilities};
+use pyo3::exceptions::PyValueError;
+use pyo3::prelude::*;
+use pyo3::{pymodule, types::PyModule, Python};
+
+use crate::alife::search::problem::{Problem, ProblemResult};
+use crate::heuristic::parser::{parse_heuristic, try_parse_heuristic, try_parse_infix_heuristic};
+use crate::heuristic::Heuristic;
+use crate::map::parser::parse_map_file;
+use crate::map::util::{Map, Maps, Tile};
+
+use crate::heuristic::executors::interpreter::Interpreter;
+use crate::heuristic::executors::HeuristicExecuter;
+
+#[pymodule]
+fn libcmput651py<'py>(py: Python<'py>, m: &'py PyModule) -> PyResult<()> {
+    m.add_function(wrap_pyfunction!(sum_as_string, m)?)?;
+    m.add_function(wrap_pyfunction!(test_heuristic, m)?)?;
+    m.add_function(wrap_pyfunction!(solve_cycle_on_map, m)?)?;
+    m.add_function(wrap_pyfunction!(get_problems, m)?)?;
+
+    let heuristic_module = PyModule::new(py, "heuristic")?;
+    heuristic_module.add_function(wrap_pyfunction!(manhattan_distance, m)?)?;
+    heuristic_module.add_function(wrap_pyfunction!(parse, m)?)?;
+    heuristic_module.add_function(wrap_pyfunction!(parse_infix, m)?)?;
+    heuristic_module.add_function(wrap_pyfunction!(admissibility, m)?)?;
+    m.add_submodule(heuristic_module)?;
+
+    // Alife module
+    let alife_module = PyModule::new(py, "alife")?;
+    alife_module.add_function(wrap_pyfunction!(simulation, m)?)?;
+    m.add_submodule(alife_module)?;
+
+    let ga_module = PyModule::new(py, "genetic_algorithm")?;
+    ga_module.add_function(wrap_pyfunction!(genetic_algorithm, m)?)?;
+    ga_module.add_function(wrap_pyfunction!(get_genetic_algorithm, m)?)?;
+    ga_module.add_function(wrap_pyfunction!(random_term_probabilities, m)?)?;
+    ga_module.add_function(wrap_pyfunction!(term_probabilities_with_numbers, m)?)?;
+    ga_module.add_function(wrap_pyfunction!(crossover_probabilities, m)?)?;
+    ga_module.add_function(wrap_pyfunction!(mutate_probabilities, m)?)?;
+    ga_module.add_function(wrap_pyfunction!(term_probabilities_from_dict, m)?)?;
+    ga_module.add_function(wrap_pyfunction!(probabilities2dict, m)?)?;
+    m.add_submodule(ga_module)?;
+
+    Ok(())
+}
+
+// simple function for debugging
+#[pyfunction]
+fn sum_as_string(a: usize, b: usize) -> PyResult<String> {
+    #[cfg(debug_assertions)]
+    println!("Debugging enabled");
+
+    #[cfg(not(debug_assertions))]
+    println!("Debugging disabled");
+
+    Ok((a + b).to_string())
+}
+
+#[pyfunction]
+fn get_problems(map_name: String) -> PyResult<(Map, ProblemCycle)> {
+    let map_path = Maps::name2path(map_name.as_str());
+    let map = parse_map_file(map_path);
+
+    let cycle = ProblemCycle::new(map.clone(), PROBLEM_CYCLE_LENGTH);
+    let manhattan = parse_heuristic("(+ deltaX deltaY)");
+    let mut baseline = CycleSolver::from_cycle(cycle.clone(), map.clone(), manhattan);
+    baseline.solve_cycle();
+
+    Ok((map, cycle))
+}
+
+#[pyfunction]
+fn test_heuristic(h: &Heuristic) -> PyResult<()> {
+    let map = parse_map_file(Maps::Den312d.path());
+
+    // Generate random start and goal positions
+    let start = map.random_free_position();
+    let mut goal = map.random_free_position();
+    while start == goal {
+        goal = map.random_free_position();
+    }
+
+    println!("Start: {:?}", map.ind2sub(start));
+    println!("Goal: {:?}", map.ind2sub(goal));
+
+    let problem = Problem::new(start, goal);
+    let executer = Interpreter::create_with_features(h, map.features.clone());
+    let result = problem.solve(&map, |x1, y1, x2, y2| executer.execute(x1, y1, x2, y2));
+
+    assert!(result.solved);
+    problem.print_path_on_map(&map, result.solution_path);
+
+    Ok(())
+}
+
+#[pyfunction]
+fn solve_cycle_on_map(map_name: String, h: &Heuristic) -> PyResult<Vec<ProblemResult>> {
+    let map_path = Maps::name2path(map_name.as_str());
+    let map = parse_map_file(map_path);
+
+    Ok(CycleSolver::new(map, h.clone(), PROBLEM_CYCLE_LENGTH).solve_cycle())
+}
+
+#[pyfunction]
+fn simulation(map_name: String, seed: u64, secs: u64) -> PyResult<SimulationResult> {
+    let map_path = Maps::name2path(map_name.as_str());
+    let map = parse_map_file(map_path);
+
+    let cycle = ProblemCycle::new(map.clone(), PROBLEM_CYCLE_LENGTH);
+    let manhattan = parse_heuristic("(+ deltaX deltaY)");
+    let mut baseline = CycleSolver::from_cycle(cycle.clone(), map.clone(), manhattan);
+    baseline.solve_cycle();
+    let expansion_limit = baseline.get_total_expansions_in_cycle() * 5;
+    let time_limit = Duration::from_secs(secs);
+
+    let mut sim = Simulation::new(
+        map,
+        cycle,
+        baseline,
+        expansion_limit,
+        time_limit,
+        Some(seed),
+        true,
+    );
+
+    Ok(sim.run())
+}
+
+#[pyfunction]
+fn manhattan_distance() -> PyResult<Heuristic> {
+    Ok(parse_heuristic("(+ deltaX deltaY)"))
+}
+
+#[pyfunction]
+fn parse(input: String) -> PyResult<Heuristic> {
+    Ok(try_parse_heuristic(input.as_str())?)
+}
+
+#[pyfunction]
+fn parse_infix(input: String) -> PyResult<Heuristic> {
+    Ok(try_parse_infix_heuristic(input.as_str())?)
+}
+
+// Checks `h` against the true distances to each of `goals`, see `check_admissibility`
+#[pyfunction]
+fn admissibility(m: Map, h: &Heuristic, goals: Vec<usize>) -> PyResult<AdmissibilityReport> {
+    if let Some(goal) = goals.iter().find(|&&goal| m.map.get(goal) != Some(&Tile::Passable)) {
+        return Err(PyValueError::new_err(format!("Goal {} is not passable", goal)));
+    }
+
+    Ok(check_admissibility(&m, h, &goals))
+}
+
+#[pyfunction]
+fn genetic_algorithm(
+    m: Map,
+    c: ProblemCycle,
+    probs: TermProbabilities,
+    seed: u64,
+    secs: u64,
+) -> PyResult<GeneticAlgorithmResult> {
+    let manhattan = parse_heuristic("(+ deltaX deltaY)");
+    let mut baseline = CycleSolver::from_cycle(c.clone(), m.clone(), manhattan);
+    baseline.solve_cycle();
+
+    let time_limit = Duration::from_secs(secs);
+    let expansion_limit: usize = baseline.get_total_expansions_in_cycle() * 5;
+
+    let mut sim = GeneticAlgorithm::new(
+        m,
+        c,
+        baseline,
+        expansion_limit,
+        time_limit,
+        Some(probs),
+        Some(seed),
+        true,
+    );
+
+    Ok(sim.run())
+}
+
+#[pyfunction]
+fn get_genetic_algorithm() -> GeneticAlgorithm {
+    let map = parse_map_file(Maps::Den312d.path());
+    let seed = Some(42);
+
+    let cycle = ProblemCycle::new(map.clone(), PROBLEM_CYCLE_LENGTH);
+    let manhattan = parse_heuristic("(+ deltaX deltaY)");
+    let mut baseline = CycleSolver::from_cycle(cycle.clone(), map.clone(), manhattan);
+    baseline.solve_cycle();
+    let expansion_limit: usize = baseline.get_total_expansions_in_cycle() * 5;
+
+    GeneticAlgorithm::new(
+        map,
+        cycle,
+        baseline,
+        expansion_limit,
+        Duration::from_secs(10),
+        None,
+        seed,
+        true,
+    )
+}
+
+#[pyfunction]
+fn crossover_probabilities(
+    probs1: TermProbabilities,
+    probs2: TermProbabilities,
+) -> PyResult<TermProbabilities> {
+    Ok(probs1.crossover(&probs2))
+}
+
+#[pyfunction]
+fn mutate_probabilities(probs: TermProbabilities, mut_prob: f64) -> PyResult<TermProbabilities> {
+    Ok(probs.mutate(mut_prob))
+}
+
+#[pyfunction]
+fn random_term_probabilities(uniform: bool) -> PyResult<TermProbabilities> {
+    Ok(TermProbabilities::new(uniform))
+}
+
+#[pyfunction]
+fn term_probabilities_with_numbers(
+    uniform: bool,
+    number_values: Vec<f32>,
+) -> PyResult<TermProbabilities> {
+    TermProbabilities::check_number_values(&number_values).map_err(PyValueError::new_err)?;
+    Ok(TermProbabilities::with_number_values(uniform, number_values))
+}
+
+#[pyfunction]
+fn term_probabilities_from_dict(dict: HashMap<String, Vec<f64>>) -> PyResult<TermProbabilities> {
+    TermProbabilities::from_hashmap(dict).map_err(PyValueError::new_err)
+}
+
+#[pyfunction]
+fn probabilities2dict(probs: TermProbabilities) -> PyResult<HashMap<String, HashMap<String, f64>>> {
+    let mut dict = HashMap::new();
+
+    for term in vec!["conditionals", "binaries", "unaries", "terminals", "numbers"] {
+        let mut term_dict = HashMap::new();
+        let term_probs = probs.get(Term::from_str(term));
+        let operators = probs.get_operator_order(term);
+
+        for (operator, prob) in operators.iter().zip(term_probs.iter()) {
+            term_dict.insert(operator.to_string(), *prob);
+        }
+
+        dict.insert(term.to_string(), term_dict);
+    }
+    Ok(dict)
+}